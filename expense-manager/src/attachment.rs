@@ -0,0 +1,410 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::bill::Bills;
+use crate::crypto::{self, Sealer};
+
+// Hash prefixes shorter than this are too likely to be ambiguous.
+const MIN_PREFIX: usize = 4;
+// Shown in listings, like a short git commit id.
+const SHORT_HASH: usize = 12;
+
+// A file attached to a bill. The file itself is kept once per content in
+// the attachments directory, named by its SHA-256 hash.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Attachment {
+    pub bill_id: u32,
+    pub hash: String,
+    // The original file name, used when exporting.
+    pub name: String,
+    pub size: u64,
+}
+
+impl Attachment {
+    // The hash plus the original extension, so the file opens with the
+    // right program.
+    fn file_name(&self) -> String {
+        match Path::new(&self.name).extension() {
+            Some(ext) => format!("{}.{}", self.hash, ext.to_string_lossy().to_lowercase()),
+            None => self.hash.clone(),
+        }
+    }
+
+    pub fn path(&self, dir: &Path) -> PathBuf {
+        dir.join(self.file_name())
+    }
+}
+
+impl fmt::Display for Attachment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "#{} {} {} ({})",
+            self.bill_id,
+            &self.hash[..SHORT_HASH.min(self.hash.len())],
+            self.name,
+            format_size(self.size)
+        )
+    }
+}
+
+pub fn format_size(bytes: u64) -> String {
+    match bytes {
+        0..1024 => format!("{bytes} B"),
+        1024..1_048_576 => format!("{:.1} KB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+fn hash(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+// Reads `source` and returns the attachment for it with its content, which
+// `store` then keeps.
+pub fn from_file(bill_id: u32, source: &Path) -> Result<(Attachment, Vec<u8>), String> {
+    let data =
+        fs::read(source).map_err(|err| format!("Could not read {}: {err}", source.display()))?;
+    let name = source
+        .file_name()
+        .ok_or(format!("{} is not a file", source.display()))?
+        .to_string_lossy()
+        .into_owned();
+    let attachment = Attachment {
+        bill_id,
+        hash: hash(&data),
+        name,
+        size: data.len() as u64,
+    };
+    Ok((attachment, data))
+}
+
+// Copies the content of an attachment into `dir`, encrypted if the data
+// is. The same content attached again reuses the stored file.
+pub fn store(
+    dir: &Path,
+    attachment: &Attachment,
+    data: &[u8],
+    sealer: Option<&Sealer>,
+) -> Result<(), String> {
+    let path = attachment.path(dir);
+    if path.exists() {
+        return Ok(());
+    }
+    let data = crypto::seal(sealer, data)?;
+    fs::create_dir_all(dir).map_err(|err| format!("Could not create {}: {err}", dir.display()))?;
+    // Written under a temporary name first, so an interrupted copy never
+    // sits under the hash of the whole file.
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, data)
+        .and_then(|()| fs::rename(&tmp_path, &path))
+        .map_err(|err| format!("Could not store {}: {err}", attachment.name))
+}
+
+// The attachment whose hash starts with `prefix`. Attachments of the same
+// file to several bills count as one.
+pub fn find<'a>(attachments: &'a [Attachment], prefix: &str) -> Result<&'a Attachment, String> {
+    if prefix.len() < MIN_PREFIX {
+        return Err(format!(
+            "Give at least {MIN_PREFIX} characters of the attachment hash"
+        ));
+    }
+    let prefix = prefix.to_lowercase();
+    let matches: Vec<&Attachment> = attachments
+        .iter()
+        .filter(|attachment| attachment.hash.starts_with(&prefix))
+        .collect();
+    match matches.first() {
+        None => Err(format!("No attachment {prefix}")),
+        Some(first) if matches.iter().all(|other| other.hash == first.hash) => Ok(first),
+        Some(_) => Err(format!("{prefix} matches more than one attachment")),
+    }
+}
+
+// Reads a stored file back, checking that it still has the content it was
+// stored with.
+fn read(dir: &Path, attachment: &Attachment, sealer: Option<&Sealer>) -> Result<Vec<u8>, String> {
+    let path = attachment.path(dir);
+    let data =
+        fs::read(&path).map_err(|err| format!("Could not read {}: {err}", path.display()))?;
+    let data =
+        crypto::unseal(sealer, data).map_err(|reason| format!("{}: {reason}", path.display()))?;
+    if hash(&data) != attachment.hash {
+        return Err(format!(
+            "{} is damaged, its content changed",
+            path.display()
+        ));
+    }
+    Ok(data)
+}
+
+// Copies an attachment out under its original name, decrypted. `to` is a
+// directory or a file name; existing files are not overwritten.
+pub fn export(
+    dir: &Path,
+    attachment: &Attachment,
+    to: &Path,
+    sealer: Option<&Sealer>,
+) -> Result<PathBuf, String> {
+    let data = read(dir, attachment, sealer)?;
+    let target = if to.is_dir() {
+        to.join(&attachment.name)
+    } else {
+        to.to_owned()
+    };
+    if target.exists() {
+        return Err(format!("{} already exists", target.display()));
+    }
+    fs::write(&target, data)
+        .map_err(|err| format!("Could not write {}: {err}", target.display()))?;
+    Ok(target)
+}
+
+// Opens an attachment with the program the desktop uses for its type. An
+// encrypted one would have to be decrypted to a file the program can open,
+// so it has to be exported instead.
+pub fn open(dir: &Path, attachment: &Attachment, sealer: Option<&Sealer>) -> Result<(), String> {
+    if sealer.is_some() {
+        return Err(format!(
+            "{} is encrypted, export it to open it",
+            attachment.name
+        ));
+    }
+    read(dir, attachment, sealer)?;
+    let path = attachment.path(dir);
+    let mut command = if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else {
+        Command::new("xdg-open")
+    };
+    command
+        .arg(&path)
+        .spawn()
+        .map(|_| ())
+        .map_err(|err| format!("Could not open {}: {err}", path.display()))
+}
+
+// The decrypted content of every stored file in use, for encrypting them
+// again under a new passphrase. Files already gone are skipped.
+pub fn stored_files(
+    dir: &Path,
+    attachments: &[Attachment],
+    sealer: Option<&Sealer>,
+) -> Result<Vec<(PathBuf, Vec<u8>)>, String> {
+    let mut seen = HashSet::new();
+    let mut files = Vec::new();
+    for attachment in attachments {
+        let path = attachment.path(dir);
+        if !seen.insert(path.clone()) || !path.exists() {
+            continue;
+        }
+        files.push((path, read(dir, attachment, sealer)?));
+    }
+    Ok(files)
+}
+
+// Copies the stored files in use from `from` to `to`, decrypted with one
+// sealer and encrypted with the other. Returns how many files were copied.
+pub fn copy(
+    from: &Path,
+    to: &Path,
+    attachments: &[Attachment],
+    from_sealer: Option<&Sealer>,
+    to_sealer: Option<&Sealer>,
+) -> Result<usize, String> {
+    let files = stored_files(from, attachments, from_sealer)?;
+    for attachment in attachments {
+        if let Some((_, data)) = files
+            .iter()
+            .find(|(path, _)| *path == attachment.path(from))
+        {
+            store(to, attachment, data, to_sealer)?;
+        }
+    }
+    Ok(files.len())
+}
+
+// What garbage collection removed.
+#[derive(Debug, Default, PartialEq)]
+pub struct Collected {
+    pub records: usize,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+// Forgets attachments of bills that are gone and deletes stored files that
+// no attachment uses any more. Only files named by a hash are touched.
+pub fn collect_garbage(
+    dir: &Path,
+    attachments: &mut Vec<Attachment>,
+    bills: &Bills,
+) -> Result<Collected, String> {
+    let before = attachments.len();
+    attachments.retain(|attachment| bills.contains_key(&attachment.bill_id));
+    let mut collected = Collected {
+        records: before - attachments.len(),
+        ..Collected::default()
+    };
+    let used: HashSet<String> = attachments.iter().map(Attachment::file_name).collect();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(collected),
+        Err(err) => return Err(format!("Could not read {}: {err}", dir.display())),
+    };
+    for entry in entries {
+        let entry = entry.map_err(|err| format!("Could not read {}: {err}", dir.display()))?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let stem = name.split('.').next().unwrap_or_default();
+        let is_hash = stem.len() == 64 && stem.chars().all(|c| c.is_ascii_hexdigit());
+        if !is_hash || used.contains(&name) {
+            continue;
+        }
+        let size = entry.metadata().map(|meta| meta.len()).unwrap_or(0);
+        fs::remove_file(entry.path())
+            .map_err(|err| format!("Could not remove {}: {err}", entry.path().display()))?;
+        collected.files += 1;
+        collected.bytes += size;
+    }
+    Ok(collected)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bill::{parse_date, Bill};
+    use rust_decimal_macros::dec;
+
+    fn add(dir: &Path, bill_id: u32, source: &Path, sealer: Option<&Sealer>) -> Attachment {
+        let (attachment, data) = from_file(bill_id, source).unwrap();
+        store(dir, &attachment, &data, sealer).unwrap();
+        attachment
+    }
+
+    #[test]
+    fn same_content_is_stored_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = dir.path().join("attachments");
+        let source = dir.path().join("Receipt.PDF");
+        fs::write(&source, b"%PDF receipt").unwrap();
+        let first = add(&store, 1, &source, None);
+        let second = add(&store, 2, &source, None);
+        assert_eq!(first.hash, second.hash);
+        assert_eq!(fs::read_dir(&store).unwrap().count(), 1);
+        assert!(first.path(&store).ends_with(format!("{}.pdf", first.hash)));
+        assert!(first
+            .to_string()
+            .starts_with(&format!("#1 {} Receipt.PDF (12 B)", &first.hash[..12])));
+
+        let attachments = [first.clone(), second];
+        assert_eq!(
+            find(&attachments, &first.hash[..6].to_uppercase()),
+            Ok(&first)
+        );
+        assert!(find(&attachments, "abc").is_err());
+
+        let exported = export(&store, &first, dir.path(), None).unwrap_err();
+        assert!(exported.contains("already exists"));
+        let out = dir.path().join("out");
+        fs::create_dir(&out).unwrap();
+        assert_eq!(
+            fs::read(export(&store, &first, &out, None).unwrap()).unwrap(),
+            b"%PDF receipt"
+        );
+
+        fs::write(first.path(&store), b"changed").unwrap();
+        assert!(export(&store, &first, &dir.path().join("again.pdf"), None)
+            .unwrap_err()
+            .contains("damaged"));
+    }
+
+    #[test]
+    fn attachments_of_encrypted_data_are_encrypted() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = dir.path().join("attachments");
+        let source = dir.path().join("receipt.txt");
+        fs::write(&source, b"Coffee 3.00").unwrap();
+        let sealer = Sealer::new("hunter2");
+        let receipt = add(&store, 1, &source, Some(&sealer));
+        let stored = fs::read(receipt.path(&store)).unwrap();
+        assert!(crypto::is_encrypted(&stored));
+        assert_eq!(receipt.hash, hash(b"Coffee 3.00"));
+
+        let out = dir.path().join("out.txt");
+        assert!(export(&store, &receipt, &out, None)
+            .unwrap_err()
+            .contains("no passphrase"));
+        export(&store, &receipt, &out, Some(&sealer)).unwrap();
+        assert_eq!(fs::read(&out).unwrap(), b"Coffee 3.00");
+        assert!(open(&store, &receipt, Some(&sealer))
+            .unwrap_err()
+            .contains("export it"));
+        assert_eq!(
+            stored_files(&store, &[receipt.clone(), receipt.clone()], Some(&sealer)),
+            Ok(vec![(receipt.path(&store), b"Coffee 3.00".to_vec())])
+        );
+    }
+
+    #[test]
+    fn garbage_collection_removes_orphans() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = |name: &str, data: &[u8]| {
+            let path = dir.path().join(name);
+            fs::write(&path, data).unwrap();
+            path
+        };
+        let store = dir.path().join("attachments");
+        let kept = add(&store, 1, &source("kept.jpg", b"kept"), None);
+        let shared = add(&store, 2, &source("shared.jpg", b"shared"), None);
+        let mut attachments = vec![
+            kept,
+            shared.clone(),
+            Attachment {
+                bill_id: 1,
+                ..shared
+            },
+            add(&store, 2, &source("gone.png", b"gone"), None),
+        ];
+        fs::write(store.join("notes.txt"), b"not an attachment").unwrap();
+        let bills: Bills = [(
+            1,
+            Bill::new(
+                1,
+                "Lunch",
+                dec!(12),
+                parse_date("2024-01-01").unwrap(),
+                "Food",
+            ),
+        )]
+        .into_iter()
+        .collect();
+
+        let collected = collect_garbage(&store, &mut attachments, &bills).unwrap();
+        assert_eq!(
+            collected,
+            Collected {
+                records: 2,
+                files: 1,
+                bytes: 4
+            }
+        );
+        assert_eq!(attachments.len(), 2);
+        assert_eq!(fs::read_dir(&store).unwrap().count(), 3);
+        assert_eq!(
+            collect_garbage(&dir.path().join("missing"), &mut attachments, &bills),
+            Ok(Collected::default())
+        );
+    }
+}
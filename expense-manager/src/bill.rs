@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::currency::{self, default_currency, DEFAULT_CURRENCY};
+
+pub const DATE_FORMAT: &str = "%Y-%m-%d";
+
+pub type Bills = HashMap<u32, Bill>;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bill {
+    pub id: u32,
+    pub title: String,
+    pub amount: Decimal,
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    pub date: NaiveDate,
+    pub category: String,
+    pub payee: String,
+    pub notes: String,
+    pub paid: bool,
+    // Set on bills generated from a recurring bill.
+    #[serde(default)]
+    pub recurring_id: Option<u32>,
+    // Set on bills imported from a bank statement, to spot re-imports.
+    #[serde(default)]
+    pub import_id: Option<String>,
+}
+
+impl Bill {
+    pub fn new(id: u32, title: &str, amount: Decimal, date: NaiveDate, category: &str) -> Self {
+        Self {
+            id,
+            title: title.to_owned(),
+            amount,
+            currency: DEFAULT_CURRENCY.to_owned(),
+            date,
+            category: category.to_owned(),
+            payee: String::new(),
+            notes: String::new(),
+            paid: false,
+            recurring_id: None,
+            import_id: None,
+        }
+    }
+}
+
+impl fmt::Display for Bill {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "#{} {} {} {:.2} {} [{}]",
+            self.id,
+            self.date.format(DATE_FORMAT),
+            self.title,
+            currency::round(self.amount),
+            self.currency,
+            self.category
+        )?;
+        if !self.payee.is_empty() {
+            write!(f, " to {}", self.payee)?;
+        }
+        write!(f, " ({})", if self.paid { "paid" } else { "unpaid" })?;
+        if !self.notes.is_empty() {
+            write!(f, " - {}", self.notes)?;
+        }
+        Ok(())
+    }
+}
+
+// Serializes bills as a list ordered by id instead of a map keyed by id.
+pub mod as_list {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(bills: &Bills, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut list: Vec<&Bill> = bills.values().collect();
+        list.sort_by_key(|bill| bill.id);
+        list.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Bills, D::Error> {
+        let list = Vec::<Bill>::deserialize(deserializer)?;
+        Ok(list.into_iter().map(|bill| (bill.id, bill)).collect())
+    }
+}
+
+// Hands out the id for a new bill. `last_id` is the last id handed out and
+// is saved with the ledger, so the id of a removed bill is never given to
+// another one: the change log, splits and attachments still refer to it.
+pub fn next_id(bills: &Bills, last_id: &mut u32) -> u32 {
+    let newest = bills.keys().max().copied().unwrap_or(0);
+    *last_id = (*last_id).max(newest) + 1;
+    *last_id
+}
+
+pub fn parse_date(input: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(input, DATE_FORMAT)
+        .map_err(|_| format!("'{input}' is not a date like 2024-01-31"))
+}
+
+// Parses a month like 2024-01 into its first day.
+pub fn parse_month(input: &str) -> Result<NaiveDate, String> {
+    parse_date(&format!("{input}-01")).map_err(|_| format!("'{input}' is not a month like 2024-01"))
+}
+
+// Empty fields match every bill.
+#[derive(Debug, Default)]
+pub struct Filter {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub category: Option<String>,
+}
+
+impl Filter {
+    pub fn matches(&self, bill: &Bill) -> bool {
+        self.from.is_none_or(|from| bill.date >= from)
+            && self.to.is_none_or(|to| bill.date <= to)
+            && self
+                .category
+                .as_ref()
+                .is_none_or(|category| bill.category.eq_ignore_ascii_case(category))
+    }
+
+    // Matching bills ordered by date, then id.
+    pub fn apply<'a>(&self, bills: &'a Bills) -> Vec<&'a Bill> {
+        let mut matches: Vec<&Bill> = bills.values().filter(|bill| self.matches(bill)).collect();
+        matches.sort_by_key(|bill| (bill.date, bill.id));
+        matches
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn date(input: &str) -> NaiveDate {
+        parse_date(input).unwrap()
+    }
+
+    fn sample_bills() -> Bills {
+        [
+            Bill::new(1, "Rent", dec!(1200), date("2024-01-01"), "Housing"),
+            Bill::new(2, "Groceries", dec!(85.5), date("2024-01-15"), "Food"),
+            Bill::new(3, "Rent", dec!(1200), date("2024-02-01"), "Housing"),
+        ]
+        .into_iter()
+        .map(|bill| (bill.id, bill))
+        .collect()
+    }
+
+    #[test]
+    fn bills_with_the_same_title_coexist() {
+        let bills = sample_bills();
+        assert_eq!(
+            bills.values().filter(|bill| bill.title == "Rent").count(),
+            2
+        );
+        let mut last_id = 0;
+        assert_eq!(next_id(&bills, &mut last_id), 4);
+        assert_eq!(next_id(&Bills::new(), &mut 0), 1);
+        // The newest bill going away doesn't free its id.
+        let mut bills = bills;
+        bills.remove(&3);
+        assert_eq!(next_id(&bills, &mut last_id), 5);
+    }
+
+    #[test]
+    fn filter_by_date_range_and_category() {
+        let bills = sample_bills();
+        let ids = |filter: Filter| -> Vec<u32> {
+            filter.apply(&bills).iter().map(|bill| bill.id).collect()
+        };
+        assert_eq!(ids(Filter::default()), vec![1, 2, 3]);
+        assert_eq!(
+            ids(Filter {
+                from: Some(date("2024-01-10")),
+                to: Some(date("2024-01-31")),
+                category: None,
+            }),
+            vec![2]
+        );
+        assert_eq!(
+            ids(Filter {
+                category: Some("housing".to_owned()),
+                ..Filter::default()
+            }),
+            vec![1, 3]
+        );
+    }
+
+    #[test]
+    fn parse_date_explains_the_expected_format() {
+        assert_eq!(date("2024-02-29").to_string(), "2024-02-29");
+        assert!(parse_date("29/02/2024").unwrap_err().contains("2024-01-31"));
+    }
+}
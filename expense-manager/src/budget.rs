@@ -0,0 +1,223 @@
+use chrono::{Datelike, NaiveDate};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::bill::{Bill, Bills};
+use crate::currency::{self, ExchangeRate};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Budget {
+    pub category: String,
+    pub monthly_limit: Decimal,
+}
+
+impl Budget {
+    pub fn new(category: &str, monthly_limit: Decimal) -> Self {
+        Self {
+            category: category.to_owned(),
+            monthly_limit,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct BudgetStatus {
+    pub category: String,
+    pub limit: Decimal,
+    pub spent: Decimal,
+}
+
+impl BudgetStatus {
+    pub fn remaining(&self) -> Decimal {
+        self.limit - self.spent
+    }
+
+    pub fn percent_used(&self) -> Decimal {
+        if self.limit > Decimal::ZERO {
+            self.spent / self.limit * Decimal::ONE_HUNDRED
+        } else {
+            Decimal::ONE_HUNDRED
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.spent > self.limit
+    }
+
+    pub fn warning(&self) -> String {
+        format!(
+            "Warning: {} is over its monthly budget of {:.2} by {:.2} ({:.0}% used)",
+            self.category,
+            self.limit,
+            -self.remaining(),
+            self.percent_used().round()
+        )
+    }
+}
+
+fn same_month(a: NaiveDate, b: NaiveDate) -> bool {
+    a.year() == b.year() && a.month() == b.month()
+}
+
+pub fn find<'a>(budgets: &'a [Budget], category: &str) -> Option<&'a Budget> {
+    budgets
+        .iter()
+        .find(|budget| budget.category.eq_ignore_ascii_case(category))
+}
+
+// Adds, replaces or, with a zero limit, removes the budget of a category.
+// Limits are in the base currency.
+pub fn set(budgets: &mut Vec<Budget>, category: &str, monthly_limit: Decimal) {
+    budgets.retain(|budget| !budget.category.eq_ignore_ascii_case(category));
+    if monthly_limit > Decimal::ZERO {
+        budgets.push(Budget::new(category, monthly_limit));
+        budgets.sort_by_key(|budget| budget.category.to_lowercase());
+    }
+}
+
+fn in_month<'a>(
+    bills: &'a Bills,
+    category: &'a str,
+    month: NaiveDate,
+) -> impl Iterator<Item = &'a Bill> {
+    bills.values().filter(move |bill| {
+        bill.category.eq_ignore_ascii_case(category) && same_month(bill.date, month)
+    })
+}
+
+// `bills` must all be in the same currency.
+pub fn spent_in_month(bills: &Bills, category: &str, month: NaiveDate) -> Decimal {
+    in_month(bills, category, month)
+        .map(|bill| bill.amount)
+        .sum()
+}
+
+// Budget versus actual spending for the month containing `month`.
+pub fn report(budgets: &[Budget], bills: &Bills, month: NaiveDate) -> Vec<BudgetStatus> {
+    budgets
+        .iter()
+        .map(|budget| BudgetStatus {
+            category: budget.category.clone(),
+            limit: budget.monthly_limit,
+            spent: spent_in_month(bills, &budget.category, month),
+        })
+        .collect()
+}
+
+// The budget status of the bill's category in the bill's month, if that
+// category is now over budget. Only the bills counted against the budget are
+// converted to `base`, so a missing rate for some other bill doesn't matter.
+pub fn exceeded_by(
+    budgets: &[Budget],
+    bills: &Bills,
+    rates: &[ExchangeRate],
+    base: &str,
+    bill: &Bill,
+) -> Result<Option<BudgetStatus>, String> {
+    let Some(budget) = find(budgets, &bill.category) else {
+        return Ok(None);
+    };
+    let counted: Vec<&Bill> = in_month(bills, &budget.category, bill.date).collect();
+    let status = BudgetStatus {
+        category: budget.category.clone(),
+        limit: budget.monthly_limit,
+        spent: currency::total(&counted, rates, base)?,
+    };
+    Ok(status.is_over().then_some(status))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bill::parse_date;
+    use rust_decimal_macros::dec;
+
+    fn bills() -> Bills {
+        [
+            Bill::new(
+                1,
+                "Groceries",
+                dec!(120),
+                parse_date("2024-01-05").unwrap(),
+                "Food",
+            ),
+            Bill::new(
+                2,
+                "Dinner",
+                dec!(60),
+                parse_date("2024-01-20").unwrap(),
+                "food",
+            ),
+            Bill::new(
+                3,
+                "Groceries",
+                dec!(300),
+                parse_date("2024-02-05").unwrap(),
+                "Food",
+            ),
+            Bill::new(
+                4,
+                "Rent",
+                dec!(1200),
+                parse_date("2024-01-01").unwrap(),
+                "Housing",
+            ),
+        ]
+        .into_iter()
+        .map(|bill| (bill.id, bill))
+        .collect()
+    }
+
+    #[test]
+    fn report_compares_the_month_against_each_budget() {
+        let mut budgets = Vec::new();
+        set(&mut budgets, "Food", dec!(200));
+        set(&mut budgets, "Fun", dec!(50));
+        let report = report(&budgets, &bills(), parse_date("2024-01-31").unwrap());
+        assert_eq!(report.len(), 2);
+        assert_eq!(report[0].spent, dec!(180));
+        assert_eq!(report[0].remaining(), dec!(20));
+        assert_eq!(report[0].percent_used(), dec!(90));
+        assert_eq!(report[1].spent, dec!(0));
+    }
+
+    #[test]
+    fn set_replaces_and_removes_budgets() {
+        let mut budgets = Vec::new();
+        set(&mut budgets, "Food", dec!(200));
+        set(&mut budgets, "food", dec!(250));
+        assert_eq!(budgets, vec![Budget::new("food", dec!(250))]);
+        set(&mut budgets, "FOOD", dec!(0));
+        assert!(budgets.is_empty());
+    }
+
+    #[test]
+    fn exceeded_by_only_reports_categories_over_budget() {
+        let budgets = vec![Budget::new("Food", dec!(200))];
+        let bills = bills();
+        let exceeded = |id| exceeded_by(&budgets, &bills, &[], "USD", &bills[&id]).unwrap();
+        assert_eq!(exceeded(1), None);
+        assert_eq!(exceeded(3).unwrap().remaining(), -dec!(100));
+        assert!(exceeded(4).is_none());
+    }
+
+    #[test]
+    fn exceeded_by_only_converts_the_bills_it_counts() {
+        let budgets = vec![Budget::new("Food", dec!(200))];
+        let mut bills = bills();
+        let rates = vec![ExchangeRate {
+            date: parse_date("2024-01-01").unwrap(),
+            from: "EUR".to_owned(),
+            to: "USD".to_owned(),
+            rate: dec!(2),
+        }];
+        // No rate for yen, but the rent isn't counted against the food budget.
+        bills.get_mut(&4).unwrap().currency = "JPY".to_owned();
+        bills.get_mut(&3).unwrap().currency = "EUR".to_owned();
+        let status = exceeded_by(&budgets, &bills, &rates, "USD", &bills[&3]).unwrap();
+        assert_eq!(status.unwrap().spent, dec!(600));
+
+        bills.get_mut(&2).unwrap().currency = "JPY".to_owned();
+        assert!(exceeded_by(&budgets, &bills, &rates, "USD", &bills[&1]).is_err());
+    }
+}
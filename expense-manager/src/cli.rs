@@ -0,0 +1,2051 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::{NaiveDate, NaiveDateTime};
+use clap::{ArgGroup, Args, Parser, Subcommand};
+use rust_decimal::Decimal;
+use serde_json::json;
+
+use crate::attachment;
+use crate::bill::{parse_date, parse_month, Bill, Filter};
+use crate::budget;
+use crate::currency::{self, parse_currency, ExchangeRate};
+use crate::forecast::{self, Goal, Income};
+use crate::helper::{parse_amount, today};
+use crate::history;
+use crate::import::{self, CategoryRule, Column, CsvMapping};
+use crate::ledger::Ledger;
+use crate::recurring::{self, Rule};
+use crate::report::{self, Grouping};
+use crate::split::{self, Method, Payment};
+use crate::storage::{self, Format, StorageError, Store};
+
+/// Manage bills. Without a subcommand the interactive menu is started.
+#[derive(Debug, Parser)]
+#[command(name = "expense-manager")]
+pub struct Cli {
+    /// Data file [default: bills.<format>]
+    #[arg(long, global = true)]
+    pub file: Option<PathBuf>,
+
+    /// Data file format (json, csv or sqlite) [default: from the file extension]
+    #[arg(long, global = true)]
+    pub format: Option<Format>,
+
+    /// Print machine-readable JSON
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Cli {
+    pub fn location(&self) -> (PathBuf, Format) {
+        let format = match (self.format, &self.file) {
+            (Some(format), _) => format,
+            (None, Some(path)) => Format::from_path(path).unwrap_or(Format::Json),
+            (None, None) => Format::Json,
+        };
+        let path = self
+            .file
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(format!("bills.{}", format.extension())));
+        (path, format)
+    }
+
+    pub fn store(&self, passphrase: Option<String>) -> Result<Box<dyn Store>, StorageError> {
+        let (path, format) = self.location();
+        storage::open(path, format, passphrase)
+    }
+}
+
+// The passphrase is read from these variables if they are set, so scripts
+// can run without a prompt.
+pub const PASSPHRASE_VAR: &str = "EXPENSE_MANAGER_PASSPHRASE";
+pub const NEW_PASSPHRASE_VAR: &str = "EXPENSE_MANAGER_NEW_PASSPHRASE";
+
+// Looks up a variable in the process environment. The lookup is passed to
+// the functions that read the variables above, so tests can give them an
+// environment of their own.
+pub fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok()
+}
+
+// The passphrase for the data file at `path`, needed only if the file is
+// encrypted. A plain file is opened without one even if PASSPHRASE_VAR is
+// set; only the `passphrase` command encrypts it.
+pub fn passphrase_for(
+    path: &Path,
+    env: &dyn Fn(&str) -> Option<String>,
+) -> Result<Option<String>, String> {
+    if !storage::is_encrypted(path) {
+        return Ok(None);
+    }
+    if let Some(passphrase) = env(PASSPHRASE_VAR) {
+        return Ok(Some(passphrase));
+    }
+    rpassword::prompt_password(format!("Passphrase for {}: ", path.display()))
+        .map(Some)
+        .map_err(|err| format!("Could not read the passphrase: {err}"))
+}
+
+// When a plain file is encrypted, PASSPHRASE_VAR is taken as the new
+// passphrase if NEW_PASSPHRASE_VAR is not set.
+fn new_passphrase(encrypted: bool, env: &dyn Fn(&str) -> Option<String>) -> Result<String, String> {
+    let passphrase =
+        env(NEW_PASSPHRASE_VAR).or_else(|| if encrypted { None } else { env(PASSPHRASE_VAR) });
+    let passphrase = match passphrase {
+        Some(passphrase) => passphrase,
+        None => {
+            let read = |prompt: &str| {
+                rpassword::prompt_password(prompt)
+                    .map_err(|err| format!("Could not read the passphrase: {err}"))
+            };
+            let passphrase = read("New passphrase: ")?;
+            if read("Repeat the new passphrase: ")? != passphrase {
+                return Err("The passphrases don't match".to_owned());
+            }
+            passphrase
+        }
+    };
+    if passphrase.is_empty() {
+        return Err("The passphrase can't be empty".to_owned());
+    }
+    Ok(passphrase)
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Add a bill
+    Add(AddArgs),
+    /// List bills
+    List(FilterArgs),
+    /// Remove a bill
+    Remove { id: u32 },
+    /// Change some fields of a bill
+    Update(UpdateArgs),
+    /// Total amount of the bills in the base currency
+    Total(FilterArgs),
+    /// Budget versus actual spending for a month
+    Report {
+        /// Month like 2024-01 [default: this month]
+        #[arg(long, value_parser = parse_month)]
+        month: Option<NaiveDate>,
+    },
+    /// Spending by category, month or payee, the monthly trend or the top bills
+    Spending(SpendingArgs),
+    /// Import expenses from a bank statement (CSV, OFX or QFX)
+    Import(ImportArgs),
+    /// Recurring bills, added from the interactive menu
+    #[command(subcommand)]
+    Recurring(RecurringCommand),
+    /// Categorize imported expenses by payee
+    #[command(subcommand)]
+    Rule(RuleCommand),
+    /// Exchange rates used to convert to the base currency
+    #[command(subcommand)]
+    Rate(RateCommand),
+    /// Share a bill between people
+    Split(SplitArgs),
+    /// Receipts and other files attached to bills
+    #[command(subcommand)]
+    Attachment(AttachmentCommand),
+    /// Money coming in, once or on a schedule
+    #[command(subcommand)]
+    Income(IncomeCommand),
+    /// Savings goals with a target date
+    #[command(subcommand)]
+    Goal(GoalCommand),
+    /// Expected balance over the coming months, from bills, income and goals
+    Forecast {
+        #[arg(long, default_value_t = 6)]
+        months: u32,
+        /// Money available today, in the base currency
+        #[arg(long, default_value = "0", allow_negative_numbers = true)]
+        balance: Decimal,
+    },
+    /// What everybody owes or is owed, and how to settle up
+    Balances,
+    /// Record that FROM paid TO back, in the base currency
+    Settle {
+        from: String,
+        to: String,
+        #[arg(value_parser = parse_amount)]
+        amount: Decimal,
+        /// [default: today]
+        #[arg(long, value_parser = parse_date)]
+        date: Option<NaiveDate>,
+    },
+    /// Show or change the currency of totals, reports and budgets
+    Base {
+        #[arg(value_parser = parse_currency)]
+        currency: Option<String>,
+    },
+    /// Show the change log, or the bills as they were at some time
+    History {
+        /// Time like "2024-01-31 18:30", or a date for the end of that day
+        #[arg(long, value_parser = history::parse_time)]
+        as_of: Option<NaiveDateTime>,
+        /// Put the bills back the way they were at --as-of
+        #[arg(long, requires = "as_of")]
+        restore: bool,
+    },
+    /// Full-screen terminal interface
+    #[cfg(feature = "tui")]
+    Tui,
+    /// Encrypt the data file, change its passphrase, or decrypt it with --remove
+    Passphrase {
+        /// Store the data unencrypted from now on
+        #[arg(long)]
+        remove: bool,
+    },
+    /// Copy the bills and change log to a new data file, e.g. bills.db
+    Migrate {
+        /// The format comes from the extension: .json, .csv, or .db for SQLite
+        to: PathBuf,
+    },
+}
+
+#[derive(Debug, Args)]
+#[command(group(ArgGroup::new("method").required(true)))]
+pub struct SplitArgs {
+    id: u32,
+    /// Who paid the bill
+    #[arg(long, required_unless_present = "clear")]
+    paid_by: Option<String>,
+    /// Equal shares, like alice,bob,carol
+    #[arg(long, group = "method")]
+    equal: Option<String>,
+    /// Exact amounts, like alice=10,bob=20
+    #[arg(long, group = "method")]
+    exact: Option<String>,
+    /// Percentages, like alice=60,bob=40
+    #[arg(long, group = "method")]
+    percent: Option<String>,
+    /// Stop sharing the bill
+    #[arg(long, group = "method")]
+    clear: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum RateCommand {
+    /// Record that 1 CURRENCY is worth RATE of the base currency
+    Set {
+        #[arg(value_parser = parse_currency)]
+        currency: String,
+        #[arg(value_parser = parse_amount)]
+        rate: Decimal,
+        /// Currency the rate converts to [default: the base currency]
+        #[arg(long, value_parser = parse_currency)]
+        to: Option<String>,
+        /// Date the rate applies from [default: today]
+        #[arg(long, value_parser = parse_date)]
+        date: Option<NaiveDate>,
+    },
+    /// List the stored rates
+    List,
+}
+
+#[derive(Debug, Args)]
+pub struct SpendingArgs {
+    /// category, month, payee, trend or top
+    #[arg(long, default_value = "category")]
+    by: Grouping,
+    /// Most rows to show when grouping by category or payee, or for top
+    #[arg(long, default_value_t = 10)]
+    limit: usize,
+    /// Also draw an ASCII bar chart
+    #[arg(long)]
+    chart: bool,
+    /// Write the report to a .csv or .html file instead
+    #[arg(long)]
+    export: Option<PathBuf>,
+    #[command(flatten)]
+    filter: FilterArgs,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum RecurringCommand {
+    /// List recurring bills
+    List,
+    /// Stop recurring bill ID; the bills it already added are kept
+    Remove { id: u32 },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum RuleCommand {
+    /// Put expenses whose payee contains PATTERN into CATEGORY
+    Add { pattern: String, category: String },
+    /// List rules in the order they are tried
+    List,
+    /// Remove the rule for PATTERN
+    Remove { pattern: String },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum IncomeCommand {
+    /// Record income from SOURCE
+    Add {
+        source: String,
+        #[arg(value_parser = parse_amount)]
+        amount: Decimal,
+        /// Day it comes in, or the first time [default: today]
+        #[arg(long, value_parser = parse_date)]
+        date: Option<NaiveDate>,
+        /// [default: the base currency]
+        #[arg(long, value_parser = parse_currency)]
+        currency: Option<String>,
+        /// weekly, monthly:<day>, yearly or every:<days>
+        #[arg(long)]
+        every: Option<Rule>,
+    },
+    /// List income
+    List,
+    /// Remove income ID
+    Remove { id: u32 },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum GoalCommand {
+    /// Save TARGET in the base currency by a date
+    Add {
+        name: String,
+        #[arg(value_parser = parse_amount)]
+        target: Decimal,
+        #[arg(long, value_parser = parse_date)]
+        by: NaiveDate,
+        /// Already put aside
+        #[arg(long, value_parser = parse_amount, default_value = "0")]
+        saved: Decimal,
+    },
+    /// Put AMOUNT towards goal NAME
+    Save {
+        name: String,
+        #[arg(value_parser = parse_amount)]
+        amount: Decimal,
+    },
+    /// List goals and what to save every month to reach them
+    List,
+    /// Remove goal NAME
+    Remove { name: String },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AttachmentCommand {
+    /// Attach a copy of FILE to bill ID
+    Add { id: u32, file: PathBuf },
+    /// List attachments, of one bill or of all
+    List { id: Option<u32> },
+    /// Open an attachment, given the start of its hash
+    Open { hash: String },
+    /// Copy an attachment to a file or directory
+    Export { hash: String, to: PathBuf },
+    /// Detach a file from bill ID
+    Remove { id: u32, hash: String },
+    /// Delete stored files that no bill uses any more
+    Gc,
+}
+
+#[derive(Debug, Args)]
+pub struct ImportArgs {
+    /// Statement file; .ofx and .qfx are read as OFX, anything else as CSV
+    statement: PathBuf,
+    /// CSV date column, by header name or zero-based index
+    #[arg(long, default_value = "Date")]
+    date_column: String,
+    /// CSV amount column
+    #[arg(long, default_value = "Amount")]
+    amount_column: String,
+    /// CSV payee column
+    #[arg(long, default_value = "Description")]
+    payee_column: String,
+    /// CSV column copied into the notes
+    #[arg(long)]
+    memo_column: Option<String>,
+    /// CSV date format, e.g. %d.%m.%Y
+    #[arg(long, default_value = "%Y-%m-%d")]
+    date_format: String,
+    #[arg(long, default_value_t = ',')]
+    delimiter: char,
+    /// Amounts use a decimal comma, like 1.234,56
+    #[arg(long)]
+    decimal_comma: bool,
+    /// Expenses are positive numbers in the statement
+    #[arg(long)]
+    expenses_positive: bool,
+    /// Currency of the statement [default: the base currency]
+    #[arg(long, value_parser = parse_currency)]
+    currency: Option<String>,
+}
+
+impl ImportArgs {
+    fn mapping(&self) -> Result<CsvMapping, String> {
+        let delimiter =
+            u8::try_from(self.delimiter).map_err(|_| "The delimiter must be ASCII".to_owned())?;
+        Ok(CsvMapping {
+            date: Column::from(self.date_column.as_str()),
+            amount: Column::from(self.amount_column.as_str()),
+            payee: Column::from(self.payee_column.as_str()),
+            memo: self.memo_column.as_deref().map(Column::from),
+            date_format: self.date_format.clone(),
+            delimiter,
+            decimal_comma: self.decimal_comma,
+            expenses_positive: self.expenses_positive,
+        })
+    }
+
+    fn is_ofx(&self) -> bool {
+        self.statement
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("ofx") || ext.eq_ignore_ascii_case("qfx"))
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct AddArgs {
+    #[arg(long)]
+    title: String,
+    #[arg(long, value_parser = parse_amount)]
+    amount: Decimal,
+    /// Currency code like EUR [default: the base currency]
+    #[arg(long, value_parser = parse_currency)]
+    currency: Option<String>,
+    /// Date like 2024-01-31 [default: today]
+    #[arg(long, value_parser = parse_date)]
+    date: Option<NaiveDate>,
+    #[arg(long, default_value = "uncategorized")]
+    category: String,
+    #[arg(long, default_value = "")]
+    payee: String,
+    #[arg(long, default_value = "")]
+    notes: String,
+    #[arg(long)]
+    paid: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct UpdateArgs {
+    id: u32,
+    #[arg(long)]
+    title: Option<String>,
+    #[arg(long, value_parser = parse_amount)]
+    amount: Option<Decimal>,
+    #[arg(long, value_parser = parse_currency)]
+    currency: Option<String>,
+    #[arg(long, value_parser = parse_date)]
+    date: Option<NaiveDate>,
+    #[arg(long)]
+    category: Option<String>,
+    #[arg(long)]
+    payee: Option<String>,
+    #[arg(long)]
+    notes: Option<String>,
+    #[arg(long)]
+    paid: Option<bool>,
+}
+
+#[derive(Debug, Args)]
+pub struct FilterArgs {
+    /// Only bills on or after this date
+    #[arg(long, value_parser = parse_date)]
+    from: Option<NaiveDate>,
+    /// Only bills on or before this date
+    #[arg(long, value_parser = parse_date)]
+    to: Option<NaiveDate>,
+    #[arg(long)]
+    category: Option<String>,
+}
+
+impl From<FilterArgs> for Filter {
+    fn from(args: FilterArgs) -> Self {
+        Filter {
+            from: args.from,
+            to: args.to,
+            category: args.category,
+        }
+    }
+}
+
+fn write_json(out: &mut dyn Write, value: &impl serde::Serialize) -> Result<(), String> {
+    serde_json::to_writer_pretty(&mut *out, value).map_err(|err| err.to_string())?;
+    writeln!(out).map_err(|err| err.to_string())
+}
+
+fn goal_json(goal: &Goal) -> serde_json::Value {
+    json!({
+        "name": goal.name,
+        "target": goal.target,
+        "saved": goal.saved,
+        "by": goal.by,
+        "monthly": goal.monthly(today()),
+    })
+}
+
+// Writes a record that was added, changed or removed: as JSON with --json,
+// otherwise as "<action> <record>".
+fn write_record(
+    out: &mut dyn Write,
+    json: bool,
+    action: &str,
+    record: &(impl serde::Serialize + std::fmt::Display),
+) -> Result<(), String> {
+    if json {
+        write_json(out, record)
+    } else {
+        writeln!(out, "{action} {record}").map_err(|err| err.to_string())
+    }
+}
+
+fn warn_if_over_budget(ledger: &Ledger, id: u32, warnings: &mut dyn Write) -> Result<(), String> {
+    match ledger.budget_warning(id) {
+        Some(warning) => writeln!(warnings, "{warning}").map_err(|err| err.to_string()),
+        None => Ok(()),
+    }
+}
+
+// Runs one subcommand against the ledger. Returns whether the ledger changed
+// and needs saving; budget warnings go to `warnings` so they never mix with
+// JSON output. The store is read for the change log, and written by
+// commands that change how it is stored. New passphrases are looked up
+// with `env`, see `env_var`.
+pub fn execute(
+    command: Command,
+    ledger: &mut Ledger,
+    store: &mut dyn Store,
+    json: bool,
+    out: &mut dyn Write,
+    warnings: &mut dyn Write,
+    env: &dyn Fn(&str) -> Option<String>,
+) -> Result<bool, String> {
+    let io_err = |err: std::io::Error| err.to_string();
+    match command {
+        Command::Add(args) => {
+            let date = args.date.unwrap_or_else(today);
+            let mut bill = Bill::new(
+                ledger.next_bill_id(),
+                &args.title,
+                args.amount,
+                date,
+                &args.category,
+            );
+            bill.currency = args
+                .currency
+                .unwrap_or_else(|| ledger.base_currency().to_owned());
+            bill.payee = args.payee;
+            bill.notes = args.notes;
+            bill.paid = args.paid;
+            write_record(out, json, "Added", &bill)?;
+            let id = bill.id;
+            ledger.bills.insert(id, bill);
+            warn_if_over_budget(ledger, id, warnings)?;
+            Ok(true)
+        }
+        Command::List(filter) => {
+            let bills = Filter::from(filter).apply(&ledger.bills);
+            if json {
+                write_json(out, &bills)?;
+            } else {
+                for bill in bills {
+                    writeln!(out, "{bill}").map_err(io_err)?;
+                }
+            }
+            Ok(false)
+        }
+        Command::Remove { id } => {
+            let bill = ledger
+                .remove_bill(id)
+                .ok_or(format!("Bill #{id} not found"))?;
+            write_record(out, json, "Removed", &bill)?;
+            Ok(true)
+        }
+        Command::Update(args) => {
+            let bill = ledger
+                .bills
+                .get_mut(&args.id)
+                .ok_or(format!("Bill #{} not found", args.id))?;
+            if let Some(title) = args.title {
+                bill.title = title;
+            }
+            if let Some(amount) = args.amount {
+                bill.amount = amount;
+            }
+            if let Some(currency) = args.currency {
+                bill.currency = currency;
+            }
+            if let Some(date) = args.date {
+                bill.date = date;
+            }
+            if let Some(category) = args.category {
+                bill.category = category;
+            }
+            if let Some(payee) = args.payee {
+                bill.payee = payee;
+            }
+            if let Some(notes) = args.notes {
+                bill.notes = notes;
+            }
+            if let Some(paid) = args.paid {
+                bill.paid = paid;
+            }
+            write_record(out, json, "Updated", bill)?;
+            warn_if_over_budget(ledger, args.id, warnings)?;
+            Ok(true)
+        }
+        Command::Total(filter) => {
+            let bills = Filter::from(filter).apply(&ledger.bills);
+            let base = ledger.base_currency();
+            let total = currency::total(&bills, &ledger.rates, base)?;
+            if json {
+                write_json(
+                    out,
+                    &json!({ "count": bills.len(), "total": total, "currency": base }),
+                )?;
+            } else {
+                writeln!(out, "Total bill: {total:.2} {base} ({} bills)", bills.len())
+                    .map_err(io_err)?;
+            }
+            Ok(false)
+        }
+        Command::Report { month } => {
+            let month = month.unwrap_or_else(today);
+            let bills = ledger.bills_in_base()?;
+            let report = budget::report(&ledger.budgets, &bills, month);
+            if json {
+                let rows: Vec<_> = report
+                    .iter()
+                    .map(|status| {
+                        json!({
+                            "category": status.category,
+                            "budget": status.limit,
+                            "spent": status.spent,
+                            "remaining": status.remaining(),
+                            "percent_used": status.percent_used().round_dp(1),
+                        })
+                    })
+                    .collect();
+                write_json(out, &rows)?;
+            } else {
+                for status in report {
+                    writeln!(
+                        out,
+                        "{}: spent {:.2} of {:.2}, {:.2} remaining ({:.0}% used)",
+                        status.category,
+                        status.spent,
+                        status.limit,
+                        status.remaining(),
+                        status.percent_used().round()
+                    )
+                    .map_err(io_err)?;
+                }
+            }
+            Ok(false)
+        }
+        Command::Spending(args) => {
+            let converted = ledger.bills_in_base()?;
+            let bills = Filter::from(args.filter).apply(&converted);
+            let report = report::build(&bills, args.by, args.limit);
+            if let Some(path) = args.export {
+                let contents = match path.extension().and_then(|ext| ext.to_str()) {
+                    Some("csv") => report::to_csv(&report)?,
+                    Some("html" | "htm") => report::to_html(&report),
+                    _ => {
+                        return Err(format!(
+                            "Can only export to .csv or .html, not {}",
+                            path.display()
+                        ))
+                    }
+                };
+                std::fs::write(&path, contents)
+                    .map_err(|err| format!("Could not write {}: {err}", path.display()))?;
+                if json {
+                    write_json(out, &json!({ "title": report.title, "path": path }))?;
+                } else {
+                    writeln!(out, "Exported {} to {}", report.title, path.display())
+                        .map_err(io_err)?;
+                }
+            } else if json {
+                let rows: Vec<serde_json::Map<String, serde_json::Value>> = report
+                    .rows
+                    .iter()
+                    .map(|row| {
+                        report
+                            .headers
+                            .iter()
+                            .map(|header| header.to_lowercase())
+                            .zip(row.iter().map(|cell| json!(cell)))
+                            .collect()
+                    })
+                    .collect();
+                write_json(out, &rows)?;
+            } else {
+                write!(out, "{}", report::table(&report)).map_err(io_err)?;
+                if args.chart {
+                    writeln!(out).map_err(io_err)?;
+                    write!(out, "{}", report::chart(&report)).map_err(io_err)?;
+                }
+            }
+            Ok(false)
+        }
+        Command::Import(args) => {
+            let data = std::fs::read(&args.statement)
+                .map_err(|err| format!("Could not read {}: {err}", args.statement.display()))?;
+            let transactions = if args.is_ofx() {
+                import::parse_ofx(&String::from_utf8_lossy(&data))?
+            } else {
+                import::parse_csv(&data, &args.mapping()?)?
+            };
+            let currency = args
+                .currency
+                .unwrap_or_else(|| ledger.base_currency().to_owned());
+            let summary = import::import(
+                &transactions,
+                &currency,
+                &mut ledger.bills,
+                &mut ledger.settings.last_bill_id,
+                &ledger.rules,
+            );
+            if json {
+                write_json(
+                    out,
+                    &json!({
+                        "imported": summary.imported,
+                        "duplicates": summary.duplicates,
+                        "credits_skipped": summary.credits_skipped,
+                    }),
+                )?;
+            } else {
+                writeln!(
+                    out,
+                    "Imported {} expenses, skipped {} already imported and {} incoming payments",
+                    summary.imported, summary.duplicates, summary.credits_skipped
+                )
+                .map_err(io_err)?;
+            }
+            Ok(summary.imported > 0)
+        }
+        Command::Recurring(RecurringCommand::List) => {
+            if json {
+                write_json(out, &ledger.recurring)?;
+            } else {
+                for bill in &ledger.recurring {
+                    writeln!(out, "{bill}").map_err(io_err)?;
+                }
+            }
+            Ok(false)
+        }
+        Command::Recurring(RecurringCommand::Remove { id }) => {
+            let bill = recurring::remove(&mut ledger.recurring, id)
+                .ok_or(format!("Recurring bill #{id} not found"))?;
+            write_record(out, json, "Stopped", &bill)?;
+            Ok(true)
+        }
+        Command::Rule(RuleCommand::Add { pattern, category }) => {
+            ledger
+                .rules
+                .retain(|rule| !rule.pattern.eq_ignore_ascii_case(&pattern));
+            let rule = CategoryRule::new(&pattern, &category);
+            if json {
+                write_json(out, &rule)?;
+            } else {
+                writeln!(out, "Payees containing '{pattern}' go to {category}").map_err(io_err)?;
+            }
+            ledger.rules.push(rule);
+            Ok(true)
+        }
+        Command::Rule(RuleCommand::List) => {
+            if json {
+                write_json(out, &ledger.rules)?;
+            } else {
+                for rule in &ledger.rules {
+                    writeln!(out, "{} -> {}", rule.pattern, rule.category).map_err(io_err)?;
+                }
+            }
+            Ok(false)
+        }
+        Command::Rule(RuleCommand::Remove { pattern }) => {
+            let position = ledger
+                .rules
+                .iter()
+                .position(|rule| rule.pattern.eq_ignore_ascii_case(&pattern))
+                .ok_or(format!("No rule for '{pattern}'"))?;
+            let rule = ledger.rules.remove(position);
+            if json {
+                write_json(out, &rule)?;
+            } else {
+                writeln!(out, "Removed the rule for '{pattern}'").map_err(io_err)?;
+            }
+            Ok(true)
+        }
+        Command::Rate(RateCommand::Set {
+            currency,
+            rate,
+            to,
+            date,
+        }) => {
+            let rate = ExchangeRate {
+                date: date.unwrap_or_else(today),
+                from: currency,
+                to: to.unwrap_or_else(|| ledger.base_currency().to_owned()),
+                rate,
+            };
+            if rate.from == rate.to || rate.rate.is_zero() {
+                return Err(format!("{rate} is not a useful rate"));
+            }
+            write_record(out, json, "Rate", &rate)?;
+            currency::set_rate(&mut ledger.rates, rate);
+            Ok(true)
+        }
+        Command::Rate(RateCommand::List) => {
+            if json {
+                write_json(out, &ledger.rates)?;
+            } else {
+                for rate in &ledger.rates {
+                    writeln!(out, "{rate}").map_err(io_err)?;
+                }
+            }
+            Ok(false)
+        }
+        Command::Split(args) => {
+            let bill = ledger
+                .bills
+                .get(&args.id)
+                .ok_or(format!("Bill #{} not found", args.id))?;
+            let paid_by = args.paid_by.unwrap_or_default();
+            let shares = if let Some(people) = args.equal {
+                let parts = split::parse_people(&people)?
+                    .into_iter()
+                    .map(|person| (person, Decimal::ZERO))
+                    .collect();
+                split::shares(bill, &paid_by, Method::Equal, parts)?
+            } else if let Some(parts) = args.exact {
+                split::shares(bill, &paid_by, Method::Exact, split::parse_parts(&parts)?)?
+            } else if let Some(parts) = args.percent {
+                split::shares(bill, &paid_by, Method::Percent, split::parse_parts(&parts)?)?
+            } else {
+                Vec::new()
+            };
+            let refs: Vec<&split::Share> = shares.iter().collect();
+            let owed = split::owed(bill, &refs)?;
+            if json {
+                let owed: Vec<_> = owed
+                    .iter()
+                    .map(|(person, amount)| {
+                        json!({
+                            "from": person,
+                            "to": paid_by,
+                            "amount": amount,
+                            "currency": bill.currency,
+                        })
+                    })
+                    .collect();
+                write_json(out, &owed)?;
+            } else if shares.is_empty() {
+                writeln!(out, "Bill #{} is no longer shared", bill.id).map_err(io_err)?;
+            } else {
+                for (person, amount) in owed {
+                    writeln!(out, "{person} owes {paid_by} {amount:.2} {}", bill.currency)
+                        .map_err(io_err)?;
+                }
+            }
+            split::set(&mut ledger.splits, args.id, shares);
+            Ok(true)
+        }
+        Command::Attachment(AttachmentCommand::Add { id, file }) => {
+            if !ledger.bills.contains_key(&id) {
+                return Err(format!("Bill #{id} not found"));
+            }
+            let (attachment, data) = attachment::from_file(id, &file)?;
+            if ledger.attachments.contains(&attachment) {
+                return Err(format!("{} is already attached to #{id}", attachment.name));
+            }
+            attachment::store(&store.attachments_dir(), &attachment, &data, store.sealer())?;
+            if json {
+                write_json(out, &attachment)?;
+            } else {
+                writeln!(out, "Attached {attachment}").map_err(io_err)?;
+            }
+            ledger.attachments.push(attachment);
+            Ok(true)
+        }
+        Command::Attachment(AttachmentCommand::List { id }) => {
+            let attachments: Vec<&attachment::Attachment> = ledger
+                .attachments
+                .iter()
+                .filter(|attachment| id.is_none_or(|id| attachment.bill_id == id))
+                .filter(|attachment| ledger.bills.contains_key(&attachment.bill_id))
+                .collect();
+            if json {
+                write_json(out, &attachments)?;
+            } else {
+                for attachment in attachments {
+                    writeln!(out, "{attachment}").map_err(io_err)?;
+                }
+            }
+            Ok(false)
+        }
+        Command::Attachment(AttachmentCommand::Open { hash }) => {
+            let attachment = attachment::find(&ledger.attachments, &hash)?;
+            attachment::open(&store.attachments_dir(), attachment, store.sealer())?;
+            write_record(out, json, "Opening", attachment)?;
+            Ok(false)
+        }
+        Command::Attachment(AttachmentCommand::Export { hash, to }) => {
+            let attachment = attachment::find(&ledger.attachments, &hash)?;
+            let path =
+                attachment::export(&store.attachments_dir(), attachment, &to, store.sealer())?;
+            if json {
+                write_json(out, &json!({ "name": attachment.name, "path": path }))?;
+            } else {
+                writeln!(out, "Exported {} to {}", attachment.name, path.display())
+                    .map_err(io_err)?;
+            }
+            Ok(false)
+        }
+        Command::Attachment(AttachmentCommand::Remove { id, hash }) => {
+            let of_bill: Vec<attachment::Attachment> = ledger
+                .attachments
+                .iter()
+                .filter(|attachment| attachment.bill_id == id)
+                .cloned()
+                .collect();
+            let removed = attachment::find(&of_bill, &hash)?;
+            ledger.attachments.retain(|attachment| {
+                !(attachment.bill_id == id && attachment.hash == removed.hash)
+            });
+            if json {
+                write_json(out, &removed)?;
+            } else {
+                writeln!(
+                    out,
+                    "Detached {} from #{id}, run `expense-manager attachment gc` to delete unused files",
+                    removed.name
+                )
+                .map_err(io_err)?;
+            }
+            Ok(true)
+        }
+        Command::Attachment(AttachmentCommand::Gc) => {
+            let collected = attachment::collect_garbage(
+                &store.attachments_dir(),
+                &mut ledger.attachments,
+                &ledger.bills,
+            )?;
+            if json {
+                write_json(
+                    out,
+                    &json!({ "records": collected.records, "files": collected.files, "bytes": collected.bytes }),
+                )?;
+            } else {
+                writeln!(
+                    out,
+                    "Deleted {} unused files ({}) and forgot {} attachments of removed bills",
+                    collected.files,
+                    attachment::format_size(collected.bytes),
+                    collected.records
+                )
+                .map_err(io_err)?;
+            }
+            Ok(collected.records > 0)
+        }
+        Command::Income(IncomeCommand::Add {
+            source,
+            amount,
+            date,
+            currency,
+            every,
+        }) => {
+            let income = Income {
+                id: forecast::next_income_id(&ledger.income),
+                source,
+                amount,
+                currency: currency.unwrap_or_else(|| ledger.base_currency().to_owned()),
+                date: date.unwrap_or_else(today),
+                rule: every,
+            };
+            write_record(out, json, "Added income", &income)?;
+            ledger.income.push(income);
+            Ok(true)
+        }
+        Command::Income(IncomeCommand::List) => {
+            if json {
+                write_json(out, &ledger.income)?;
+            } else {
+                for income in &ledger.income {
+                    writeln!(out, "{income}").map_err(io_err)?;
+                }
+            }
+            Ok(false)
+        }
+        Command::Income(IncomeCommand::Remove { id }) => {
+            let position = ledger
+                .income
+                .iter()
+                .position(|income| income.id == id)
+                .ok_or(format!("Income #{id} not found"))?;
+            let income = ledger.income.remove(position);
+            write_record(out, json, "Removed income", &income)?;
+            Ok(true)
+        }
+        Command::Goal(GoalCommand::Add {
+            name,
+            target,
+            by,
+            saved,
+        }) => {
+            if forecast::find_goal(&mut ledger.goals, &name).is_ok() {
+                return Err(format!("There is already a goal '{name}'"));
+            }
+            let goal = Goal {
+                name,
+                target,
+                saved,
+                by,
+            };
+            if json {
+                write_json(out, &goal_json(&goal))?;
+            } else {
+                writeln!(
+                    out,
+                    "Added goal {goal}, {:.2} {} a month",
+                    goal.monthly(today()),
+                    ledger.base_currency()
+                )
+                .map_err(io_err)?;
+            }
+            ledger.goals.push(goal);
+            Ok(true)
+        }
+        Command::Goal(GoalCommand::Save { name, amount }) => {
+            let goal = forecast::find_goal(&mut ledger.goals, &name)?;
+            goal.saved += amount;
+            if json {
+                write_json(out, &goal_json(goal))?;
+            } else {
+                writeln!(out, "{goal}").map_err(io_err)?;
+            }
+            Ok(true)
+        }
+        Command::Goal(GoalCommand::List) => {
+            let base = ledger.base_currency();
+            if json {
+                let goals: Vec<_> = ledger.goals.iter().map(goal_json).collect();
+                write_json(out, &goals)?;
+            } else {
+                for goal in &ledger.goals {
+                    if goal.remaining().is_zero() {
+                        writeln!(out, "{goal}, reached").map_err(io_err)?;
+                    } else {
+                        writeln!(out, "{goal}, {:.2} {base} a month", goal.monthly(today()))
+                            .map_err(io_err)?;
+                    }
+                }
+            }
+            Ok(false)
+        }
+        Command::Goal(GoalCommand::Remove { name }) => {
+            let position = ledger
+                .goals
+                .iter()
+                .position(|goal| goal.name.eq_ignore_ascii_case(&name))
+                .ok_or(format!("No goal '{name}'"))?;
+            let goal = ledger.goals.remove(position);
+            if json {
+                write_json(out, &goal_json(&goal))?;
+            } else {
+                writeln!(out, "Removed the goal '{name}'").map_err(io_err)?;
+            }
+            Ok(true)
+        }
+        Command::Forecast { months, balance } => {
+            if !(1..=forecast::MAX_MONTHS).contains(&months) {
+                return Err(format!(
+                    "Forecast at least 1 and at most {} months",
+                    forecast::MAX_MONTHS
+                ));
+            }
+            let base = ledger.base_currency();
+            let forecast = forecast::forecast(ledger, today(), months, balance)?;
+            let negative = forecast.iter().find(|month| month.balance < Decimal::ZERO);
+            if json {
+                let months: Vec<_> = forecast
+                    .iter()
+                    .map(|month| {
+                        json!({
+                            "month": month.start.format("%Y-%m").to_string(),
+                            "income": month.income,
+                            "bills": month.bills,
+                            "savings": month.savings,
+                            "balance": month.balance,
+                        })
+                    })
+                    .collect();
+                let negative = negative.map(|month| month.start.format("%Y-%m").to_string());
+                write_json(
+                    out,
+                    &json!({ "currency": base, "months": months, "negative": negative }),
+                )?;
+            } else {
+                let report = report::Report {
+                    title: format!("Forecast in {base}"),
+                    headers: vec!["Month", "Income", "Bills", "Savings", "Balance"],
+                    rows: forecast
+                        .iter()
+                        .map(|month| {
+                            vec![
+                                month.start.format("%Y-%m").to_string(),
+                                format!("{:.2}", month.income),
+                                format!("{:.2}", month.bills),
+                                format!("{:.2}", month.savings),
+                                format!("{:.2}", month.balance),
+                            ]
+                        })
+                        .collect(),
+                    bars: Vec::new(),
+                };
+                write!(out, "{}", report::table(&report)).map_err(io_err)?;
+                if let Some(month) = negative {
+                    writeln!(
+                        out,
+                        "Warning: the balance goes negative in {} ({:.2} {base})",
+                        month.start.format("%Y-%m"),
+                        month.balance
+                    )
+                    .map_err(io_err)?;
+                }
+            }
+            Ok(false)
+        }
+        Command::Balances => {
+            let base = ledger.base_currency();
+            let balances = split::balances(
+                &ledger.bills,
+                &ledger.splits,
+                &ledger.payments,
+                &ledger.rates,
+                base,
+            )?;
+            let transfers = split::settle_up(&balances);
+            if json {
+                let transfers: Vec<_> = transfers
+                    .iter()
+                    .map(|transfer| {
+                        json!({ "from": transfer.from, "to": transfer.to, "amount": transfer.amount })
+                    })
+                    .collect();
+                write_json(
+                    out,
+                    &json!({ "currency": base, "balances": balances, "transfers": transfers }),
+                )?;
+            } else {
+                for (person, balance) in &balances {
+                    writeln!(out, "{person}: {balance:+.2} {base}").map_err(io_err)?;
+                }
+                for transfer in &transfers {
+                    writeln!(out, "{transfer} {base}").map_err(io_err)?;
+                }
+            }
+            Ok(false)
+        }
+        Command::Settle {
+            from,
+            to,
+            amount,
+            date,
+        } => {
+            let payment = Payment {
+                date: date.unwrap_or_else(today),
+                from,
+                to,
+                amount,
+            };
+            if json {
+                write_json(out, &payment)?;
+            } else {
+                writeln!(out, "{payment} {}", ledger.base_currency()).map_err(io_err)?;
+            }
+            ledger.payments.push(payment);
+            Ok(true)
+        }
+        Command::Base { currency: None } => {
+            if json {
+                write_json(out, &json!({ "base_currency": ledger.base_currency() }))?;
+            } else {
+                writeln!(out, "{}", ledger.base_currency()).map_err(io_err)?;
+            }
+            Ok(false)
+        }
+        Command::Base {
+            currency: Some(currency),
+        } => {
+            if json {
+                write_json(out, &json!({ "base_currency": currency }))?;
+            } else {
+                writeln!(
+                    out,
+                    "Totals and reports are now in {currency}; budget limits are kept as they are"
+                )
+                .map_err(io_err)?;
+            }
+            ledger.settings.base_currency = currency;
+            Ok(true)
+        }
+        Command::History { as_of: None, .. } => {
+            let log = store.load_log().map_err(|err| err.to_string())?;
+            if json {
+                write_json(out, &log)?;
+            } else {
+                for entry in &log {
+                    writeln!(
+                        out,
+                        "{} {}",
+                        entry.time.format("%Y-%m-%d %H:%M:%S"),
+                        entry.change
+                    )
+                    .map_err(io_err)?;
+                }
+            }
+            Ok(false)
+        }
+        Command::History {
+            as_of: Some(time),
+            restore,
+        } => {
+            let log = store.load_log().map_err(|err| err.to_string())?;
+            let past = history::as_of(&ledger.bills, &log, time)?;
+            if restore {
+                let changes = history::diff(&ledger.bills, &past).len();
+                if json {
+                    write_json(out, &json!({ "changed": changes }))?;
+                } else {
+                    writeln!(out, "Restored the bills, {changes} changed").map_err(io_err)?;
+                }
+                ledger.bills = past;
+                return Ok(changes > 0);
+            }
+            let bills: Vec<&Bill> = past.values().collect();
+            if json {
+                write_json(out, &bills)?;
+            } else {
+                for bill in bills {
+                    writeln!(out, "{bill}").map_err(io_err)?;
+                }
+            }
+            Ok(false)
+        }
+        Command::Migrate { to } => {
+            let format = Format::from_path(&to).ok_or(format!(
+                "Can't tell the format of {} from its extension",
+                to.display()
+            ))?;
+            let passphrase = store.passphrase().map(str::to_owned);
+            let target = storage::open(&to, format, passphrase).map_err(|err| err.to_string())?;
+            match target.load() {
+                Err(StorageError::NotFound(_)) => {}
+                Ok(_) => return Err(format!("{} already exists", to.display())),
+                Err(err) => return Err(err.to_string()),
+            }
+            let log = store.load_log().map_err(|err| err.to_string())?;
+            // Attached files are kept next to the data file, so they move
+            // with it. They are copied first, so the new file never refers
+            // to files that are missing.
+            let attached = attachment::copy(
+                &store.attachments_dir(),
+                &target.attachments_dir(),
+                &ledger.attachments,
+                store.sealer(),
+                target.sealer(),
+            )?;
+            target.save(ledger).map_err(|err| err.to_string())?;
+            target.append_log(&log).map_err(|err| err.to_string())?;
+            if json {
+                write_json(
+                    out,
+                    &json!({
+                        "bills": ledger.bills.len(),
+                        "changes": log.len(),
+                        "attachments": attached,
+                        "file": to,
+                    }),
+                )?;
+            } else {
+                writeln!(
+                    out,
+                    "Copied {} bills, {} logged changes and {attached} attached files to {}, \
+                     use --file {} from now on",
+                    ledger.bills.len(),
+                    log.len(),
+                    to.display(),
+                    to.display()
+                )
+                .map_err(io_err)?;
+            }
+            Ok(false)
+        }
+        Command::Passphrase { remove } => {
+            let passphrase = if remove {
+                None
+            } else {
+                Some(new_passphrase(store.passphrase().is_some(), env)?)
+            };
+            let message = match (&passphrase, store.passphrase()) {
+                (None, None) => return Err(format!("{} is not encrypted", store.path().display())),
+                (None, Some(_)) => "is no longer encrypted",
+                (Some(_), None) => "is now encrypted",
+                (Some(_), Some(_)) => "has a new passphrase",
+            };
+            let encrypted = passphrase.is_some();
+            store
+                .change_passphrase(ledger, passphrase)
+                .map_err(|err| err.to_string())?;
+            if json {
+                write_json(
+                    out,
+                    &json!({ "file": store.path(), "encrypted": encrypted }),
+                )?;
+            } else {
+                writeln!(out, "{} {message}", store.path().display()).map_err(io_err)?;
+            }
+            Ok(false)
+        }
+        #[cfg(feature = "tui")]
+        Command::Tui => unreachable!("main() starts the TUI itself"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::budget::Budget;
+    use crate::recurring::RecurringBill;
+    use rust_decimal_macros::dec;
+
+    fn run(args: &[&str], ledger: &mut Ledger) -> (Result<bool, String>, String, String) {
+        run_with(
+            &mut storage::FileStore::new("does-not-exist.json", Format::Json),
+            args,
+            ledger,
+        )
+    }
+
+    fn run_with(
+        store: &mut dyn Store,
+        args: &[&str],
+        ledger: &mut Ledger,
+    ) -> (Result<bool, String>, String, String) {
+        run_in_env(store, &[], args, ledger)
+    }
+
+    // Runs with `vars` as the whole environment.
+    fn run_in_env(
+        store: &mut dyn Store,
+        vars: &[(&str, &str)],
+        args: &[&str],
+        ledger: &mut Ledger,
+    ) -> (Result<bool, String>, String, String) {
+        let cli = Cli::try_parse_from([&["expense-manager"], args].concat()).unwrap();
+        let (mut out, mut warnings) = (Vec::new(), Vec::new());
+        let result = execute(
+            cli.command.unwrap(),
+            ledger,
+            store,
+            cli.json,
+            &mut out,
+            &mut warnings,
+            &env_of(vars),
+        );
+        (
+            result,
+            String::from_utf8(out).unwrap(),
+            String::from_utf8(warnings).unwrap(),
+        )
+    }
+
+    fn env_of<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        |name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        }
+    }
+
+    #[test]
+    fn add_list_update_and_remove() {
+        let mut ledger = Ledger::default();
+        let add = [
+            "add",
+            "--title",
+            "Rent",
+            "--amount",
+            "1200",
+            "--date",
+            "2024-01-01",
+            "--category",
+            "Housing",
+        ];
+        assert_eq!(run(&add, &mut ledger).0, Ok(true));
+        assert_eq!(run(&add, &mut ledger).0, Ok(true));
+        assert_eq!(ledger.bills.len(), 2);
+
+        let (result, out, _) = run(
+            &["update", "2", "--amount", "1300", "--paid", "true"],
+            &mut ledger,
+        );
+        assert_eq!(result, Ok(true));
+        assert!(out.starts_with("Updated #2 2024-01-01 Rent 1300.00 USD [Housing] (paid)"));
+
+        let (_, out, _) = run(&["list", "--category", "housing"], &mut ledger);
+        assert_eq!(out.lines().count(), 2);
+
+        assert_eq!(run(&["remove", "1"], &mut ledger).0, Ok(true));
+        assert_eq!(
+            run(&["remove", "1"], &mut ledger).0,
+            Err("Bill #1 not found".to_owned())
+        );
+    }
+
+    #[test]
+    fn a_bill_without_a_rate_does_not_hide_other_budget_warnings() {
+        let mut ledger = Ledger::default();
+        ledger.budgets.push(Budget::new("Food", Decimal::from(50)));
+        let date = parse_date("2024-03-01").unwrap();
+        let mut hotel = Bill::new(1, "Hotel", Decimal::from(9000), date, "Travel");
+        hotel.currency = "JPY".to_owned();
+        ledger.bills.insert(1, hotel);
+        let add = [
+            "add",
+            "--title",
+            "Dinner",
+            "--amount",
+            "80",
+            "--date",
+            "2024-03-02",
+            "--category",
+            "Food",
+        ];
+        let (result, _, warnings) = run(&add, &mut ledger);
+        assert_eq!(result, Ok(true));
+        assert!(
+            warnings.contains("Food is over its monthly budget"),
+            "{warnings}"
+        );
+    }
+
+    #[test]
+    fn json_output_is_machine_readable() {
+        let mut ledger = Ledger::default();
+        ledger.budgets.push(Budget::new("Food", Decimal::from(50)));
+        let (_, _, warnings) = run(
+            &[
+                "add",
+                "--title",
+                "Dinner",
+                "--amount",
+                "80",
+                "--date",
+                "2024-03-02",
+                "--category",
+                "Food",
+                "--json",
+            ],
+            &mut ledger,
+        );
+        assert!(warnings.contains("Food is over its monthly budget"));
+
+        let (_, out, _) = run(&["list", "--json"], &mut ledger);
+        let bills: Vec<Bill> = serde_json::from_str(&out).unwrap();
+        assert_eq!(bills[0].title, "Dinner");
+
+        let (_, out, _) = run(&["total", "--json", "--from", "2024-03-01"], &mut ledger);
+        let total: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(
+            total,
+            json!({ "count": 1, "total": "80", "currency": "USD" })
+        );
+
+        let (_, out, _) = run(&["report", "--month", "2024-03", "--json"], &mut ledger);
+        let report: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(report[0]["remaining"], json!("-30"));
+    }
+
+    #[test]
+    fn changes_are_reported_as_json_too() {
+        let mut ledger = Ledger::default();
+        let mut json_of = |args: &[&str]| {
+            let (result, out, _) = run(&[&["--json"], args].concat(), &mut ledger);
+            assert_eq!(result, Ok(true), "{args:?}");
+            serde_json::from_str::<serde_json::Value>(&out).unwrap()
+        };
+        let rule = json_of(&["rule", "add", "tesco", "Groceries"]);
+        assert_eq!(rule["category"], json!("Groceries"));
+        let rule = json_of(&["rule", "remove", "TESCO"]);
+        assert_eq!(rule["pattern"], json!("tesco"));
+        let income = json_of(&["income", "add", "Salary", "2500"]);
+        assert_eq!(income["source"], json!("Salary"));
+        let payment = json_of(&["settle", "bob", "alice", "20"]);
+        assert_eq!(payment["amount"], json!("20"));
+        let goal = json_of(&["goal", "add", "Bike", "900", "--by", "2099-12-31"]);
+        assert_eq!(goal["target"], json!("900"));
+        let base = json_of(&["base", "eur"]);
+        assert_eq!(base, json!({ "base_currency": "EUR" }));
+    }
+
+    #[test]
+    fn totals_are_converted_to_the_base_currency() {
+        let mut ledger = Ledger::default();
+        assert_eq!(run(&["base", "eur"], &mut ledger).0, Ok(true));
+        let rate = ["rate", "set", "usd", "0.9", "--date", "2024-01-01"];
+        assert_eq!(run(&rate, &mut ledger).0, Ok(true));
+        for (amount, currency) in [("100", "USD"), ("19.99", "EUR"), ("1000", "JPY")] {
+            let args = [
+                "add",
+                "--title",
+                "Thing",
+                "--amount",
+                amount,
+                "--currency",
+                currency,
+                "--date",
+                "2024-02-01",
+            ];
+            assert_eq!(run(&args, &mut ledger).0, Ok(true));
+        }
+        assert_eq!(
+            run(&["total"], &mut ledger).0,
+            Err("No JPY to EUR exchange rate on or before 2024-02-01".to_owned())
+        );
+        let rate = [
+            "rate",
+            "set",
+            "eur",
+            "160",
+            "--to",
+            "jpy",
+            "--date",
+            "2024-01-15",
+        ];
+        assert_eq!(run(&rate, &mut ledger).0, Ok(true));
+        let (_, out, _) = run(&["total"], &mut ledger);
+        assert_eq!(out, "Total bill: 116.24 EUR (3 bills)\n");
+        let (_, out, _) = run(&["rate", "list"], &mut ledger);
+        assert_eq!(
+            out,
+            "2024-01-15 1 EUR = 160 JPY\n2024-01-01 1 USD = 0.9 EUR\n"
+        );
+    }
+
+    #[test]
+    fn shared_bills_settle_up() {
+        let mut ledger = Ledger::default();
+        for amount in ["90", "40"] {
+            let args = ["add", "--title", "Offsite", "--amount", amount];
+            assert_eq!(run(&args, &mut ledger).0, Ok(true));
+        }
+        let (result, out, _) = run(
+            &[
+                "split",
+                "1",
+                "--paid-by",
+                "alice",
+                "--equal",
+                "alice,bob,carol",
+            ],
+            &mut ledger,
+        );
+        assert_eq!(result, Ok(true));
+        assert!(out.contains("bob owes alice 30.00 USD"));
+        let exact = [
+            "split",
+            "2",
+            "--paid-by",
+            "bob",
+            "--exact",
+            "carol=25,dave=15",
+        ];
+        assert_eq!(run(&exact, &mut ledger).0, Ok(true));
+        assert!(run(
+            &["split", "2", "--paid-by", "bob", "--percent", "carol=50"],
+            &mut ledger
+        )
+        .0
+        .is_err());
+        assert!(
+            Cli::try_parse_from(["expense-manager", "split", "2", "--paid-by", "bob"]).is_err()
+        );
+
+        let (_, out, _) = run(&["balances"], &mut ledger);
+        assert_eq!(
+            out,
+            "alice: +60.00 USD\nbob: +10.00 USD\ncarol: -55.00 USD\ndave: -15.00 USD\n\
+             carol pays alice 55.00 USD\ndave pays bob 10.00 USD\ndave pays alice 5.00 USD\n"
+        );
+        assert_eq!(
+            run(&["settle", "carol", "alice", "55"], &mut ledger).0,
+            Ok(true)
+        );
+        let (_, out, _) = run(&["balances", "--json"], &mut ledger);
+        let balances: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(balances["balances"]["carol"], json!("0"));
+        assert_eq!(balances["transfers"].as_array().unwrap().len(), 2);
+
+        let splits = ledger.splits.len();
+        assert_eq!(run(&["remove", "2"], &mut ledger).0, Ok(true));
+        assert_eq!(ledger.splits.len(), splits, "kept in case of an undo");
+        let (_, out, _) = run(&["balances"], &mut ledger);
+        assert!(!out.contains("dave"), "{out}");
+        assert_eq!(run(&["split", "1", "--clear"], &mut ledger).0, Ok(true));
+        assert!(ledger.splits.iter().all(|share| share.bill_id == 2));
+    }
+
+    #[test]
+    fn spending_prints_charts_and_exports() {
+        let mut ledger = Ledger::default();
+        for (title, amount, category) in [("Rent", "900", "Housing"), ("Lunch", "12.5", "Food")] {
+            let args = [
+                "add",
+                "--title",
+                title,
+                "--amount",
+                amount,
+                "--date",
+                "2024-02-01",
+                "--category",
+                category,
+            ];
+            assert_eq!(run(&args, &mut ledger).0, Ok(true));
+        }
+        let (_, out, _) = run(&["spending", "--chart"], &mut ledger);
+        assert!(out.starts_with("Spending by category\n"));
+        assert!(out.contains(&format!("Housing |{}", "#".repeat(40))));
+
+        let (_, out, _) = run(
+            &["spending", "--by", "top", "--limit", "1", "--json"],
+            &mut ledger,
+        );
+        let rows: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(
+            rows,
+            json!([{ "id": "1", "date": "2024-02-01", "title": "Rent", "category": "Housing", "amount": "900.00" }])
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let export = dir.path().join("spending.html");
+        let (result, _, _) = run(
+            &[
+                "spending",
+                "--by",
+                "month",
+                "--export",
+                export.to_str().unwrap(),
+            ],
+            &mut ledger,
+        );
+        assert_eq!(result, Ok(false));
+        assert!(std::fs::read_to_string(export)
+            .unwrap()
+            .contains("<td>2024-02</td>"));
+        assert!(run(&["spending", "--export", "out.pdf"], &mut ledger)
+            .0
+            .is_err());
+    }
+
+    #[test]
+    fn import_applies_rules_and_skips_re_imports() {
+        let dir = tempfile::tempdir().unwrap();
+        let statement = dir.path().join("statement.csv");
+        std::fs::write(
+            &statement,
+            "Datum;Empfaenger;Betrag\n31.01.2024;Stadtwerke;-80,00\n31.01.2024;Bakery;-3,50\n",
+        )
+        .unwrap();
+        let statement = statement.to_str().unwrap();
+        let import = [
+            "import",
+            statement,
+            "--date-column",
+            "Datum",
+            "--amount-column",
+            "Betrag",
+            "--payee-column",
+            "1",
+            "--date-format",
+            "%d.%m.%Y",
+            "--delimiter",
+            ";",
+            "--decimal-comma",
+        ];
+        let mut ledger = Ledger::default();
+        assert_eq!(
+            run(&["rule", "add", "stadtwerke", "Utilities"], &mut ledger).0,
+            Ok(true)
+        );
+        let (result, out, _) = run(&import, &mut ledger);
+        assert_eq!(result, Ok(true));
+        assert!(out.starts_with("Imported 2 expenses"));
+        assert_eq!(ledger.bills[&1].category, "Utilities");
+        assert_eq!(ledger.bills[&2].category, "uncategorized");
+
+        let (result, out, _) = run(&import, &mut ledger);
+        assert_eq!(result, Ok(false));
+        assert!(out.contains("skipped 2 already imported"));
+        assert_eq!(
+            run(&["rule", "remove", "gas"], &mut ledger).0,
+            Err("No rule for 'gas'".to_owned())
+        );
+    }
+
+    #[test]
+    fn store_follows_file_and_format_flags() {
+        let store = |args: &[&str]| {
+            Cli::try_parse_from([&["expense-manager"], args].concat())
+                .unwrap()
+                .store(None)
+                .unwrap()
+        };
+        assert_eq!(store(&[]).path(), PathBuf::from("bills.json"));
+        assert_eq!(
+            store(&["--format", "csv"]).path(),
+            PathBuf::from("bills.csv")
+        );
+        assert!(format!("{:?}", store(&["--file", "data/my.csv"])).contains("Csv"));
+        assert!(
+            Cli::try_parse_from(["expense-manager", "add", "--title", "x", "--amount", "-1"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn history_shows_and_restores_past_bills() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = storage::FileStore::new(dir.path().join("bills.json"), Format::Json);
+        let mut ledger = Ledger::default();
+        let rent = Bill::new(
+            1,
+            "Rent",
+            Decimal::from(1200),
+            parse_date("2024-01-01").unwrap(),
+            "Housing",
+        );
+        ledger.bills.insert(1, rent.clone());
+        let entry = |time, before: &Ledger, after: &Ledger| history::LogEntry {
+            time: history::parse_time(time).unwrap(),
+            change: history::diff(&before.bills, &after.bills).remove(0),
+        };
+        let added = entry("2024-01-01 09:00", &Ledger::default(), &ledger);
+        let before = ledger.clone();
+        ledger.bills.get_mut(&1).unwrap().paid = true;
+        let paid = entry("2024-01-05 09:00", &before, &ledger);
+        store.append_log(&[added, paid]).unwrap();
+
+        let (result, out, _) = run_with(&mut store, &["history"], &mut ledger);
+        assert_eq!(result, Ok(false));
+        // The change is shown before and after, on two lines.
+        assert_eq!(out.lines().count(), 3);
+        assert!(out.starts_with("2024-01-01 09:00:00 added #1"));
+
+        let (_, out, _) = run_with(
+            &mut store,
+            &["history", "--as-of", "2024-01-02"],
+            &mut ledger,
+        );
+        assert!(out.contains("Rent") && out.contains("(unpaid)"));
+        assert!(ledger.bills[&1].paid);
+
+        let (result, _, _) = run_with(
+            &mut store,
+            &["history", "--as-of", "2024-01-02", "--restore"],
+            &mut ledger,
+        );
+        assert_eq!(result, Ok(true));
+        assert_eq!(ledger.bills[&1], rent);
+        assert!(Cli::try_parse_from(["expense-manager", "history", "--restore"]).is_err());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn migrate_copies_bills_and_log_to_sqlite() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = storage::FileStore::new(dir.path().join("bills.json"), Format::Json);
+        let mut ledger = Ledger::default();
+        let before = ledger.bills.clone();
+        let add = run_with(
+            &mut store,
+            &[
+                "add",
+                "--title",
+                "Rent",
+                "--amount",
+                "1200",
+                "--category",
+                "Housing",
+            ],
+            &mut ledger,
+        );
+        assert_eq!(add.0, Ok(true));
+        store.save_with_log(&ledger, &before).unwrap();
+
+        let db = dir.path().join("bills.db");
+        let (result, out, _) =
+            run_with(&mut store, &["migrate", db.to_str().unwrap()], &mut ledger);
+        assert_eq!(result, Ok(false));
+        assert!(out.starts_with("Copied 1 bills, 1 logged changes and 0 attached files"));
+        let target = storage::open(&db, Format::Sqlite, None).unwrap();
+        assert_eq!(target.load().unwrap(), ledger);
+        assert_eq!(target.load_log().unwrap(), store.load_log().unwrap());
+
+        let (result, _, _) = run_with(&mut store, &["migrate", db.to_str().unwrap()], &mut ledger);
+        assert!(result.unwrap_err().contains("already exists"));
+        let (result, _, _) = run_with(&mut store, &["migrate", "bills.txt"], &mut ledger);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn migrate_takes_the_attached_files_along() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = storage::FileStore::new(dir.path().join("bills.json"), Format::Json)
+            .with_passphrase(Some("hunter2".to_owned()));
+        let receipt = dir.path().join("receipt.jpg");
+        std::fs::write(&receipt, b"jpeg").unwrap();
+        let mut ledger = Ledger::default();
+        let add = ["add", "--title", "Lunch", "--amount", "12"];
+        assert_eq!(run_with(&mut store, &add, &mut ledger).0, Ok(true));
+        let attach = ["attachment", "add", "1", receipt.to_str().unwrap()];
+        assert_eq!(run_with(&mut store, &attach, &mut ledger).0, Ok(true));
+
+        std::fs::create_dir(dir.path().join("data")).unwrap();
+        let target = dir.path().join("data").join("expenses.json");
+        let (result, out, _) = run_with(
+            &mut store,
+            &["migrate", target.to_str().unwrap()],
+            &mut ledger,
+        );
+        assert_eq!(result, Ok(false));
+        assert!(out.contains("1 attached files"), "{out}");
+
+        let mut moved =
+            storage::FileStore::new(&target, Format::Json).with_passphrase(Some("hunter2".into()));
+        let mut ledger = moved.load().unwrap();
+        let hash = ledger.attachments[0].hash.clone();
+        let exported = dir.path().join("exported.jpg");
+        let export = ["attachment", "export", &hash, exported.to_str().unwrap()];
+        assert_eq!(run_with(&mut moved, &export, &mut ledger).0, Ok(false));
+        assert_eq!(std::fs::read(&exported).unwrap(), b"jpeg");
+    }
+
+    #[test]
+    fn passphrase_encrypts_and_decrypts_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bills.json");
+        let mut store: Box<dyn Store> = Box::new(storage::FileStore::new(&path, Format::Json));
+        let mut ledger = Ledger::default();
+        let (result, _, _) = run_with(store.as_mut(), &["passphrase", "--remove"], &mut ledger);
+        assert!(result.unwrap_err().contains("is not encrypted"));
+        store.save(&ledger).unwrap();
+
+        // The passphrase variable doesn't encrypt a plain file by itself,
+        // but the passphrase command takes it as the new passphrase.
+        let vars = [(PASSPHRASE_VAR, "hunter2")];
+        assert_eq!(passphrase_for(&path, &env_of(&vars)), Ok(None));
+        let (result, out, _) = run_in_env(store.as_mut(), &vars, &["passphrase"], &mut ledger);
+        assert_eq!(result, Ok(false));
+        assert!(out.ends_with("is now encrypted\n"));
+        assert!(storage::is_encrypted(&path));
+        assert_eq!(store.passphrase(), Some("hunter2"));
+        assert_eq!(
+            passphrase_for(&path, &env_of(&vars)),
+            Ok(Some("hunter2".to_owned()))
+        );
+
+        // Once encrypted, only the new passphrase variable changes it.
+        let vars = [
+            (PASSPHRASE_VAR, "hunter2"),
+            (NEW_PASSPHRASE_VAR, "swordfish"),
+        ];
+        let (result, out, _) = run_in_env(store.as_mut(), &vars, &["passphrase"], &mut ledger);
+        assert_eq!(result, Ok(false));
+        assert!(out.ends_with("has a new passphrase\n"));
+        assert_eq!(store.passphrase(), Some("swordfish"));
+
+        let (result, out, _) = run_with(store.as_mut(), &["passphrase", "--remove"], &mut ledger);
+        assert_eq!(result, Ok(false));
+        assert!(out.ends_with("is no longer encrypted\n"));
+        assert!(!storage::is_encrypted(&path));
+    }
+
+    #[test]
+    fn attachments_are_kept_until_garbage_collected() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = storage::FileStore::new(dir.path().join("bills.json"), Format::Json);
+        let receipt = dir.path().join("receipt.jpg");
+        std::fs::write(&receipt, b"jpeg").unwrap();
+        let receipt = receipt.to_str().unwrap();
+        let mut ledger = Ledger::default();
+        let mut run = |args: &[&str], ledger: &mut Ledger| run_with(&mut store, args, ledger);
+        let (result, _, _) = run(
+            &[
+                "add",
+                "--title",
+                "Lunch",
+                "--amount",
+                "12",
+                "--category",
+                "Food",
+            ],
+            &mut ledger,
+        );
+        assert_eq!(result, Ok(true));
+
+        let (result, out, _) = run(&["attachment", "add", "1", receipt], &mut ledger);
+        assert_eq!(result, Ok(true));
+        assert!(out.starts_with("Attached #1 "), "{out}");
+        assert!(run(&["attachment", "add", "1", receipt], &mut ledger)
+            .0
+            .is_err());
+        assert!(run(&["attachment", "add", "7", receipt], &mut ledger)
+            .0
+            .is_err());
+        let hash = ledger.attachments[0].hash.clone();
+        let (_, out, _) = run(&["attachment", "list", "1"], &mut ledger);
+        assert!(out.contains("receipt.jpg (4 B)"));
+
+        let exported = dir.path().join("copy.jpg");
+        let (result, _, _) = run(
+            &[
+                "attachment",
+                "export",
+                &hash[..8],
+                exported.to_str().unwrap(),
+            ],
+            &mut ledger,
+        );
+        assert_eq!(result, Ok(false));
+        assert_eq!(std::fs::read(&exported).unwrap(), b"jpeg");
+
+        assert_eq!(run(&["remove", "1"], &mut ledger).0, Ok(true));
+        assert_eq!(run(&["attachment", "list"], &mut ledger).1, "");
+        let stored = dir.path().join("bills.attachments");
+        assert_eq!(std::fs::read_dir(&stored).unwrap().count(), 1);
+        let (result, out, _) = run(&["attachment", "gc"], &mut ledger);
+        assert_eq!(result, Ok(true));
+        assert!(
+            out.starts_with("Deleted 1 unused files (4 B) and forgot 1 attachments"),
+            "{out}"
+        );
+        assert!(ledger.attachments.is_empty());
+        assert_eq!(std::fs::read_dir(&stored).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn recurring_bills_are_listed_and_stopped() {
+        let mut ledger = Ledger::default();
+        ledger.recurring.push(RecurringBill::new(
+            1,
+            "Gym",
+            dec!(30),
+            "Health",
+            Rule::Weekly,
+            parse_date("2024-01-01").unwrap(),
+        ));
+        let (result, out, _) = run(&["recurring", "list"], &mut ledger);
+        assert_eq!(result, Ok(false));
+        assert_eq!(
+            out,
+            "#1 Gym 30.00 USD [Health] weekly from 2024-01-01, next due 2024-01-01\n"
+        );
+        let (result, out, _) = run(&["--json", "recurring", "list"], &mut ledger);
+        assert_eq!(result, Ok(false));
+        assert!(out.contains("\"rule\": \"weekly\""), "{out}");
+
+        assert_eq!(
+            run(&["recurring", "remove", "2"], &mut ledger).0,
+            Err("Recurring bill #2 not found".to_owned())
+        );
+        let (result, out, _) = run(&["recurring", "remove", "1"], &mut ledger);
+        assert_eq!(result, Ok(true));
+        assert!(out.starts_with("Stopped #1 Gym"));
+        assert!(ledger.recurring.is_empty());
+    }
+
+    #[test]
+    fn income_goals_and_forecast() {
+        let mut ledger = Ledger::default();
+        let (result, out, _) = run(
+            &[
+                "income",
+                "add",
+                "Salary",
+                "2500",
+                "--date",
+                "2024-01-28",
+                "--every",
+                "monthly:28",
+            ],
+            &mut ledger,
+        );
+        assert_eq!(result, Ok(true));
+        assert_eq!(
+            out,
+            "Added income #1 Salary 2500.00 USD monthly:28 from 2024-01-28\n"
+        );
+        assert!(Cli::try_parse_from([
+            "expense-manager",
+            "income",
+            "add",
+            "Gift",
+            "50",
+            "--every",
+            "daily"
+        ])
+        .is_err());
+
+        let (result, _, _) = run(
+            &[
+                "goal",
+                "add",
+                "Bike",
+                "900",
+                "--by",
+                "2099-12-31",
+                "--saved",
+                "100",
+            ],
+            &mut ledger,
+        );
+        assert_eq!(result, Ok(true));
+        assert!(run(
+            &["goal", "add", "bike", "1", "--by", "2099-12-31"],
+            &mut ledger
+        )
+        .0
+        .is_err());
+        assert_eq!(
+            run(&["goal", "save", "BIKE", "800"], &mut ledger).0,
+            Ok(true)
+        );
+        let (_, out, _) = run(&["goal", "list"], &mut ledger);
+        assert_eq!(out, "Bike: 900.00 of 900.00 saved by 2099-12-31, reached\n");
+
+        assert_eq!(run(&["income", "remove", "1"], &mut ledger).0, Ok(true));
+        let (result, out, _) = run(
+            &["forecast", "--months", "2", "--balance", "-50"],
+            &mut ledger,
+        );
+        assert_eq!(result, Ok(false));
+        assert!(out.starts_with("Forecast in USD\nMonth"), "{out}");
+        assert!(out.contains("Warning: the balance goes negative in"));
+        assert!(out.ends_with("(-50.00 USD)\n"), "{out}");
+        let (_, out, _) = run(&["--json", "forecast", "--months", "3"], &mut ledger);
+        let forecast: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(forecast["months"].as_array().unwrap().len(), 3);
+        assert!(forecast["negative"].is_null());
+        assert!(run(&["forecast", "--months", "0"], &mut ledger).0.is_err());
+        assert_eq!(
+            run(&["forecast", "--months", "5000000"], &mut ledger).0,
+            Err("Forecast at least 1 and at most 1200 months".to_owned())
+        );
+        assert_eq!(
+            run(&["forecast", "--months", "1200"], &mut ledger).0,
+            Ok(false)
+        );
+    }
+}
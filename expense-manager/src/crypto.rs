@@ -0,0 +1,191 @@
+use std::cell::RefCell;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce};
+
+// An encrypted file starts with this, then a header and the ciphertext:
+//
+//   magic (8) | version (1) | argon2 memory KiB, passes, lanes (3 x u32 LE)
+//   | salt (16) | nonce (12) | ciphertext with its 16 byte tag
+//
+// The whole header is authenticated along with the data.
+const MAGIC: &[u8; 8] = b"EXPMGR\0\0";
+const VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = MAGIC.len() + 1 + 12 + SALT_LEN + NONCE_LEN;
+
+// Argon2id with its recommended settings. The settings are stored in each
+// file, so they can be raised later without breaking old files.
+#[cfg(not(test))]
+const KDF_COST: (u32, u32, u32) = (19 * 1024, 2, 1);
+// Tests only need the format, not the strength.
+#[cfg(test)]
+const KDF_COST: (u32, u32, u32) = (8, 1, 1);
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+// Encrypts `data` if there is a sealer.
+pub fn seal(sealer: Option<&Sealer>, data: &[u8]) -> Result<Vec<u8>, String> {
+    match sealer {
+        Some(sealer) => sealer.encrypt(data),
+        None => Ok(data.to_vec()),
+    }
+}
+
+// Decrypts `data` if there is a sealer. Data that is encrypted without a
+// sealer, or unencrypted with one, is refused.
+pub fn unseal(sealer: Option<&Sealer>, data: Vec<u8>) -> Result<Vec<u8>, String> {
+    match (sealer, is_encrypted(&data)) {
+        (Some(sealer), true) => sealer.decrypt(&data),
+        (None, false) => Ok(data),
+        (Some(_), false) => Err("not encrypted, but a passphrase was given".to_owned()),
+        (None, true) => Err("encrypted, but no passphrase was given".to_owned()),
+    }
+}
+
+// Encrypts and decrypts with keys derived from one passphrase. Deriving a
+// key is slow on purpose, so the last one is kept: every file a store
+// writes shares its salt.
+pub struct Sealer {
+    passphrase: String,
+    // The header fields the key was derived from, and the key.
+    key: RefCell<Option<(Vec<u8>, Key)>>,
+}
+
+impl std::fmt::Debug for Sealer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Sealer")
+    }
+}
+
+impl Sealer {
+    pub fn new(passphrase: impl Into<String>) -> Self {
+        Self {
+            passphrase: passphrase.into(),
+            key: RefCell::new(None),
+        }
+    }
+
+    pub fn passphrase(&self) -> &str {
+        &self.passphrase
+    }
+
+    // `settings` is the cost and salt part of a header.
+    fn key(&self, settings: &[u8]) -> Result<Key, String> {
+        if let Some((cached, key)) = &*self.key.borrow() {
+            if cached == settings {
+                return Ok(*key);
+            }
+        }
+        let number = |at: usize| u32::from_le_bytes(settings[at..at + 4].try_into().unwrap());
+        let params = Params::new(number(0), number(4), number(8), Some(32))
+            .map_err(|err| format!("bad key settings: {err}"))?;
+        let mut key = Key::default();
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(self.passphrase.as_bytes(), &settings[12..], &mut key)
+            .map_err(|err| format!("could not derive the key: {err}"))?;
+        *self.key.borrow_mut() = Some((settings.to_vec(), key));
+        Ok(key)
+    }
+
+    pub fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>, String> {
+        let settings = match &*self.key.borrow() {
+            Some((settings, _)) => settings.clone(),
+            None => {
+                let (memory, passes, lanes) = KDF_COST;
+                let mut settings = Vec::new();
+                for number in [memory, passes, lanes] {
+                    settings.extend(number.to_le_bytes());
+                }
+                let mut salt = [0; SALT_LEN];
+                OsRng.fill_bytes(&mut salt);
+                settings.extend(salt);
+                settings
+            }
+        };
+        let key = self.key(&settings)?;
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let mut header = MAGIC.to_vec();
+        header.push(VERSION);
+        header.extend(&settings);
+        header.extend(nonce);
+        let ciphertext = ChaCha20Poly1305::new(&key)
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: data,
+                    aad: &header,
+                },
+            )
+            .map_err(|_| "encryption failed".to_owned())?;
+        header.extend(ciphertext);
+        Ok(header)
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, String> {
+        if !is_encrypted(data) || data.len() < HEADER_LEN {
+            return Err("not an encrypted expense file".to_owned());
+        }
+        let version = data[MAGIC.len()];
+        if version != VERSION {
+            return Err(format!(
+                "encrypted with format version {version}, which this program does not know"
+            ));
+        }
+        let (header, ciphertext) = data.split_at(HEADER_LEN);
+        let settings = &header[MAGIC.len() + 1..HEADER_LEN - NONCE_LEN];
+        let key = self.key(settings)?;
+        ChaCha20Poly1305::new(&key)
+            .decrypt(
+                Nonce::from_slice(&header[HEADER_LEN - NONCE_LEN..]),
+                Payload {
+                    msg: ciphertext,
+                    aad: header,
+                },
+            )
+            .map_err(|_| {
+                "wrong passphrase, or the file was changed since it was encrypted".to_owned()
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip_and_wrong_passphrase() {
+        let sealer = Sealer::new("correct horse");
+        let sealed = sealer.encrypt(b"rent 1200").unwrap();
+        assert!(is_encrypted(&sealed));
+        assert!(!sealed.windows(4).any(|window| window == b"rent"));
+        assert_eq!(sealer.decrypt(&sealed).unwrap(), b"rent 1200");
+        // A fresh sealer derives the key from the header.
+        assert_eq!(
+            Sealer::new("correct horse").decrypt(&sealed).unwrap(),
+            b"rent 1200"
+        );
+        assert_eq!(
+            Sealer::new("battery staple").decrypt(&sealed),
+            Err("wrong passphrase, or the file was changed since it was encrypted".to_owned())
+        );
+    }
+
+    #[test]
+    fn tampering_and_unknown_versions_are_refused() {
+        let sealer = Sealer::new("correct horse");
+        let mut sealed = sealer.encrypt(b"rent 1200").unwrap();
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        assert!(sealer.decrypt(&sealed).is_err());
+        sealed[last] ^= 1;
+        sealed[MAGIC.len()] = 9;
+        assert!(sealer.decrypt(&sealed).unwrap_err().contains("version 9"));
+        assert!(sealer.decrypt(b"{}").is_err());
+    }
+}
@@ -0,0 +1,195 @@
+use std::fmt;
+
+use chrono::NaiveDate;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+
+use crate::bill::{Bill, Bills, DATE_FORMAT};
+
+// Used for data saved before bills had a currency.
+pub const DEFAULT_CURRENCY: &str = "USD";
+
+pub fn default_currency() -> String {
+    DEFAULT_CURRENCY.to_owned()
+}
+
+// Accepts a three-letter code like eur and returns it as EUR.
+pub fn parse_currency(input: &str) -> Result<String, String> {
+    let code = input.trim();
+    if code.len() == 3 && code.chars().all(|c| c.is_ascii_alphabetic()) {
+        Ok(code.to_ascii_uppercase())
+    } else {
+        Err(format!("'{input}' is not a currency code like EUR"))
+    }
+}
+
+// Rounds to cents, with halves away from zero like a bank would.
+pub fn round(amount: Decimal) -> Decimal {
+    amount.round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero)
+}
+
+// One unit of `from` is worth `rate` units of `to` from `date` until the
+// next rate for the same pair.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExchangeRate {
+    pub date: NaiveDate,
+    pub from: String,
+    pub to: String,
+    pub rate: Decimal,
+}
+
+impl fmt::Display for ExchangeRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} 1 {} = {} {}",
+            self.date.format(DATE_FORMAT),
+            self.from,
+            self.rate,
+            self.to
+        )
+    }
+}
+
+// Adds a rate, replacing one for the same pair and date.
+pub fn set_rate(rates: &mut Vec<ExchangeRate>, rate: ExchangeRate) {
+    rates.retain(|old| !(old.date == rate.date && old.from == rate.from && old.to == rate.to));
+    rates.push(rate);
+    rates.sort_by(|a, b| (&a.from, &a.to, a.date).cmp(&(&b.from, &b.to, b.date)));
+}
+
+// The newest rate on or before `on`. A rate stored the other way round is
+// used inverted.
+pub fn rate(
+    rates: &[ExchangeRate],
+    from: &str,
+    to: &str,
+    on: NaiveDate,
+) -> Result<Decimal, String> {
+    if from == to {
+        return Ok(Decimal::ONE);
+    }
+    rates
+        .iter()
+        .filter(|rate| rate.date <= on && !rate.rate.is_zero())
+        .filter_map(|rate| {
+            if rate.from == from && rate.to == to {
+                Some((rate.date, rate.rate))
+            } else if rate.from == to && rate.to == from {
+                Some((rate.date, Decimal::ONE / rate.rate))
+            } else {
+                None
+            }
+        })
+        .max_by_key(|(date, _)| *date)
+        .map(|(_, rate)| rate)
+        .ok_or(format!(
+            "No {from} to {to} exchange rate on or before {}",
+            on.format(DATE_FORMAT)
+        ))
+}
+
+pub fn convert(
+    rates: &[ExchangeRate],
+    amount: Decimal,
+    from: &str,
+    to: &str,
+    on: NaiveDate,
+) -> Result<Decimal, String> {
+    Ok(round(amount * rate(rates, from, to, on)?))
+}
+
+// Copies of the bills with every amount converted to `base` at the rate of
+// the bill's date, so totals and reports can add them up.
+pub fn in_base(bills: &Bills, rates: &[ExchangeRate], base: &str) -> Result<Bills, String> {
+    bills
+        .values()
+        .map(|bill| {
+            let mut converted = bill.clone();
+            converted.amount = convert(rates, bill.amount, &bill.currency, base, bill.date)?;
+            converted.currency = base.to_owned();
+            Ok((bill.id, converted))
+        })
+        .collect()
+}
+
+pub fn total(bills: &[&Bill], rates: &[ExchangeRate], base: &str) -> Result<Decimal, String> {
+    bills
+        .iter()
+        .map(|bill| convert(rates, bill.amount, &bill.currency, base, bill.date))
+        .sum()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bill::parse_date;
+    use rust_decimal_macros::dec;
+
+    fn rates() -> Vec<ExchangeRate> {
+        let mut rates = Vec::new();
+        for (date, from, to, rate) in [
+            ("2024-01-01", "EUR", "USD", dec!(1.10)),
+            ("2024-02-01", "EUR", "USD", dec!(1.08)),
+            ("2024-01-01", "USD", "JPY", dec!(150)),
+        ] {
+            set_rate(
+                &mut rates,
+                ExchangeRate {
+                    date: parse_date(date).unwrap(),
+                    from: from.to_owned(),
+                    to: to.to_owned(),
+                    rate,
+                },
+            );
+        }
+        rates
+    }
+
+    #[test]
+    fn parse_currency_normalizes_codes() {
+        assert_eq!(parse_currency(" eur"), Ok("EUR".to_owned()));
+        assert!(parse_currency("EURO").is_err());
+        assert!(parse_currency("€").is_err());
+    }
+
+    #[test]
+    fn rate_uses_the_newest_rate_for_the_date() {
+        let rates = rates();
+        let on = |date| parse_date(date).unwrap();
+        assert_eq!(rate(&rates, "EUR", "USD", on("2024-01-31")), Ok(dec!(1.10)));
+        assert_eq!(rate(&rates, "EUR", "USD", on("2024-06-01")), Ok(dec!(1.08)));
+        assert!(rate(&rates, "EUR", "USD", on("2023-12-31"))
+            .unwrap_err()
+            .contains("No EUR to USD exchange rate on or before 2023-12-31"));
+        assert_eq!(
+            convert(&rates, dec!(3000), "JPY", "USD", on("2024-01-02")),
+            Ok(dec!(20.00))
+        );
+        assert_eq!(
+            rate(&rates, "GBP", "GBP", on("2000-01-01")),
+            Ok(Decimal::ONE)
+        );
+    }
+
+    #[test]
+    fn in_base_converts_exactly() {
+        let mut bills = Bills::new();
+        for (id, amount, currency) in [
+            (1, dec!(0.10), "EUR"),
+            (2, dec!(0.20), "EUR"),
+            (3, dec!(0.30), "USD"),
+        ] {
+            let mut bill = Bill::new(id, "Gum", amount, parse_date("2024-01-15").unwrap(), "Food");
+            bill.currency = currency.to_owned();
+            bills.insert(id, bill);
+        }
+        let converted = in_base(&bills, &rates(), "USD").unwrap();
+        let total: Decimal = converted.values().map(|bill| bill.amount).sum();
+        assert_eq!(total, dec!(0.63));
+        let refs: Vec<&Bill> = bills.values().collect();
+        assert_eq!(super::total(&refs, &rates(), "USD"), Ok(dec!(0.63)));
+        assert!(converted.values().all(|bill| bill.currency == "USD"));
+        assert!(in_base(&bills, &rates(), "GBP").is_err());
+    }
+}
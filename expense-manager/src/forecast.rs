@@ -0,0 +1,289 @@
+use std::fmt;
+
+use chrono::{Datelike, Days, Months, NaiveDate};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::bill::DATE_FORMAT;
+use crate::currency::{self, default_currency};
+use crate::ledger::Ledger;
+use crate::recurring::Rule;
+
+// Money coming in, once on `date` or on a schedule starting then.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Income {
+    pub id: u32,
+    pub source: String,
+    pub amount: Decimal,
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    pub date: NaiveDate,
+    pub rule: Option<Rule>,
+}
+
+impl Income {
+    // When the money comes in between `from` and `until` inclusive.
+    pub fn dates(&self, from: NaiveDate, until: NaiveDate) -> Vec<NaiveDate> {
+        match self.rule {
+            Some(rule) => rule.occurrences(self.date, from, until),
+            None if (from..=until).contains(&self.date) => vec![self.date],
+            None => Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for Income {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "#{} {} {:.2} {}",
+            self.id,
+            self.source,
+            currency::round(self.amount),
+            self.currency
+        )?;
+        match self.rule {
+            Some(rule) => write!(f, " {rule} from {}", self.date.format(DATE_FORMAT)),
+            None => write!(f, " on {}", self.date.format(DATE_FORMAT)),
+        }
+    }
+}
+
+pub fn next_income_id(income: &[Income]) -> u32 {
+    income
+        .iter()
+        .map(|income| income.id)
+        .max()
+        .map_or(1, |id| id + 1)
+}
+
+// Money to put aside by a date, in the base currency.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Goal {
+    pub name: String,
+    pub target: Decimal,
+    pub saved: Decimal,
+    pub by: NaiveDate,
+}
+
+impl Goal {
+    pub fn remaining(&self) -> Decimal {
+        (self.target - self.saved).max(Decimal::ZERO)
+    }
+
+    // What to put aside every month from `today`'s month to the target
+    // month to get there in time. Everything is due now once it is late.
+    pub fn monthly(&self, today: NaiveDate) -> Decimal {
+        let months = months_between(today, self.by) + 1;
+        if months <= 1 {
+            self.remaining()
+        } else {
+            currency::round(self.remaining() / Decimal::from(months))
+        }
+    }
+}
+
+impl fmt::Display for Goal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {:.2} of {:.2} saved by {}",
+            self.name,
+            currency::round(self.saved),
+            currency::round(self.target),
+            self.by.format(DATE_FORMAT)
+        )
+    }
+}
+
+pub fn find_goal<'a>(goals: &'a mut [Goal], name: &str) -> Result<&'a mut Goal, String> {
+    goals
+        .iter_mut()
+        .find(|goal| goal.name.eq_ignore_ascii_case(name))
+        .ok_or(format!("No goal '{name}'"))
+}
+
+// Whole calendar months from `from`'s month to `to`'s month.
+fn months_between(from: NaiveDate, to: NaiveDate) -> i32 {
+    (to.year() - from.year()) * 12 + to.month() as i32 - from.month() as i32
+}
+
+// One month of the forecast, in the base currency. `balance` is what is
+// left at the end of the month.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Month {
+    pub start: NaiveDate,
+    pub income: Decimal,
+    pub bills: Decimal,
+    pub savings: Decimal,
+    pub balance: Decimal,
+}
+
+// A hundred years; the months are kept in memory one by one.
+pub const MAX_MONTHS: u32 = 1200;
+
+// The balance month by month, starting with `balance` today. Unpaid bills,
+// including overdue ones, and bills still to come from recurring rules are
+// paid, income arrives, and every unfinished goal gets its monthly share
+// until its target month.
+pub fn forecast(
+    ledger: &Ledger,
+    today: NaiveDate,
+    months: u32,
+    balance: Decimal,
+) -> Result<Vec<Month>, String> {
+    let base = ledger.base_currency();
+    let first = today.with_day(1).expect("every month has a first day");
+    let end = first
+        .checked_add_months(Months::new(months))
+        .ok_or(format!("Can't forecast {months} months ahead"))?
+        - Days::new(1);
+    let mut forecast: Vec<Month> = (0..months)
+        .map(|month| Month {
+            start: first + Months::new(month),
+            income: Decimal::ZERO,
+            bills: Decimal::ZERO,
+            savings: Decimal::ZERO,
+            balance: Decimal::ZERO,
+        })
+        .collect();
+    // Anything before today lands in the first month.
+    let index = |date: NaiveDate| months_between(today, date).max(0) as usize;
+
+    for bill in ledger.bills.values() {
+        if !bill.paid && bill.date <= end {
+            forecast[index(bill.date)].bills +=
+                currency::convert(&ledger.rates, bill.amount, &bill.currency, base, bill.date)?;
+        }
+    }
+    for rule in &ledger.recurring {
+        for date in rule.upcoming(end) {
+            forecast[index(date)].bills +=
+                currency::convert(&ledger.rates, rule.amount, &rule.currency, base, date)?;
+        }
+    }
+    for income in &ledger.income {
+        for date in income.dates(today, end) {
+            forecast[index(date)].income +=
+                currency::convert(&ledger.rates, income.amount, &income.currency, base, date)?;
+        }
+    }
+    for goal in ledger
+        .goals
+        .iter()
+        .filter(|goal| goal.remaining() > Decimal::ZERO)
+    {
+        let monthly = goal.monthly(today);
+        for (number, month) in forecast.iter_mut().enumerate() {
+            if number == 0 || month.start <= goal.by {
+                month.savings += monthly;
+            }
+        }
+    }
+
+    let mut balance = balance;
+    for month in &mut forecast {
+        balance += month.income - month.bills - month.savings;
+        month.balance = balance;
+    }
+    Ok(forecast)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bill::{parse_date, Bill};
+    use crate::recurring::RecurringBill;
+    use rust_decimal_macros::dec;
+
+    fn date(input: &str) -> NaiveDate {
+        parse_date(input).unwrap()
+    }
+
+    #[test]
+    fn goals_spread_what_is_left_over_the_months() {
+        let goal = Goal {
+            name: "Holiday".to_owned(),
+            target: dec!(1000),
+            saved: dec!(100),
+            by: date("2024-06-30"),
+        };
+        assert_eq!(goal.monthly(date("2024-04-10")), dec!(300));
+        assert_eq!(goal.monthly(date("2024-06-30")), dec!(900));
+        assert_eq!(goal.monthly(date("2024-09-01")), dec!(900));
+        assert_eq!(
+            goal.to_string(),
+            "Holiday: 100.00 of 1000.00 saved by 2024-06-30"
+        );
+    }
+
+    #[test]
+    fn forecasts_past_the_last_date_are_refused() {
+        let last_year = NaiveDate::MAX.year();
+        let today = NaiveDate::from_ymd_opt(last_year, 6, 1).unwrap();
+        assert_eq!(
+            forecast(&Ledger::default(), today, 12, Decimal::ZERO),
+            Err("Can't forecast 12 months ahead".to_owned())
+        );
+    }
+
+    #[test]
+    fn forecast_flags_the_month_the_money_runs_out() {
+        let mut ledger = Ledger::default();
+        let mut overdue = Bill::new(1, "Tax", dec!(300), date("2024-02-20"), "Tax");
+        overdue.currency = "EUR".to_owned();
+        let mut paid = Bill::new(2, "Lunch", dec!(20), date("2024-03-05"), "Food");
+        paid.paid = true;
+        ledger.bills = [(1, overdue), (2, paid)].into_iter().collect();
+        ledger.rates.push(currency::ExchangeRate {
+            date: date("2024-01-01"),
+            from: "EUR".to_owned(),
+            to: "USD".to_owned(),
+            rate: dec!(1.1),
+        });
+        ledger.recurring.push(RecurringBill::new(
+            1,
+            "Rent",
+            dec!(1200),
+            "Housing",
+            Rule::Monthly(1),
+            date("2024-03-01"),
+        ));
+        ledger.income = vec![
+            Income {
+                id: 1,
+                source: "Salary".to_owned(),
+                amount: dec!(1000),
+                currency: "USD".to_owned(),
+                date: date("2024-01-25"),
+                rule: Some(Rule::Monthly(25)),
+            },
+            Income {
+                id: 2,
+                source: "Bonus".to_owned(),
+                amount: dec!(500),
+                currency: "USD".to_owned(),
+                date: date("2024-04-15"),
+                rule: None,
+            },
+        ];
+        ledger.goals.push(Goal {
+            name: "Car".to_owned(),
+            target: dec!(400),
+            saved: Decimal::ZERO,
+            by: date("2024-04-30"),
+        });
+
+        let months = forecast(&ledger, date("2024-03-10"), 3, dec!(1000)).unwrap();
+        let balances: Vec<Decimal> = months.iter().map(|month| month.balance).collect();
+        // March: 1000 + 1000 - 1200 rent - 330 tax - 200 saved.
+        // April: + 1000 + 500 - 1200 - 200. May: + 1000 - 1200.
+        assert_eq!(balances, vec![dec!(270), dec!(370), dec!(170)]);
+        assert_eq!(months[0].bills, dec!(1530));
+        assert_eq!(months[2].savings, Decimal::ZERO);
+
+        let months = forecast(&ledger, date("2024-03-10"), 3, Decimal::ZERO).unwrap();
+        let negative = months.iter().find(|month| month.balance < Decimal::ZERO);
+        assert_eq!(negative.map(|month| month.start), Some(date("2024-03-01")));
+    }
+}
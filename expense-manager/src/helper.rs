@@ -1,67 +1,465 @@
-use std::collections::hash_map::Entry;
-use std::collections::HashMap;
-use std::io;
+use chrono::{Days, Local, NaiveDate};
+use rust_decimal::Decimal;
 
-#[derive(Debug)]
-pub struct Bill {
-    pub title: String,
-    pub amount: f64,
+use crate::bill::{parse_date, parse_month, Bill, Bills, Filter, DATE_FORMAT};
+use crate::budget::{self, Budget};
+use crate::currency::{self, parse_currency};
+use crate::history::History;
+use crate::input::{Console, InputError};
+use crate::ledger::Ledger;
+use crate::recurring::{self, next_recurring_id, RecurringBill, Rule};
+use crate::report::{self, Grouping};
+
+pub const DEFAULT_CATEGORY: &str = "uncategorized";
+
+// Unpaid bills due within this many days are shown as due soon, and
+// recurring bills are generated this far ahead.
+pub const DUE_SOON_DAYS: u64 = 7;
+const UPCOMING_DAYS: u64 = 30;
+// Rows shown in interactive spending reports.
+const REPORT_ROWS: usize = 10;
+
+pub fn parse_title(input: &str) -> Result<String, String> {
+    if input.is_empty() {
+        Err("The title can't be empty".to_owned())
+    } else {
+        Ok(input.to_owned())
+    }
+}
+
+fn parse_text(input: &str) -> Result<String, String> {
+    Ok(input.to_owned())
+}
+
+pub fn parse_amount(input: &str) -> Result<Decimal, String> {
+    match input.parse::<Decimal>() {
+        Ok(amount) if amount >= Decimal::ZERO => Ok(amount),
+        _ => Err(format!("'{input}' is not a valid amount")),
+    }
+}
+
+fn parse_paid(input: &str) -> Result<bool, String> {
+    match input.to_lowercase().as_str() {
+        "y" | "yes" => Ok(true),
+        "n" | "no" => Ok(false),
+        _ => Err(format!("'{input}' is not y or n")),
+    }
+}
+
+// Wraps a parser so that a blank answer keeps `default`.
+fn or_default<T: Clone>(
+    default: T,
+    parse: impl Fn(&str) -> Result<T, String>,
+) -> impl Fn(&str) -> Result<T, String> {
+    move |input| {
+        if input.is_empty() {
+            Ok(default.clone())
+        } else {
+            parse(input)
+        }
+    }
+}
+
+fn optional<T>(
+    parse: impl Fn(&str) -> Result<T, String>,
+) -> impl Fn(&str) -> Result<Option<T>, String> {
+    move |input| {
+        if input.is_empty() {
+            Ok(None)
+        } else {
+            parse(input).map(Some)
+        }
+    }
+}
+
+pub fn today() -> NaiveDate {
+    Local::now().date_naive()
+}
+
+fn prompt_filter(console: &mut Console) -> Result<Filter, InputError> {
+    Ok(Filter {
+        from: console.prompt_with(
+            "From date (YYYY-MM-DD, blank for any): ",
+            optional(parse_date),
+        )?,
+        to: console.prompt_with(
+            "To date (YYYY-MM-DD, blank for any): ",
+            optional(parse_date),
+        )?,
+        category: console.prompt_with("Category (blank for any): ", optional(parse_title))?,
+    })
+}
+
+// Lists the bills matching a filter and asks for the id of one of them.
+fn select_bill(
+    console: &mut Console,
+    bills: &Bills,
+    action: &str,
+) -> Result<Option<u32>, InputError> {
+    let filter = prompt_filter(console)?;
+    let ids: Vec<u32> = filter.apply(bills).iter().map(|bill| bill.id).collect();
+    if ids.is_empty() {
+        console.say("No bills match")?;
+        return Ok(None);
+    }
+    for id in &ids {
+        console.say(&bills[id])?;
+    }
+    let message = format!("Enter the id to {action}: ");
+    let id = console.prompt_with(&message, |input| match input.parse::<u32>() {
+        Ok(id) if ids.contains(&id) => Ok(id),
+        _ => Err(format!("'{input}' is not one of the listed ids")),
+    })?;
+    Ok(Some(id))
+}
+
+fn warn_if_over_budget(console: &mut Console, ledger: &Ledger, id: u32) -> Result<(), InputError> {
+    match ledger.budget_warning(id) {
+        Some(warning) => console.say(warning),
+        None => Ok(()),
+    }
+}
+
+fn prompt_currency(console: &mut Console, current: &str) -> Result<String, InputError> {
+    console.prompt_with(
+        &format!("Currency [{current}]: "),
+        or_default(current.to_owned(), parse_currency),
+    )
+}
+
+pub fn add(console: &mut Console, ledger: &mut Ledger) -> Result<(), InputError> {
+    let title = console.prompt_with("Enter the title: ", parse_title)?;
+    let amount = console.prompt_with("Enter the amount: ", parse_amount)?;
+    let currency = prompt_currency(console, ledger.base_currency())?;
+    let date = console.prompt_with(
+        "Enter the date (YYYY-MM-DD, blank for today): ",
+        or_default(today(), parse_date),
+    )?;
+    let category = console.prompt_with(
+        "Enter the category (blank for uncategorized): ",
+        or_default(DEFAULT_CATEGORY.to_owned(), parse_text),
+    )?;
+    let mut bill = Bill::new(0, &title, amount, date, &category);
+    bill.currency = currency;
+    bill.payee = console.prompt_with("Enter the payee (optional): ", parse_text)?;
+    bill.notes = console.prompt_with("Enter notes (optional): ", parse_text)?;
+    bill.paid = console.prompt_with("Is it paid? (y/N): ", or_default(false, parse_paid))?;
+    // The id is only handed out once every field has been answered, so
+    // cancelling halfway doesn't use one up.
+    bill.id = ledger.next_bill_id();
+    console.say(format!("Added {bill}"))?;
+    let id = bill.id;
+    ledger.bills.insert(id, bill);
+    warn_if_over_budget(console, ledger, id)
+}
+
+pub fn view(console: &mut Console, ledger: &Ledger) -> Result<(), InputError> {
+    let filter = prompt_filter(console)?;
+    let matches = filter.apply(&ledger.bills);
+    console.say("Bills: ")?;
+    for bill in &matches {
+        console.say(bill)?;
+    }
+    let base = ledger.base_currency();
+    match currency::total(&matches, &ledger.rates, base) {
+        Ok(total) => console.say(format!(
+            "{} bills, {total:.2} {base} in total",
+            matches.len()
+        )),
+        Err(err) => console.say(format!("{} bills, no total: {err}", matches.len())),
+    }
+}
+
+pub fn remove(console: &mut Console, ledger: &mut Ledger) -> Result<(), InputError> {
+    let Some(id) = select_bill(console, &ledger.bills, "remove")? else {
+        return Ok(());
+    };
+    if let Some(bill) = ledger.remove_bill(id) {
+        console.say(format!("Removed {bill}"))?;
+    }
+    Ok(())
 }
 
-impl Bill {
-    pub fn new(title: &str, amount: f64) -> Self {
-        Self {
-            title: title.to_owned(),
-            amount,
+pub fn update(console: &mut Console, ledger: &mut Ledger) -> Result<(), InputError> {
+    let Some(id) = select_bill(console, &ledger.bills, "update")? else {
+        return Ok(());
+    };
+    let mut bill = ledger.bills[&id].clone();
+    console.say("Press enter to keep the current value")?;
+    bill.title = console.prompt_with(
+        &format!("Title [{}]: ", bill.title),
+        or_default(bill.title.clone(), parse_title),
+    )?;
+    bill.amount = console.prompt_with(
+        &format!("Amount [{}]: ", bill.amount),
+        or_default(bill.amount, parse_amount),
+    )?;
+    bill.currency = prompt_currency(console, &bill.currency)?;
+    bill.date = console.prompt_with(
+        &format!("Date [{}]: ", bill.date.format(DATE_FORMAT)),
+        or_default(bill.date, parse_date),
+    )?;
+    bill.category = console.prompt_with(
+        &format!("Category [{}]: ", bill.category),
+        or_default(bill.category.clone(), parse_text),
+    )?;
+    bill.payee = console.prompt_with(
+        &format!("Payee [{}]: ", bill.payee),
+        or_default(bill.payee.clone(), parse_text),
+    )?;
+    bill.notes = console.prompt_with(
+        &format!("Notes [{}]: ", bill.notes),
+        or_default(bill.notes.clone(), parse_text),
+    )?;
+    bill.paid = console.prompt_with(
+        &format!("Paid [{}]: ", if bill.paid { "y" } else { "n" }),
+        or_default(bill.paid, parse_paid),
+    )?;
+    // Nothing is changed until every field has been answered, so cancelling
+    // halfway keeps the original bill.
+    console.say(format!("Updated {bill}"))?;
+    ledger.bills.insert(id, bill);
+    warn_if_over_budget(console, ledger, id)
+}
+
+pub fn total_bill(console: &mut Console, ledger: &Ledger) -> Result<(), InputError> {
+    let bills: Vec<&Bill> = ledger.bills.values().collect();
+    let base = ledger.base_currency();
+    match currency::total(&bills, &ledger.rates, base) {
+        Ok(total) => console.say(format!("Total bill: {total:.2} {base}")),
+        Err(err) => console.say(format!("No total: {err}")),
+    }
+}
+
+pub fn mark_paid(console: &mut Console, bills: &mut Bills) -> Result<(), InputError> {
+    let Some(id) = select_bill(console, bills, "mark as paid")? else {
+        return Ok(());
+    };
+    if let Some(bill) = bills.get_mut(&id) {
+        bill.paid = true;
+        console.say(format!("Paid {bill}"))?;
+    }
+    Ok(())
+}
+
+pub fn add_recurring(
+    console: &mut Console,
+    recurring: &mut Vec<RecurringBill>,
+    base_currency: &str,
+) -> Result<(), InputError> {
+    let title = console.prompt_with("Enter the title: ", parse_title)?;
+    let amount = console.prompt_with("Enter the amount: ", parse_amount)?;
+    let currency = prompt_currency(console, base_currency)?;
+    let category = console.prompt_with(
+        "Enter the category (blank for uncategorized): ",
+        or_default(DEFAULT_CATEGORY.to_owned(), parse_text),
+    )?;
+    let payee = console.prompt_with("Enter the payee (optional): ", parse_text)?;
+    let rule = console.prompt_with(
+        "Repeat (weekly, monthly:<day>, yearly, every:<days>): ",
+        |input| input.parse::<Rule>(),
+    )?;
+    let start = console.prompt_with(
+        "Starting from (YYYY-MM-DD, blank for today): ",
+        or_default(today(), parse_date),
+    )?;
+    let mut bill = RecurringBill::new(
+        next_recurring_id(recurring),
+        &title,
+        amount,
+        &category,
+        rule,
+        start,
+    );
+    bill.currency = currency;
+    bill.payee = payee;
+    // A start far in the past would otherwise add a bill for every
+    // occurrence since then.
+    let today = today();
+    let past = bill
+        .upcoming(today)
+        .iter()
+        .filter(|&&date| date < today)
+        .count();
+    if past > 0 {
+        let message = format!("Add the {past} bills due before today? (y/n): ");
+        if !console.prompt_with(&message, parse_paid)? {
+            bill.skip_to(today);
         }
     }
+    console.say(format!("Added {bill}"))?;
+    recurring.push(bill);
+    Ok(())
 }
 
-pub fn get_input() -> io::Result<String> {
-    let mut buffer = String::new();
-    while io::stdin().read_line(&mut buffer).is_err() {
-        println!("Please enter your data again");
+pub fn remove_recurring(
+    console: &mut Console,
+    recurring: &mut Vec<RecurringBill>,
+) -> Result<(), InputError> {
+    if recurring.is_empty() {
+        return console.say("No recurring bills");
+    }
+    for bill in recurring.iter() {
+        console.say(bill)?;
+    }
+    let id = console.prompt_with("Enter the id to stop: ", |input| {
+        match input.parse::<u32>() {
+            Ok(id) if recurring.iter().any(|bill| bill.id == id) => Ok(id),
+            _ => Err(format!("'{input}' is not one of the listed ids")),
+        }
+    })?;
+    if let Some(bill) = recurring::remove(recurring, id) {
+        console.say(format!("Stopped {bill}, the bills it added are kept"))?;
     }
-    Ok(buffer.trim().to_owned())
+    Ok(())
 }
 
-pub fn add(bills: &mut HashMap<String, Bill>) {
-    println!("Enter the title: ");
-    let title = get_input().unwrap();
-    println!("Enter the amount: ");
-    let amount = get_input().unwrap().parse::<f64>().unwrap();
-    let bill = Bill::new(&title, amount);
-    bills.insert(bill.title.clone(), bill);
+pub fn view_recurring(
+    console: &mut Console,
+    recurring: &[RecurringBill],
+) -> Result<(), InputError> {
+    let until = today() + Days::new(UPCOMING_DAYS);
+    console.say("Recurring bills: ")?;
+    for bill in recurring {
+        console.say(bill)?;
+        for date in bill.upcoming(until) {
+            console.say(format!("    upcoming {}", date.format(DATE_FORMAT)))?;
+        }
+    }
+    Ok(())
 }
 
-pub fn view(bills: &HashMap<String, Bill>) {
-    println!("Bills: ");
-    for bill in bills {
-        println!("{:?}", bill.1);
+pub fn due_soon(console: &mut Console, bills: &Bills) -> Result<(), InputError> {
+    let today = today();
+    let filter = Filter {
+        to: Some(today + Days::new(DUE_SOON_DAYS)),
+        ..Filter::default()
+    };
+    let unpaid: Vec<&Bill> = filter
+        .apply(bills)
+        .into_iter()
+        .filter(|bill| !bill.paid)
+        .collect();
+    if unpaid.is_empty() {
+        return console.say("Nothing is due soon");
     }
+    for bill in unpaid {
+        let status = if bill.date < today {
+            "OVERDUE"
+        } else {
+            "due soon"
+        };
+        console.say(format!("{status:>8} {bill}"))?;
+    }
+    Ok(())
 }
 
-pub fn remove(bills: &mut HashMap<String, Bill>) {
-    println!("Enter the title to remove: ");
-    let title = get_input().unwrap();
-    bills.remove(&title);
+pub fn set_budget(console: &mut Console, budgets: &mut Vec<Budget>) -> Result<(), InputError> {
+    let category = console.prompt_with("Enter the category: ", parse_title)?;
+    let current =
+        budget::find(budgets, &category).map_or(Decimal::ZERO, |budget| budget.monthly_limit);
+    let limit = console.prompt_with(
+        &format!("Monthly budget [{current:.2}] (0 removes it): "),
+        or_default(current, parse_amount),
+    )?;
+    budget::set(budgets, &category, limit);
+    console.say(format!("Budget for {category} set to {limit:.2}"))
 }
 
-pub fn update(bills: &mut HashMap<String, Bill>) {
-    println!("Enter the title to update: ");
-    let title = get_input().unwrap();
-    println!("Enter the new amount: ");
-    let amount = get_input().unwrap().parse::<f64>().unwrap();
-    match bills.entry(title.to_owned()) {
-        Entry::Occupied(mut entry) => {
-            entry.get_mut().amount = amount;
-            println!("Updated the bill");
+// Converts the bills to the base currency, or explains why it can't.
+fn bills_in_base(console: &mut Console, ledger: &Ledger) -> Result<Option<Bills>, InputError> {
+    match ledger.bills_in_base() {
+        Ok(bills) => Ok(Some(bills)),
+        Err(err) => {
+            console.say(format!("{err}, add one with `expense-manager rate set`"))?;
+            Ok(None)
         }
-        Entry::Vacant(_) => println!("Bill: {title} not found"),
+    }
+}
+
+pub fn budget_report(console: &mut Console, ledger: &Ledger) -> Result<(), InputError> {
+    if ledger.budgets.is_empty() {
+        return console.say("No budgets set");
+    }
+    let month = console.prompt_with(
+        "Month (YYYY-MM, blank for this month): ",
+        or_default(today(), parse_month),
+    )?;
+    let Some(bills) = bills_in_base(console, ledger)? else {
+        return Ok(());
+    };
+    console.say(format!(
+        "{:<16} {:>10} {:>10} {:>10} {:>7}  (in {})",
+        "Category",
+        "Budget",
+        "Spent",
+        "Remaining",
+        "Used",
+        ledger.base_currency()
+    ))?;
+    for status in budget::report(&ledger.budgets, &bills, month) {
+        console.say(format!(
+            "{:<16} {:>10.2} {:>10.2} {:>10.2} {:>6.0}%{}",
+            status.category,
+            status.limit,
+            status.spent,
+            status.remaining(),
+            status.percent_used().round(),
+            if status.is_over() {
+                "  OVER BUDGET"
+            } else {
+                ""
+            }
+        ))?;
+    }
+    Ok(())
+}
+
+pub fn spending_report(console: &mut Console, ledger: &Ledger) -> Result<(), InputError> {
+    let grouping = console.prompt_with(
+        "Group by (category, month, payee, trend or top, blank for category): ",
+        or_default(Grouping::Category, str::parse),
+    )?;
+    let filter = prompt_filter(console)?;
+    let Some(bills) = bills_in_base(console, ledger)? else {
+        return Ok(());
     };
+    let report = report::build(&filter.apply(&bills), grouping, REPORT_ROWS);
+    console.say(report::table(&report).trim_end())?;
+    console.say("")?;
+    console.say(report::chart(&report).trim_end())
 }
 
-pub fn total_bill(bills: &HashMap<String, Bill>) {
-    let total = bills.values().map(|bill| bill.amount).sum::<f64>();
-    println!("Total bill: {total}");
+pub fn undo(
+    console: &mut Console,
+    history: &mut History,
+    ledger: &mut Ledger,
+) -> Result<(), InputError> {
+    match history.undo(&mut ledger.bills) {
+        Ok(Some(changes)) => {
+            for change in changes {
+                console.say(format!("Undo: {change}"))?;
+            }
+            Ok(())
+        }
+        Ok(None) => console.say("Nothing to undo"),
+        Err(err) => console.say(format!("Can't undo: {err}")),
+    }
+}
+
+pub fn redo(
+    console: &mut Console,
+    history: &mut History,
+    ledger: &mut Ledger,
+) -> Result<(), InputError> {
+    match history.redo(&mut ledger.bills) {
+        Ok(Some(changes)) => {
+            for change in changes {
+                console.say(format!("Redo: {change}"))?;
+            }
+            Ok(())
+        }
+        Ok(None) => console.say("Nothing to redo"),
+        Err(err) => console.say(format!("Can't redo: {err}")),
+    }
 }
@@ -0,0 +1,261 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use serde::{Deserialize, Serialize};
+
+use crate::bill::{Bill, Bills};
+
+// One bill before and after an edit: no `before` for an add, no `after`
+// for a remove.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Change {
+    pub before: Option<Bill>,
+    pub after: Option<Bill>,
+}
+
+impl Change {
+    pub fn id(&self) -> u32 {
+        self.before
+            .as_ref()
+            .or(self.after.as_ref())
+            .map(|bill| bill.id)
+            .expect("a change has a bill on at least one side")
+    }
+
+    pub fn inverse(&self) -> Change {
+        Change {
+            before: self.after.clone(),
+            after: self.before.clone(),
+        }
+    }
+
+    // Applies the change, unless the bill is no longer in the `before` state
+    // because something else changed it since.
+    pub fn apply(&self, bills: &mut Bills) -> Result<(), String> {
+        let id = self.id();
+        if bills.get(&id) != self.before.as_ref() {
+            return Err(format!("Bill #{id} was changed since"));
+        }
+        match &self.after {
+            Some(bill) => bills.insert(id, bill.clone()),
+            None => bills.remove(&id),
+        };
+        Ok(())
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.before, &self.after) {
+            (None, Some(after)) => write!(f, "added {after}"),
+            (Some(before), None) => write!(f, "removed {before}"),
+            (Some(before), Some(after)) => write!(f, "changed {before}\n    to {after}"),
+            (None, None) => write!(f, "nothing"),
+        }
+    }
+}
+
+// The changes that turn `before` into `after`, ordered by bill id.
+pub fn diff(before: &Bills, after: &Bills) -> Vec<Change> {
+    let ids: BTreeSet<u32> = before.keys().chain(after.keys()).copied().collect();
+    ids.into_iter()
+        .filter(|id| before.get(id) != after.get(id))
+        .map(|id| Change {
+            before: before.get(&id).cloned(),
+            after: after.get(&id).cloned(),
+        })
+        .collect()
+}
+
+// Applies a group of changes, or none of them if one does not fit.
+fn apply_all<'a>(
+    changes: impl Iterator<Item = &'a Change>,
+    bills: &mut Bills,
+) -> Result<(), String> {
+    let mut result = bills.clone();
+    for change in changes {
+        change.apply(&mut result)?;
+    }
+    *bills = result;
+    Ok(())
+}
+
+// Undo and redo for one session. Every step is the group of changes made
+// by one action.
+#[derive(Debug, Default)]
+pub struct History {
+    done: Vec<Vec<Change>>,
+    undone: Vec<Vec<Change>>,
+}
+
+impl History {
+    pub fn record(&mut self, changes: Vec<Change>) {
+        if !changes.is_empty() {
+            self.done.push(changes);
+            self.undone.clear();
+        }
+    }
+
+    // Reverts the latest step and returns it, or None if there is nothing
+    // to undo.
+    pub fn undo(&mut self, bills: &mut Bills) -> Result<Option<Vec<Change>>, String> {
+        let Some(step) = self.done.last() else {
+            return Ok(None);
+        };
+        let inverse: Vec<Change> = step.iter().rev().map(Change::inverse).collect();
+        apply_all(inverse.iter(), bills)?;
+        let step = self.done.pop().expect("checked above");
+        self.undone.push(step);
+        Ok(Some(inverse))
+    }
+
+    pub fn redo(&mut self, bills: &mut Bills) -> Result<Option<Vec<Change>>, String> {
+        let Some(step) = self.undone.last() else {
+            return Ok(None);
+        };
+        apply_all(step.iter(), bills)?;
+        let step = self.undone.pop().expect("checked above");
+        self.done.push(step.clone());
+        Ok(Some(step))
+    }
+}
+
+// A saved change, as kept in the change log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogEntry {
+    pub time: NaiveDateTime,
+    #[serde(flatten)]
+    pub change: Change,
+}
+
+// Parses `2024-01-31 18:30`, `2024-01-31 18:30:15` or `2024-01-31`, which
+// means the end of that day.
+pub fn parse_time(input: &str) -> Result<NaiveDateTime, String> {
+    let input = input.trim();
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S"] {
+        if let Ok(time) = NaiveDateTime::parse_from_str(input, format) {
+            return Ok(time);
+        }
+    }
+    NaiveDate::parse_from_str(input, "%Y-%m-%d")
+        .map(|date| date.and_time(NaiveTime::from_hms_opt(23, 59, 59).unwrap()))
+        .map_err(|_| format!("'{input}' is not a time like 2024-01-31 18:30"))
+}
+
+// The bills as they were at `time`, found by undoing every logged change
+// made after it.
+pub fn as_of(bills: &Bills, log: &[LogEntry], time: NaiveDateTime) -> Result<Bills, String> {
+    let undo: Vec<Change> = log
+        .iter()
+        .rev()
+        .take_while(|entry| entry.time > time)
+        .map(|entry| entry.change.inverse())
+        .collect();
+    let mut past = bills.clone();
+    apply_all(undo.iter(), &mut past)
+        .map_err(|err| format!("The change log does not match the bills: {err}"))?;
+    Ok(past)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bill::parse_date;
+    use rust_decimal_macros::dec;
+
+    fn rent(amount: rust_decimal::Decimal) -> Bill {
+        Bill::new(
+            1,
+            "Rent",
+            amount,
+            parse_date("2024-01-01").unwrap(),
+            "Housing",
+        )
+    }
+
+    fn bills(list: &[Bill]) -> Bills {
+        list.iter().map(|bill| (bill.id, bill.clone())).collect()
+    }
+
+    #[test]
+    fn diff_finds_adds_removes_and_updates() {
+        let mut groceries = rent(dec!(80));
+        groceries.id = 2;
+        let changes = diff(
+            &bills(&[rent(dec!(1200)), groceries.clone()]),
+            &bills(&[rent(dec!(1300))]),
+        );
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].before, Some(rent(dec!(1200))));
+        assert_eq!(changes[0].after, Some(rent(dec!(1300))));
+        assert_eq!(changes[1].after, None);
+        assert!(diff(&bills(&[groceries.clone()]), &bills(&[groceries])).is_empty());
+    }
+
+    #[test]
+    fn undo_and_redo_walk_the_steps() {
+        let mut history = History::default();
+        let mut current = Bills::new();
+        for amount in [dec!(1200), dec!(1300)] {
+            let before = current.clone();
+            current.insert(1, rent(amount));
+            history.record(diff(&before, &current));
+        }
+        history.undo(&mut current).unwrap();
+        assert_eq!(current[&1].amount, dec!(1200));
+        history.undo(&mut current).unwrap();
+        assert!(current.is_empty());
+        assert_eq!(history.undo(&mut current), Ok(None));
+        history.redo(&mut current).unwrap();
+        assert_eq!(current[&1].amount, dec!(1200));
+
+        // A new edit forgets what could be redone.
+        let before = current.clone();
+        current.get_mut(&1).unwrap().paid = true;
+        history.record(diff(&before, &current));
+        assert_eq!(history.redo(&mut current), Ok(None));
+    }
+
+    #[test]
+    fn undo_refuses_when_the_bill_changed_since() {
+        let mut history = History::default();
+        let mut current = bills(&[rent(dec!(1200))]);
+        history.record(diff(&Bills::new(), &current));
+        current.get_mut(&1).unwrap().paid = true;
+        assert_eq!(
+            history.undo(&mut current),
+            Err("Bill #1 was changed since".to_owned())
+        );
+        assert!(current.contains_key(&1));
+    }
+
+    #[test]
+    fn as_of_rewinds_the_log() {
+        let time = |input| parse_time(input).unwrap();
+        let log = vec![
+            LogEntry {
+                time: time("2024-01-01 10:00"),
+                change: diff(&Bills::new(), &bills(&[rent(dec!(1200))])).remove(0),
+            },
+            LogEntry {
+                time: time("2024-02-01 10:00"),
+                change: diff(&bills(&[rent(dec!(1200))]), &bills(&[rent(dec!(1300))])).remove(0),
+            },
+        ];
+        let current = bills(&[rent(dec!(1300))]);
+        assert_eq!(
+            as_of(&current, &log, time("2024-01-15")).unwrap()[&1].amount,
+            dec!(1200)
+        );
+        assert!(as_of(&current, &log, time("2023-12-31"))
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            as_of(&current, &log, time("2024-02-01 10:00")).unwrap(),
+            current
+        );
+        assert!(as_of(&Bills::new(), &log, time("2024-01-15")).is_err());
+        assert!(parse_time("yesterday").is_err());
+    }
+}
@@ -0,0 +1,426 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::bill::{next_id, Bill, Bills};
+use crate::helper::DEFAULT_CATEGORY;
+
+// Assigns a category to imported transactions whose payee contains
+// `pattern`, ignoring case. The first matching rule wins.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CategoryRule {
+    pub pattern: String,
+    pub category: String,
+}
+
+impl CategoryRule {
+    pub fn new(pattern: &str, category: &str) -> Self {
+        Self {
+            pattern: pattern.to_owned(),
+            category: category.to_owned(),
+        }
+    }
+
+    fn matches(&self, payee: &str) -> bool {
+        payee.to_lowercase().contains(&self.pattern.to_lowercase())
+    }
+}
+
+pub fn categorize<'a>(rules: &'a [CategoryRule], payee: &str) -> &'a str {
+    rules
+        .iter()
+        .find(|rule| rule.matches(payee))
+        .map_or(DEFAULT_CATEGORY, |rule| rule.category.as_str())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transaction {
+    pub date: NaiveDate,
+    // Negative for money leaving the account.
+    pub amount: Decimal,
+    pub payee: String,
+    pub memo: String,
+    // Identifier from the bank, if the statement has one.
+    pub bank_id: Option<String>,
+}
+
+// A CSV column, either by header name or by zero-based position.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Column {
+    Name(String),
+    Index(usize),
+}
+
+impl From<&str> for Column {
+    fn from(value: &str) -> Self {
+        match value.parse::<usize>() {
+            Ok(index) => Column::Index(index),
+            Err(_) => Column::Name(value.to_owned()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CsvMapping {
+    pub date: Column,
+    pub amount: Column,
+    pub payee: Column,
+    pub memo: Option<Column>,
+    pub date_format: String,
+    pub delimiter: u8,
+    // Amounts written like 1.234,56 instead of 1,234.56.
+    pub decimal_comma: bool,
+    // The bank writes money leaving the account as positive numbers.
+    pub expenses_positive: bool,
+}
+
+impl Default for CsvMapping {
+    fn default() -> Self {
+        Self {
+            date: Column::from("Date"),
+            amount: Column::from("Amount"),
+            payee: Column::from("Description"),
+            memo: None,
+            date_format: "%Y-%m-%d".to_owned(),
+            delimiter: b',',
+            decimal_comma: false,
+            expenses_positive: false,
+        }
+    }
+}
+
+pub fn parse_amount(input: &str, decimal_comma: bool) -> Result<Decimal, String> {
+    let trimmed = input.trim();
+    let (negative, body) = match trimmed.strip_prefix('(').and_then(|s| s.strip_suffix(')')) {
+        Some(inner) => (true, inner),
+        None => (false, trimmed),
+    };
+    let mut cleaned: String = body
+        .chars()
+        .filter(|c| c.is_ascii_digit() || matches!(c, '.' | ',' | '-' | '+'))
+        .collect();
+    cleaned = if decimal_comma {
+        cleaned.replace('.', "").replace(',', ".")
+    } else {
+        // Commas only group thousands, so "1,5" is refused instead of being
+        // read as 15.
+        let grouped = cleaned.split(',').skip(1).all(|group| {
+            let digits = group.split('.').next().unwrap_or_default();
+            digits.len() == 3 && digits.chars().all(|c| c.is_ascii_digit())
+        });
+        if !grouped {
+            return Err(format!(
+                "'{input}' is not an amount, use --decimal-comma if the comma is the decimal point"
+            ));
+        }
+        cleaned.replace(',', "")
+    };
+    let amount = cleaned
+        .parse::<Decimal>()
+        .map_err(|_| format!("'{input}' is not an amount"))?;
+    Ok(if negative { -amount.abs() } else { amount })
+}
+
+fn column_index(headers: &csv::StringRecord, column: &Column) -> Result<usize, String> {
+    match column {
+        Column::Index(index) => Ok(*index),
+        Column::Name(name) => headers
+            .iter()
+            .position(|header| header.trim().eq_ignore_ascii_case(name))
+            .ok_or(format!("No column named '{name}'")),
+    }
+}
+
+pub fn parse_csv(data: &[u8], mapping: &CsvMapping) -> Result<Vec<Transaction>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(mapping.delimiter)
+        .flexible(true)
+        .from_reader(data);
+    let headers = reader.headers().map_err(|err| err.to_string())?.clone();
+    let date = column_index(&headers, &mapping.date)?;
+    let amount = column_index(&headers, &mapping.amount)?;
+    let payee = column_index(&headers, &mapping.payee)?;
+    let memo = match &mapping.memo {
+        Some(column) => Some(column_index(&headers, column)?),
+        None => None,
+    };
+
+    let mut transactions = Vec::new();
+    for (line, record) in reader.records().enumerate() {
+        let record = record.map_err(|err| err.to_string())?;
+        // Header is line 1.
+        let line = line + 2;
+        let field = |index: usize| {
+            record
+                .get(index)
+                .map(str::trim)
+                .ok_or(format!("Line {line}: missing column {index}"))
+        };
+        let date = NaiveDate::parse_from_str(field(date)?, &mapping.date_format).map_err(|_| {
+            format!(
+                "Line {line}: '{}' does not match {}",
+                field(date).unwrap_or_default(),
+                mapping.date_format
+            )
+        })?;
+        let mut amount = parse_amount(field(amount)?, mapping.decimal_comma)
+            .map_err(|err| format!("Line {line}: {err}"))?;
+        if mapping.expenses_positive {
+            amount = -amount;
+        }
+        transactions.push(Transaction {
+            date,
+            amount,
+            payee: field(payee)?.to_owned(),
+            memo: match memo {
+                Some(index) => field(index)?.to_owned(),
+                None => String::new(),
+            },
+            bank_id: None,
+        });
+    }
+    Ok(transactions)
+}
+
+// Value of an OFX element such as `<TRNAMT>-12.50`. OFX 1.x (SGML) leaves
+// out closing tags, so the value ends at the next tag or line break.
+fn ofx_value<'a>(block: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{tag}>");
+    let start = block.find(&open)? + open.len();
+    let rest = &block[start..];
+    let end = rest.find(['<', '\n', '\r']).unwrap_or(rest.len());
+    Some(rest[..end].trim())
+}
+
+pub fn parse_ofx(data: &str) -> Result<Vec<Transaction>, String> {
+    let mut transactions = Vec::new();
+    for (index, block) in data.split("<STMTTRN>").skip(1).enumerate() {
+        let block = block.split("</STMTTRN>").next().unwrap_or(block);
+        let missing = |tag: &str| format!("Transaction {}: missing <{tag}>", index + 1);
+        let posted = ofx_value(block, "DTPOSTED").ok_or_else(|| missing("DTPOSTED"))?;
+        let date = posted
+            .get(..8)
+            .and_then(|digits| NaiveDate::parse_from_str(digits, "%Y%m%d").ok())
+            .ok_or(format!("Transaction {}: bad date '{posted}'", index + 1))?;
+        let amount = parse_amount(
+            ofx_value(block, "TRNAMT").ok_or_else(|| missing("TRNAMT"))?,
+            false,
+        )?;
+        let name = ofx_value(block, "NAME").unwrap_or_default();
+        let memo = ofx_value(block, "MEMO").unwrap_or_default();
+        transactions.push(Transaction {
+            date,
+            amount,
+            payee: if name.is_empty() { memo } else { name }.to_owned(),
+            memo: memo.to_owned(),
+            bank_id: ofx_value(block, "FITID").map(str::to_owned),
+        });
+    }
+    if transactions.is_empty() && !data.contains("<OFX>") {
+        return Err("Not an OFX file".to_owned());
+    }
+    Ok(transactions)
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ImportSummary {
+    pub imported: usize,
+    pub duplicates: usize,
+    pub credits_skipped: usize,
+}
+
+// Identifies a transaction across imports. Without a bank id, identical
+// transactions on the same day are told apart by their position among
+// each other in the statement.
+fn import_ids(transactions: &[Transaction]) -> Vec<String> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    transactions
+        .iter()
+        .map(|transaction| match &transaction.bank_id {
+            Some(id) => format!("bank:{id}"),
+            None => {
+                let key = format!(
+                    "{}|{:.2}|{}",
+                    transaction.date,
+                    transaction.amount,
+                    transaction.payee.to_lowercase()
+                );
+                let count = seen.entry(key.clone()).or_insert(0);
+                *count += 1;
+                format!("{key}|{count}")
+            }
+        })
+        .collect()
+}
+
+// Adds every expense that was not imported before as an unpaid bill in
+// the statement's currency.
+pub fn import(
+    transactions: &[Transaction],
+    currency: &str,
+    bills: &mut Bills,
+    last_id: &mut u32,
+    rules: &[CategoryRule],
+) -> ImportSummary {
+    let known: HashSet<String> = bills
+        .values()
+        .filter_map(|bill| bill.import_id.clone())
+        .collect();
+    let mut summary = ImportSummary::default();
+    for (transaction, import_id) in transactions.iter().zip(import_ids(transactions)) {
+        if transaction.amount >= Decimal::ZERO {
+            summary.credits_skipped += 1;
+            continue;
+        }
+        if known.contains(&import_id) {
+            summary.duplicates += 1;
+            continue;
+        }
+        let title = if transaction.payee.is_empty() {
+            &transaction.memo
+        } else {
+            &transaction.payee
+        };
+        let category = categorize(rules, &transaction.payee);
+        let mut bill = Bill::new(
+            next_id(bills, last_id),
+            title,
+            -transaction.amount,
+            transaction.date,
+            category,
+        );
+        bill.currency = currency.to_owned();
+        bill.payee = transaction.payee.clone();
+        bill.notes = transaction.memo.clone();
+        bill.import_id = Some(import_id);
+        bills.insert(bill.id, bill);
+        summary.imported += 1;
+    }
+    summary
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    const BANK_CSV: &str = "\
+Booking date;Text;Amount;Reference
+05.01.2024;REWE Markt 123;-45,30;card
+05.01.2024;REWE Markt 123;-45,30;card
+06.01.2024;Salary ACME;2.500,00;transfer
+07.01.2024;Netflix.com;-12,99;card
+";
+
+    const OFX: &str = "OFXHEADER:100
+<OFX><BANKMSGSRSV1><STMTTRNRS><STMTRS><BANKTRANLIST>
+<STMTTRN>
+<TRNTYPE>DEBIT
+<DTPOSTED>20240105120000[-5:EST]
+<TRNAMT>-45.30
+<FITID>2024010501
+<NAME>REWE Markt 123
+<MEMO>Groceries
+</STMTTRN>
+<STMTTRN><TRNTYPE>CREDIT<DTPOSTED>20240106<TRNAMT>2500.00<FITID>2024010601<NAME>Salary ACME</STMTTRN>
+</BANKTRANLIST></STMTRS></STMTTRNRS></BANKMSGSRSV1></OFX>
+";
+
+    fn german_mapping() -> CsvMapping {
+        CsvMapping {
+            date: Column::from("Booking date"),
+            amount: Column::from("2"),
+            payee: Column::from("text"),
+            memo: Some(Column::from("Reference")),
+            date_format: "%d.%m.%Y".to_owned(),
+            delimiter: b';',
+            decimal_comma: true,
+            expenses_positive: false,
+        }
+    }
+
+    #[test]
+    fn parse_amount_handles_separators_and_parentheses() {
+        assert_eq!(parse_amount("1,234.56", false), Ok(dec!(1234.56)));
+        assert_eq!(parse_amount("1.234,56 €", true), Ok(dec!(1234.56)));
+        assert_eq!(parse_amount("($12.00)", false), Ok(dec!(-12)));
+        assert_eq!(parse_amount("+0.10", false), Ok(dec!(0.1)));
+        assert!(parse_amount("n/a", false).is_err());
+        assert_eq!(parse_amount("1,234,567", false), Ok(dec!(1234567)));
+        assert_eq!(parse_amount("1,5", true), Ok(dec!(1.5)));
+        for ambiguous in ["1,5", "12,50", "1,2345", "1,23.4", "1,,234"] {
+            assert!(
+                parse_amount(ambiguous, false)
+                    .unwrap_err()
+                    .contains("--decimal-comma"),
+                "{ambiguous}"
+            );
+        }
+    }
+
+    #[test]
+    fn csv_columns_are_mapped_by_name_or_index() {
+        let transactions = parse_csv(BANK_CSV.as_bytes(), &german_mapping()).unwrap();
+        assert_eq!(transactions.len(), 4);
+        assert_eq!(
+            transactions[0].date,
+            NaiveDate::from_ymd_opt(2024, 1, 5).unwrap()
+        );
+        assert_eq!(transactions[0].amount, dec!(-45.3));
+        assert_eq!(transactions[2].amount, dec!(2500));
+        assert_eq!(transactions[3].memo, "card");
+    }
+
+    #[test]
+    fn csv_errors_name_the_line() {
+        let mapping = CsvMapping::default();
+        let err =
+            parse_csv(b"Date,Amount,Description\n2024-01-05,abc,Shop\n", &mapping).unwrap_err();
+        assert!(err.starts_with("Line 2:"), "{err}");
+        assert!(parse_csv(b"When,Amount,Description\n", &mapping)
+            .unwrap_err()
+            .contains("'Date'"));
+    }
+
+    #[test]
+    fn ofx_transactions_are_read_with_and_without_closing_tags() {
+        let transactions = parse_ofx(OFX).unwrap();
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(transactions[0].payee, "REWE Markt 123");
+        assert_eq!(transactions[0].memo, "Groceries");
+        assert_eq!(transactions[0].bank_id.as_deref(), Some("2024010501"));
+        assert_eq!(transactions[1].amount, dec!(2500));
+        assert!(parse_ofx("Date,Amount").is_err());
+    }
+
+    #[test]
+    fn importing_twice_skips_duplicates_and_applies_rules() {
+        let rules = vec![
+            CategoryRule::new("rewe", "Groceries"),
+            CategoryRule::new("NETFLIX", "Subscriptions"),
+        ];
+        let transactions = parse_csv(BANK_CSV.as_bytes(), &german_mapping()).unwrap();
+        let mut bills = Bills::new();
+        let mut last_id = 0;
+        let summary = import(&transactions, "EUR", &mut bills, &mut last_id, &rules);
+        assert_eq!(
+            summary,
+            ImportSummary {
+                imported: 3,
+                duplicates: 0,
+                credits_skipped: 1
+            }
+        );
+        assert_eq!(bills[&1].category, "Groceries");
+        assert_eq!(bills[&3].category, "Subscriptions");
+        assert_eq!(bills[&3].amount, dec!(12.99));
+        assert_eq!(bills[&3].currency, "EUR");
+
+        let again = import(&transactions, "EUR", &mut bills, &mut last_id, &rules);
+        assert_eq!(again.imported, 0);
+        assert_eq!(again.duplicates, 3);
+        assert_eq!(bills.len(), 3);
+    }
+}
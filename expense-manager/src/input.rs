@@ -0,0 +1,130 @@
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+// Typing one of these at any prompt abandons the current operation.
+const CANCEL_WORDS: [&str; 2] = ["cancel", "back"];
+
+#[derive(Debug)]
+pub enum InputError {
+    Cancelled,
+    Eof,
+    Io(io::Error),
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputError::Cancelled => write!(f, "cancelled"),
+            InputError::Eof => write!(f, "no more input"),
+            InputError::Io(err) => write!(f, "input/output error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for InputError {}
+
+impl From<io::Error> for InputError {
+    fn from(err: io::Error) -> Self {
+        InputError::Io(err)
+    }
+}
+
+// Where prompts are written and answers are read from. The interactive program
+// uses stdin/stdout, tests pass in-memory buffers.
+pub struct Console<'a> {
+    input: Box<dyn BufRead + 'a>,
+    output: Box<dyn Write + 'a>,
+}
+
+impl<'a> Console<'a> {
+    pub fn new(input: impl BufRead + 'a, output: impl Write + 'a) -> Self {
+        Self {
+            input: Box::new(input),
+            output: Box::new(output),
+        }
+    }
+
+    pub fn stdio() -> Console<'static> {
+        Console::new(io::stdin().lock(), io::stdout())
+    }
+
+    pub fn say(&mut self, message: impl fmt::Display) -> Result<(), InputError> {
+        writeln!(self.output, "{message}")?;
+        Ok(())
+    }
+
+    fn read_line(&mut self) -> Result<String, InputError> {
+        self.output.flush()?;
+        let mut buffer = String::new();
+        if self.input.read_line(&mut buffer)? == 0 {
+            return Err(InputError::Eof);
+        }
+        Ok(buffer.trim().to_owned())
+    }
+
+    // Asks until `parse` accepts the answer, showing its error message after
+    // every rejected attempt.
+    pub fn prompt_with<T>(
+        &mut self,
+        message: &str,
+        parse: impl Fn(&str) -> Result<T, String>,
+    ) -> Result<T, InputError> {
+        loop {
+            self.say(message)?;
+            let answer = self.read_line()?;
+            if CANCEL_WORDS.contains(&answer.to_lowercase().as_str()) {
+                return Err(InputError::Cancelled);
+            }
+            match parse(&answer) {
+                Ok(value) => return Ok(value),
+                Err(reason) => {
+                    self.say(format!("{reason}, please try again (or type 'cancel')"))?
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse_number(answer: &str) -> Result<f64, String> {
+        answer
+            .parse::<f64>()
+            .map_err(|_| format!("'{answer}' is not valid"))
+    }
+
+    #[test]
+    fn prompt_with_re_asks_until_valid() {
+        let mut output = Vec::new();
+        let mut console = Console::new("abc\n\n12.5\n".as_bytes(), &mut output);
+        let amount = console.prompt_with("Amount: ", parse_number).unwrap();
+        drop(console);
+        assert_eq!(amount, 12.5);
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.matches("Amount: ").count(), 3);
+        assert!(output.contains("'abc' is not valid"));
+    }
+
+    #[test]
+    fn cancel_words_abort_the_prompt() {
+        for word in ["cancel", "BACK"] {
+            let line = format!("{word}\n");
+            let mut console = Console::new(line.as_bytes(), io::sink());
+            assert!(matches!(
+                console.prompt_with("Choice: ", parse_number),
+                Err(InputError::Cancelled)
+            ));
+        }
+    }
+
+    #[test]
+    fn end_of_input_is_reported() {
+        let mut console = Console::new("".as_bytes(), io::sink());
+        assert!(matches!(
+            console.prompt_with("Title: ", parse_number),
+            Err(InputError::Eof)
+        ));
+    }
+}
@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+
+use crate::attachment::Attachment;
+use crate::bill::{self, Bill, Bills};
+use crate::budget::{self, Budget, BudgetStatus};
+use crate::currency::{self, default_currency, ExchangeRate};
+use crate::forecast::{Goal, Income};
+use crate::import::CategoryRule;
+use crate::recurring::RecurringBill;
+use crate::split::{Payment, Share};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Settings {
+    // Totals, reports and budgets are in this currency.
+    #[serde(default = "default_currency")]
+    pub base_currency: String,
+    // The last bill id handed out, see `bill::next_id`. Files from before
+    // it was kept start from their newest bill.
+    #[serde(default)]
+    pub last_bill_id: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            base_currency: default_currency(),
+            last_bill_id: 0,
+        }
+    }
+}
+
+// Everything the expense manager saves to its data file.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ledger {
+    #[serde(with = "bill::as_list")]
+    pub bills: Bills,
+    #[serde(default)]
+    pub recurring: Vec<RecurringBill>,
+    #[serde(default)]
+    pub budgets: Vec<Budget>,
+    #[serde(default)]
+    pub rules: Vec<CategoryRule>,
+    #[serde(default)]
+    pub settings: Settings,
+    #[serde(default)]
+    pub rates: Vec<ExchangeRate>,
+    #[serde(default)]
+    pub splits: Vec<Share>,
+    #[serde(default)]
+    pub payments: Vec<Payment>,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    #[serde(default)]
+    pub income: Vec<Income>,
+    #[serde(default)]
+    pub goals: Vec<Goal>,
+}
+
+impl Ledger {
+    pub fn base_currency(&self) -> &str {
+        &self.settings.base_currency
+    }
+
+    pub fn next_bill_id(&mut self) -> u32 {
+        bill::next_id(&self.bills, &mut self.settings.last_bill_id)
+    }
+
+    // Removes a bill. Its split and attachments are kept, so undoing the
+    // remove brings them back; `attachment gc` forgets the attachments of
+    // bills that stay removed. Bill ids are never reused, so they can't end
+    // up on another bill.
+    pub fn remove_bill(&mut self, id: u32) -> Option<Bill> {
+        self.bills.remove(&id)
+    }
+
+    // The status of the bill's budget, if the bill put its category over
+    // budget for the month.
+    pub fn over_budget(&self, id: u32) -> Result<Option<BudgetStatus>, String> {
+        let Some(bill) = self.bills.get(&id) else {
+            return Ok(None);
+        };
+        budget::exceeded_by(
+            &self.budgets,
+            &self.bills,
+            &self.rates,
+            self.base_currency(),
+            bill,
+        )
+    }
+
+    // The warning to show after adding or editing a bill, if any.
+    pub fn budget_warning(&self, id: u32) -> Option<String> {
+        match self.over_budget(id) {
+            Ok(status) => status.map(|status| status.warning()),
+            Err(err) => Some(format!("Could not check the budget: {err}")),
+        }
+    }
+
+    // The bills with their amounts in the base currency.
+    pub fn bills_in_base(&self) -> Result<Bills, String> {
+        currency::in_base(&self.bills, &self.rates, self.base_currency())
+    }
+}
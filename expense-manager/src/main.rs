@@ -1,32 +1,421 @@
+mod attachment;
+mod bill;
+mod budget;
+mod cli;
+mod crypto;
+mod currency;
+mod forecast;
 mod helper;
+mod history;
+mod import;
+mod input;
+mod ledger;
+mod recurring;
+mod report;
+mod split;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod storage;
+#[cfg(feature = "tui")]
+mod tui;
 
+use bill::Bills;
+use chrono::Days;
+use clap::Parser;
+use cli::Cli;
 use helper::*;
-use std::collections::HashMap;
-
-fn main_options() {
-    println!("1. Add bill");
-    println!("2. View bill");
-    println!("3. Remove bill");
-    println!("4. Update bill");
-    println!("5. Bill total");
-    println!("6. Print options");
+use history::History;
+use input::{Console, InputError};
+use ledger::Ledger;
+use std::io;
+use storage::{StorageError, Store};
+
+const MAIN_OPTIONS: [&str; 17] = [
+    "1. Add bill",
+    "2. View bill",
+    "3. Remove bill",
+    "4. Update bill",
+    "5. Bill total",
+    "6. Mark bill paid",
+    "7. Add recurring bill",
+    "8. View recurring bills",
+    "9. Stop recurring bill",
+    "10. Due soon / overdue",
+    "11. Set category budget",
+    "12. Budget report",
+    "13. Spending report",
+    "14. Undo",
+    "15. Redo",
+    "16. Print options",
+    "17. Exit",
+];
+
+fn main_options(console: &mut Console) -> Result<(), InputError> {
+    for option in MAIN_OPTIONS {
+        console.say(option)?;
+    }
+    Ok(())
 }
 
-fn main() {
-    let mut bills: HashMap<String, Bill> = HashMap::new();
-    println!("== Manage Bills ==");
-    main_options();
+// `saved` holds the bills as last saved, for the change log.
+fn save(
+    console: &mut Console,
+    store: &dyn Store,
+    ledger: &Ledger,
+    saved: &mut Bills,
+) -> Result<(), InputError> {
+    match store.save_with_log(ledger, saved) {
+        Ok(()) => {
+            *saved = ledger.bills.clone();
+            Ok(())
+        }
+        Err(err) => console.say(format!("Could not save bills: {err}")),
+    }
+}
+
+fn parse_choice(input: &str) -> Result<u8, String> {
+    match input.parse::<u8>() {
+        Ok(choice) if (1..=MAIN_OPTIONS.len() as u8).contains(&choice) => Ok(choice),
+        _ => Err(format!("'{input}' is not a menu option")),
+    }
+}
+
+// Turns recurring bills that are due soon into bills. Returns whether any
+// bill was added.
+fn generate_recurring(ledger: &mut Ledger) -> bool {
+    let until = today() + Days::new(DUE_SOON_DAYS);
+    recurring::generate(
+        &mut ledger.recurring,
+        &mut ledger.bills,
+        &mut ledger.settings.last_bill_id,
+        until,
+    ) > 0
+}
+
+// Runs the menu until the user exits or the input ends. Each menu action can
+// be cancelled, which returns to the menu without changing anything.
+fn run(console: &mut Console, store: &dyn Store, ledger: &mut Ledger) -> Result<(), InputError> {
+    console.say("== Manage Bills ==")?;
+    let mut saved = ledger.bills.clone();
+    let mut history = History::default();
+    if generate_recurring(ledger) {
+        save(console, store, ledger, &mut saved)?;
+    }
+    main_options(console)?;
     loop {
-        println!("Enter selection: ");
-        let input: u8 = get_input().unwrap().parse().unwrap();
-        match input {
-            1 => add(&mut bills),
-            2 => view(&bills),
-            3 => remove(&mut bills),
-            4 => update(&mut bills),
-            5 => total_bill(&bills),
-            6 => main_options(),
-            _ => break,
+        let choice = match console.prompt_with("Enter selection: ", parse_choice) {
+            Ok(choice) => choice,
+            Err(InputError::Cancelled) => continue,
+            Err(InputError::Eof) => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        let before = ledger.bills.clone();
+        let result = match choice {
+            1 => add(console, ledger),
+            2 => view(console, ledger),
+            3 => remove(console, ledger),
+            4 => update(console, ledger),
+            5 => total_bill(console, ledger),
+            6 => mark_paid(console, &mut ledger.bills),
+            7 => add_recurring(
+                console,
+                &mut ledger.recurring,
+                &ledger.settings.base_currency,
+            ),
+            8 => view_recurring(console, &ledger.recurring),
+            9 => remove_recurring(console, &mut ledger.recurring),
+            10 => due_soon(console, &ledger.bills),
+            11 => set_budget(console, &mut ledger.budgets),
+            12 => budget_report(console, ledger),
+            13 => spending_report(console, ledger),
+            14 => undo(console, &mut history, ledger),
+            15 => redo(console, &mut history, ledger),
+            16 => main_options(console),
+            _ => return Ok(()),
+        };
+        match result {
+            Ok(()) => {
+                // Undo and redo move through the history instead of adding
+                // to it.
+                if !matches!(choice, 14 | 15) {
+                    history.record(history::diff(&before, &ledger.bills));
+                }
+                let generated = generate_recurring(ledger);
+                if generated || matches!(choice, 1 | 3 | 4 | 6 | 7 | 9 | 11 | 14 | 15) {
+                    save(console, store, ledger, &mut saved)?;
+                }
+            }
+            Err(InputError::Cancelled) => console.say("Cancelled")?,
+            Err(InputError::Eof) => return Ok(()),
+            Err(err) => return Err(err),
         }
     }
 }
+
+fn main() {
+    let cli = Cli::parse();
+    let store = cli::passphrase_for(&cli.location().0, &cli::env_var)
+        .and_then(|passphrase| cli.store(passphrase).map_err(|err| err.to_string()));
+    let mut store = match store {
+        Ok(store) => store,
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    };
+    let interactive = cli.command.is_none();
+    let mut ledger = match store.load() {
+        Ok(ledger) => {
+            if interactive {
+                println!(
+                    "Loaded {} bills from {}",
+                    ledger.bills.len(),
+                    store.path().display()
+                );
+            }
+            ledger
+        }
+        Err(StorageError::NotFound(path)) => {
+            if interactive {
+                println!("No bills saved at {} yet, starting fresh", path.display());
+            }
+            Ledger::default()
+        }
+        Err(err) => {
+            eprintln!("Could not load bills: {err}");
+            std::process::exit(1);
+        }
+    };
+
+    let Some(command) = cli.command else {
+        if let Err(err) = run(&mut Console::stdio(), store.as_ref(), &mut ledger) {
+            eprintln!("Stopped: {err}");
+            std::process::exit(1);
+        }
+        return;
+    };
+    #[cfg(feature = "tui")]
+    if let cli::Command::Tui = command {
+        if let Err(err) = tui::run(store.as_ref(), ledger) {
+            eprintln!("Stopped: {err}");
+            std::process::exit(1);
+        }
+        return;
+    }
+    let saved = ledger.bills.clone();
+    let generated = generate_recurring(&mut ledger);
+    match cli::execute(
+        command,
+        &mut ledger,
+        store.as_mut(),
+        cli.json,
+        &mut io::stdout(),
+        &mut io::stderr(),
+        &cli::env_var,
+    ) {
+        Ok(changed) => {
+            if changed || generated {
+                if let Err(err) = store.save_with_log(&ledger, &saved) {
+                    eprintln!("Could not save bills: {err}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bill::Bill;
+    use crate::*;
+    use rust_decimal_macros::dec;
+
+    fn run_script(script: &str, ledger: &mut Ledger) -> String {
+        let dir = tempfile::tempdir().unwrap();
+        let store = storage::FileStore::new(dir.path().join("bills.json"), storage::Format::Json);
+        let mut output = Vec::new();
+        let mut console = Console::new(script.as_bytes(), &mut output);
+        run(&mut console, &store, ledger).unwrap();
+        drop(console);
+        String::from_utf8(output).unwrap()
+    }
+
+    fn rent(id: u32, date: &str) -> Bill {
+        Bill::new(
+            id,
+            "Rent",
+            dec!(1200),
+            bill::parse_date(date).unwrap(),
+            "Housing",
+        )
+    }
+
+    #[test]
+    fn typos_are_re_asked_instead_of_panicking() {
+        let mut ledger = Ledger::default();
+        let script =
+            "one\n1\nRent\nlots\n-5\n1200\neuro\neur\n2024-13-01\n2024-01-01\nHousing\n\n\nmaybe\ny\n";
+        let output = run_script(script, &mut ledger);
+        assert_eq!(ledger.bills[&1].amount, dec!(1200));
+        assert_eq!(ledger.bills[&1].currency, "EUR");
+        assert!(ledger.bills[&1].paid);
+        assert!(output.contains("'one' is not a menu option"));
+        assert!(output.contains("'lots' is not a valid amount"));
+        assert!(output.contains("'-5' is not a valid amount"));
+        assert!(output.contains("'euro' is not a currency code"));
+        assert!(output.contains("'2024-13-01' is not a date"));
+        assert!(output.contains("'maybe' is not y or n"));
+    }
+
+    #[test]
+    fn update_picks_a_bill_by_id_from_the_filtered_list() {
+        let mut ledger = Ledger::default();
+        ledger.bills.insert(1, rent(1, "2024-01-01"));
+        ledger.bills.insert(2, rent(2, "2024-02-01"));
+        let script = "4\n2024-01-15\n\nhousing\n1\n2\n\n1300\n\n\n\nLandlord\n\n\n";
+        let output = run_script(script, &mut ledger);
+        assert!(output.contains("'1' is not one of the listed ids"));
+        assert_eq!(ledger.bills[&1], rent(1, "2024-01-01"));
+        assert_eq!(ledger.bills[&2].amount, dec!(1300));
+        assert_eq!(ledger.bills[&2].payee, "Landlord");
+        assert_eq!(ledger.bills[&2].title, "Rent");
+    }
+
+    #[test]
+    fn cancel_returns_to_the_menu_without_changes() {
+        let mut ledger = Ledger::default();
+        ledger.bills.insert(1, rent(1, "2024-01-01"));
+        let output = run_script("4\n\n\n\n1\n\nback\n3\ncancel\n5\n", &mut ledger);
+        assert_eq!(ledger.bills[&1], rent(1, "2024-01-01"));
+        assert_eq!(output.matches("Cancelled").count(), 2);
+        assert!(output.contains("Total bill: 1200"));
+    }
+
+    #[test]
+    fn cancelling_an_add_does_not_use_up_an_id() {
+        let mut ledger = Ledger::default();
+        let script = "1\nRent\n1200\n\n2024-01-01\nHousing\nLandlord\ncancel\n\
+                      1\nGym\n30\n\n2024-01-02\nHealth\n\n\n\n";
+        let output = run_script(script, &mut ledger);
+        assert!(output.contains("Cancelled"));
+        assert_eq!(ledger.bills.len(), 1);
+        assert_eq!(ledger.bills[&1].title, "Gym");
+    }
+
+    #[test]
+    fn recurring_instances_are_paid_one_at_a_time() {
+        let mut ledger = Ledger::default();
+        let script = "7\nGym\n30\n\nHealth\n\nweekly\n\n6\n\n\n\n1\n10\n";
+        let output = run_script(script, &mut ledger);
+        assert_eq!(
+            ledger.bills.len(),
+            2,
+            "today and a week from today are due soon"
+        );
+        assert!(ledger.bills[&1].paid);
+        assert!(!ledger.bills[&2].paid);
+        assert_eq!(ledger.bills[&2].recurring_id, Some(1));
+        assert_eq!(output.matches("due soon #").count(), 1);
+    }
+
+    #[test]
+    fn old_recurring_bills_ask_before_adding_past_bills_and_can_be_stopped() {
+        let mut ledger = Ledger::default();
+        let script = "7\nGym\n30\n\nHealth\n\nevery:1\n2000-01-01\nmaybe\nn\n9\n2\n1\n";
+        let output = run_script(script, &mut ledger);
+        assert!(output.contains("'maybe' is not y or n"));
+        assert!(output.contains("'2' is not one of the listed ids"));
+        assert!(output.contains("Stopped #1 Gym"));
+        assert!(ledger.recurring.is_empty());
+        // Only today and the week ahead, not every day since 2000.
+        assert_eq!(ledger.bills.len(), 8);
+        assert!(ledger.bills.values().all(|bill| bill.date >= today()));
+
+        let output = run_script("9\n", &mut ledger);
+        assert!(output.contains("No recurring bills"));
+        assert_eq!(ledger.bills.len(), 8, "the added bills are kept");
+    }
+
+    #[test]
+    fn undo_and_redo_a_remove() {
+        let mut ledger = Ledger::default();
+        ledger.bills.insert(1, rent(1, "2024-01-01"));
+        let output = run_script("3\n\n\n\n1\n14\n15\n14\n14\n15\n15\n", &mut ledger);
+        assert!(ledger.bills.is_empty());
+        assert_eq!(output.matches("Undo: added #1").count(), 2);
+        assert_eq!(output.matches("Redo: removed #1").count(), 2);
+        assert!(output.contains("Nothing to undo"));
+        assert!(output.contains("Nothing to redo"));
+    }
+
+    #[test]
+    fn undoing_a_remove_brings_back_the_split_and_attachment() {
+        let mut ledger = Ledger::default();
+        ledger.bills.insert(1, rent(1, "2024-01-01"));
+        let shares = split::shares(
+            &ledger.bills[&1],
+            "alice",
+            split::Method::Equal,
+            vec![("bob".to_owned(), dec!(0))],
+        )
+        .unwrap();
+        split::set(&mut ledger.splits, 1, shares);
+        let receipt = attachment::Attachment {
+            bill_id: 1,
+            hash: "ab".repeat(32),
+            name: "receipt.pdf".to_owned(),
+            size: 4,
+        };
+        ledger.attachments.push(receipt.clone());
+        let before = ledger.clone();
+
+        let output = run_script("3\n\n\n\n1\n14\n", &mut ledger);
+        assert!(output.contains("Undo: added #1"));
+        assert_eq!(ledger.bills, before.bills);
+        assert_eq!(ledger.splits, before.splits);
+        assert_eq!(ledger.attachments, vec![receipt]);
+    }
+
+    #[test]
+    fn due_soon_flags_overdue_bills() {
+        let mut ledger = Ledger::default();
+        ledger.bills.insert(1, rent(1, "2024-01-01"));
+        let output = run_script("10\n", &mut ledger);
+        assert!(output.contains("OVERDUE #1 2024-01-01 Rent"));
+    }
+
+    #[test]
+    fn adding_a_bill_over_budget_warns() {
+        let mut ledger = Ledger::default();
+        let script = "11\nFood\n100\n1\nGroceries\n80\n\n2024-03-01\nFood\n\n\n\n1\nDinner\n45\n\n2024-03-09\nfood\n\n\n\n12\n2024-03\n";
+        let output = run_script(script, &mut ledger);
+        assert_eq!(ledger.budgets.len(), 1);
+        assert_eq!(output.matches("Warning:").count(), 1);
+        assert!(output.contains("Food is over its monthly budget of 100.00 by 25.00 (125% used)"));
+        assert!(output.contains("OVER BUDGET"));
+    }
+
+    #[test]
+    fn spending_report_shows_a_table_and_chart() {
+        let mut ledger = Ledger::default();
+        ledger.bills.insert(1, rent(1, "2024-01-01"));
+        ledger.bills.insert(2, rent(2, "2024-03-01"));
+        let output = run_script(
+            "13
+yearly
+trend
+
+
+
+",
+            &mut ledger,
+        );
+        assert!(output.contains("'yearly' is not category, month, payee, trend or top"));
+        assert!(output.contains("2024-02      0     0.00  -100.0%"));
+        assert!(output.contains(&format!("2024-03 |{} 1200.00", "#".repeat(40))));
+    }
+}
@@ -0,0 +1,342 @@
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{Datelike, Days, Months, NaiveDate};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::bill::{next_id, Bill, Bills, DATE_FORMAT};
+use crate::currency::{self, default_currency, DEFAULT_CURRENCY};
+
+// How often a recurring bill comes due. Stored as text such as "monthly:15"
+// so it fits in a single CSV column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Rule {
+    Weekly,
+    // Day of the month, moved to the last day in shorter months.
+    Monthly(u32),
+    Yearly,
+    EveryDays(u32),
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rule::Weekly => write!(f, "weekly"),
+            Rule::Monthly(day) => write!(f, "monthly:{day}"),
+            Rule::Yearly => write!(f, "yearly"),
+            Rule::EveryDays(days) => write!(f, "every:{days}"),
+        }
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("'{s}' is not weekly, monthly:<day>, yearly or every:<days>");
+        let lower = s.trim().to_lowercase();
+        let (kind, value) = match lower.split_once(':') {
+            Some((kind, value)) => (kind, Some(value.parse::<u32>().map_err(|_| invalid())?)),
+            None => (lower.as_str(), None),
+        };
+        match (kind, value) {
+            ("weekly", None) => Ok(Rule::Weekly),
+            ("monthly", Some(day)) if (1..=31).contains(&day) => Ok(Rule::Monthly(day)),
+            ("yearly", None) => Ok(Rule::Yearly),
+            ("every", Some(days)) if days > 0 => Ok(Rule::EveryDays(days)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl TryFrom<String> for Rule {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Rule> for String {
+    fn from(rule: Rule) -> Self {
+        rule.to_string()
+    }
+}
+
+fn last_day_of_month(year: i32, month: u32) -> u32 {
+    let first = NaiveDate::from_ymd_opt(year, month, 1).expect("valid month");
+    let next = first + Months::new(1);
+    next.pred_opt()
+        .expect("date after the first of a month")
+        .day()
+}
+
+impl Rule {
+    // The occurrence following `date`, which must itself be an occurrence.
+    fn next_after(&self, date: NaiveDate, start: NaiveDate) -> NaiveDate {
+        match self {
+            Rule::Weekly => date + Days::new(7),
+            Rule::EveryDays(days) => date + Days::new(u64::from(*days)),
+            Rule::Monthly(day) => on_day(date.with_day(1).unwrap() + Months::new(1), *day),
+            // Anchored on the start date so a 29 February start comes back
+            // on 29 February in leap years.
+            Rule::Yearly => {
+                let years = (date.year() - start.year() + 1) as u32;
+                start + Months::new(12 * years)
+            }
+        }
+    }
+
+    // The first occurrence on or after `start`.
+    fn first(&self, start: NaiveDate) -> NaiveDate {
+        match self {
+            Rule::Monthly(day) => {
+                let this_month = on_day(start, *day);
+                if this_month >= start {
+                    this_month
+                } else {
+                    on_day(start.with_day(1).unwrap() + Months::new(1), *day)
+                }
+            }
+            _ => start,
+        }
+    }
+
+    // Occurrences of a schedule that began on `start`, between `from` and
+    // `until` inclusive.
+    pub fn occurrences(
+        &self,
+        start: NaiveDate,
+        from: NaiveDate,
+        until: NaiveDate,
+    ) -> Vec<NaiveDate> {
+        let mut dates = Vec::new();
+        let mut date = self.first(start);
+        while date <= until {
+            if date >= from {
+                dates.push(date);
+            }
+            date = self.next_after(date, start);
+        }
+        dates
+    }
+}
+
+fn on_day(date: NaiveDate, day: u32) -> NaiveDate {
+    let day = day.min(last_day_of_month(date.year(), date.month()));
+    date.with_day(day).expect("day clamped to the month")
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecurringBill {
+    pub id: u32,
+    pub title: String,
+    pub amount: Decimal,
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    pub category: String,
+    pub payee: String,
+    pub rule: Rule,
+    pub start: NaiveDate,
+    // First occurrence that has not been turned into a bill yet.
+    pub next_due: NaiveDate,
+}
+
+impl RecurringBill {
+    pub fn new(
+        id: u32,
+        title: &str,
+        amount: Decimal,
+        category: &str,
+        rule: Rule,
+        start: NaiveDate,
+    ) -> Self {
+        Self {
+            id,
+            title: title.to_owned(),
+            amount,
+            currency: DEFAULT_CURRENCY.to_owned(),
+            category: category.to_owned(),
+            payee: String::new(),
+            rule,
+            start,
+            next_due: rule.first(start),
+        }
+    }
+
+    // Occurrences from `next_due` up to and including `until`.
+    pub fn upcoming(&self, until: NaiveDate) -> Vec<NaiveDate> {
+        let mut dates = Vec::new();
+        let mut date = self.next_due;
+        while date <= until {
+            dates.push(date);
+            date = self.rule.next_after(date, self.start);
+        }
+        dates
+    }
+
+    // Moves `next_due` to the first occurrence on or after `date`, so the
+    // occurrences before it never become bills.
+    pub fn skip_to(&mut self, date: NaiveDate) {
+        while self.next_due < date {
+            self.next_due = self.rule.next_after(self.next_due, self.start);
+        }
+    }
+
+    fn instance(&self, id: u32, date: NaiveDate) -> Bill {
+        let mut bill = Bill::new(id, &self.title, self.amount, date, &self.category);
+        bill.currency = self.currency.clone();
+        bill.payee = self.payee.clone();
+        bill.recurring_id = Some(self.id);
+        bill
+    }
+}
+
+impl fmt::Display for RecurringBill {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "#{} {} {:.2} {} [{}] {} from {}, next due {}",
+            self.id,
+            self.title,
+            currency::round(self.amount),
+            self.currency,
+            self.category,
+            self.rule,
+            self.start.format(DATE_FORMAT),
+            self.next_due.format(DATE_FORMAT)
+        )
+    }
+}
+
+pub fn next_recurring_id(recurring: &[RecurringBill]) -> u32 {
+    recurring
+        .iter()
+        .map(|rule| rule.id)
+        .max()
+        .map_or(1, |id| id + 1)
+}
+
+// Stops recurring bill `id`. The bills it already added are kept.
+pub fn remove(recurring: &mut Vec<RecurringBill>, id: u32) -> Option<RecurringBill> {
+    let position = recurring.iter().position(|rule| rule.id == id)?;
+    Some(recurring.remove(position))
+}
+
+// Adds a separate bill for every occurrence due by `until`, so paying one
+// instance never touches the others. Returns how many bills were added.
+pub fn generate(
+    recurring: &mut [RecurringBill],
+    bills: &mut Bills,
+    last_id: &mut u32,
+    until: NaiveDate,
+) -> usize {
+    let mut added = 0;
+    for rule in recurring.iter_mut() {
+        for date in rule.upcoming(until) {
+            let bill = rule.instance(next_id(bills, last_id), date);
+            bills.insert(bill.id, bill);
+            rule.next_due = rule.rule.next_after(date, rule.start);
+            added += 1;
+        }
+    }
+    added
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bill::parse_date;
+    use rust_decimal_macros::dec;
+
+    fn date(input: &str) -> NaiveDate {
+        parse_date(input).unwrap()
+    }
+
+    fn dates(rule: &str, start: &str, until: &str) -> Vec<String> {
+        RecurringBill::new(
+            1,
+            "Bill",
+            dec!(1),
+            "test",
+            rule.parse().unwrap(),
+            date(start),
+        )
+        .upcoming(date(until))
+        .iter()
+        .map(|date| date.to_string())
+        .collect()
+    }
+
+    #[test]
+    fn rules_round_trip_through_text() {
+        for text in ["weekly", "monthly:31", "yearly", "every:10"] {
+            assert_eq!(text.parse::<Rule>().unwrap().to_string(), text);
+        }
+        assert!("monthly:32".parse::<Rule>().is_err());
+        assert!("every:0".parse::<Rule>().is_err());
+        assert!("fortnightly".parse::<Rule>().is_err());
+    }
+
+    #[test]
+    fn monthly_rule_clamps_to_short_months() {
+        assert_eq!(
+            dates("monthly:31", "2024-01-15", "2024-04-30"),
+            vec!["2024-01-31", "2024-02-29", "2024-03-31", "2024-04-30"]
+        );
+        assert_eq!(
+            dates("monthly:5", "2024-01-15", "2024-03-10"),
+            vec!["2024-02-05", "2024-03-05"]
+        );
+    }
+
+    #[test]
+    fn weekly_yearly_and_every_n_days() {
+        assert_eq!(
+            dates("weekly", "2024-01-01", "2024-01-15"),
+            vec!["2024-01-01", "2024-01-08", "2024-01-15"]
+        );
+        assert_eq!(
+            dates("yearly", "2024-02-29", "2028-03-01"),
+            vec![
+                "2024-02-29",
+                "2025-02-28",
+                "2026-02-28",
+                "2027-02-28",
+                "2028-02-29"
+            ]
+        );
+        assert_eq!(
+            dates("every:10", "2024-01-01", "2024-01-25"),
+            vec!["2024-01-01", "2024-01-11", "2024-01-21"]
+        );
+    }
+
+    #[test]
+    fn generated_instances_are_independent_bills() {
+        let mut recurring = vec![RecurringBill::new(
+            1,
+            "Rent",
+            dec!(1200),
+            "Housing",
+            Rule::Monthly(1),
+            date("2024-01-01"),
+        )];
+        let mut bills = Bills::new();
+        let mut last_id = 0;
+        let mut generate =
+            |bills: &mut Bills, until| generate(&mut recurring, bills, &mut last_id, date(until));
+        assert_eq!(generate(&mut bills, "2024-02-15"), 2);
+        bills.get_mut(&1).unwrap().paid = true;
+
+        assert_eq!(generate(&mut bills, "2024-02-15"), 0);
+        assert_eq!(generate(&mut bills, "2024-03-01"), 1);
+        assert_eq!(recurring[0].next_due, date("2024-04-01"));
+        assert!(bills[&1].paid);
+        assert!(!bills[&2].paid && !bills[&3].paid);
+        assert_eq!(bills[&3].date, date("2024-03-01"));
+        assert_eq!(bills[&3].recurring_id, Some(1));
+    }
+}
@@ -0,0 +1,412 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::str::FromStr;
+
+use chrono::{Datelike, Months, NaiveDate};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+
+use crate::bill::{Bill, DATE_FORMAT};
+
+const BAR_WIDTH: usize = 40;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Grouping {
+    Category,
+    Month,
+    Payee,
+    // Months including empty ones, with the change against the month before.
+    Trend,
+    // The largest single bills.
+    Top,
+}
+
+impl FromStr for Grouping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "category" => Ok(Grouping::Category),
+            "month" => Ok(Grouping::Month),
+            "payee" => Ok(Grouping::Payee),
+            "trend" => Ok(Grouping::Trend),
+            "top" => Ok(Grouping::Top),
+            _ => Err(format!("'{s}' is not category, month, payee, trend or top")),
+        }
+    }
+}
+
+// A table independent of how it is rendered. Every row also has a label
+// and a value for the bar chart, which only needs to be roughly right.
+#[derive(Debug, PartialEq)]
+pub struct Report {
+    pub title: String,
+    pub headers: Vec<&'static str>,
+    pub rows: Vec<Vec<String>>,
+    pub bars: Vec<(String, f64)>,
+}
+
+fn month_label(date: NaiveDate) -> String {
+    date.format("%Y-%m").to_string()
+}
+
+fn first_of_month(date: NaiveDate) -> NaiveDate {
+    date.with_day(1).expect("every month has a first day")
+}
+
+// Totals and bill counts per label.
+fn totals<'a>(
+    bills: &[&'a Bill],
+    label: impl Fn(&'a Bill) -> String,
+) -> BTreeMap<String, (Decimal, usize)> {
+    let mut totals = BTreeMap::new();
+    for bill in bills {
+        let entry = totals.entry(label(bill)).or_insert((Decimal::ZERO, 0));
+        entry.0 += bill.amount;
+        entry.1 += 1;
+    }
+    totals
+}
+
+fn bar(value: Decimal) -> f64 {
+    value.to_f64().unwrap_or_default()
+}
+
+fn share(amount: Decimal, total: Decimal) -> String {
+    if total > Decimal::ZERO {
+        format!(
+            "{:.1}%",
+            (amount / total * Decimal::ONE_HUNDRED).round_dp(1)
+        )
+    } else {
+        "-".to_owned()
+    }
+}
+
+fn grouped(bills: &[&Bill], grouping: Grouping, limit: usize) -> Report {
+    let (title, header) = match grouping {
+        Grouping::Category => ("Spending by category", "Category"),
+        Grouping::Payee => ("Spending by payee", "Payee"),
+        _ => ("Spending by month", "Month"),
+    };
+    let totals = totals(bills, |bill| match grouping {
+        Grouping::Category => bill.category.clone(),
+        Grouping::Payee if bill.payee.is_empty() => "(no payee)".to_owned(),
+        Grouping::Payee => bill.payee.clone(),
+        _ => month_label(bill.date),
+    });
+    let grand_total: Decimal = totals.values().map(|(total, _)| total).sum();
+    let mut groups: Vec<_> = totals.into_iter().collect();
+    // Months read best in order, everything else largest first.
+    if grouping != Grouping::Month {
+        groups.sort_by(|a, b| b.1 .0.cmp(&a.1 .0).then_with(|| a.0.cmp(&b.0)));
+        groups.truncate(limit);
+    }
+    Report {
+        title: title.to_owned(),
+        headers: vec![header, "Bills", "Total", "Share"],
+        rows: groups
+            .iter()
+            .map(|(label, (total, count))| {
+                vec![
+                    label.clone(),
+                    count.to_string(),
+                    format!("{total:.2}"),
+                    share(*total, grand_total),
+                ]
+            })
+            .collect(),
+        bars: groups
+            .into_iter()
+            .map(|(label, (total, _))| (label, bar(total)))
+            .collect(),
+    }
+}
+
+fn trend(bills: &[&Bill]) -> Report {
+    let mut report = Report {
+        title: "Month-over-month trend".to_owned(),
+        headers: vec!["Month", "Bills", "Total", "Change"],
+        rows: Vec::new(),
+        bars: Vec::new(),
+    };
+    let (Some(first), Some(last)) = (
+        bills.iter().map(|bill| bill.date).min(),
+        bills.iter().map(|bill| bill.date).max(),
+    ) else {
+        return report;
+    };
+    let totals = totals(bills, |bill| month_label(bill.date));
+    let mut previous: Option<Decimal> = None;
+    let mut month = first_of_month(first);
+    while month <= last {
+        let label = month_label(month);
+        let (total, count) = totals.get(&label).copied().unwrap_or((Decimal::ZERO, 0));
+        let change = match previous {
+            Some(previous) if previous > Decimal::ZERO => {
+                format!(
+                    "{:+.1}%",
+                    ((total - previous) / previous * Decimal::ONE_HUNDRED).round_dp(1)
+                )
+            }
+            _ => "-".to_owned(),
+        };
+        report.rows.push(vec![
+            label.clone(),
+            count.to_string(),
+            format!("{total:.2}"),
+            change,
+        ]);
+        report.bars.push((label, bar(total)));
+        previous = Some(total);
+        month = month + Months::new(1);
+    }
+    report
+}
+
+fn top(bills: &[&Bill], limit: usize) -> Report {
+    let mut largest = bills.to_vec();
+    largest.sort_by(|a, b| b.amount.cmp(&a.amount).then(a.id.cmp(&b.id)));
+    largest.truncate(limit);
+    Report {
+        title: format!("Top {limit} expenses"),
+        headers: vec!["Id", "Date", "Title", "Category", "Amount"],
+        rows: largest
+            .iter()
+            .map(|bill| {
+                vec![
+                    bill.id.to_string(),
+                    bill.date.format(DATE_FORMAT).to_string(),
+                    bill.title.clone(),
+                    bill.category.clone(),
+                    format!("{:.2}", bill.amount),
+                ]
+            })
+            .collect(),
+        bars: largest
+            .iter()
+            .map(|bill| (format!("#{} {}", bill.id, bill.title), bar(bill.amount)))
+            .collect(),
+    }
+}
+
+// `limit` caps the rows of every grouping except month and trend, which
+// always cover the whole range.
+pub fn build(bills: &[&Bill], grouping: Grouping, limit: usize) -> Report {
+    match grouping {
+        Grouping::Trend => trend(bills),
+        Grouping::Top => top(bills, limit),
+        _ => grouped(bills, grouping, limit),
+    }
+}
+
+fn is_numeric(cell: &str) -> bool {
+    cell == "-" || cell.trim_end_matches('%').parse::<f64>().is_ok()
+}
+
+pub fn table(report: &Report) -> String {
+    let mut widths: Vec<usize> = report.headers.iter().map(|header| header.len()).collect();
+    for row in &report.rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    // Numbers, and the headers above them, are aligned right.
+    let right: Vec<bool> = (0..widths.len())
+        .map(|column| {
+            !report.rows.is_empty() && report.rows.iter().all(|row| is_numeric(&row[column]))
+        })
+        .collect();
+    let line = |cells: Vec<&str>| {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .zip(&right)
+            .map(|((cell, &width), &right)| {
+                if right {
+                    format!("{cell:>width$}")
+                } else {
+                    format!("{cell:<width$}")
+                }
+            })
+            .collect();
+        padded.join("  ").trim_end().to_owned()
+    };
+    let mut out = format!("{}\n", report.title);
+    out += &line(report.headers.clone());
+    out.push('\n');
+    out += &widths
+        .iter()
+        .map(|width| "-".repeat(*width))
+        .collect::<Vec<_>>()
+        .join("  ");
+    out.push('\n');
+    for row in &report.rows {
+        out += &line(row.iter().map(String::as_str).collect());
+        out.push('\n');
+    }
+    out
+}
+
+pub fn chart(report: &Report) -> String {
+    let max = report
+        .bars
+        .iter()
+        .map(|(_, value)| *value)
+        .fold(0.0, f64::max);
+    let label_width = report
+        .bars
+        .iter()
+        .map(|(label, _)| label.chars().count())
+        .max()
+        .unwrap_or(0);
+    let mut out = String::new();
+    for (label, value) in &report.bars {
+        let length = if max > 0.0 {
+            (value / max * BAR_WIDTH as f64).round() as usize
+        } else {
+            0
+        };
+        let _ = writeln!(
+            out,
+            "{label:<label_width$} |{:<BAR_WIDTH$} {value:.2}",
+            "#".repeat(length)
+        );
+    }
+    out
+}
+
+pub fn to_csv(report: &Report) -> Result<String, String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record(&report.headers)
+        .map_err(|err| err.to_string())?;
+    for row in &report.rows {
+        writer.write_record(row).map_err(|err| err.to_string())?;
+    }
+    let data = writer.into_inner().map_err(|err| err.to_string())?;
+    String::from_utf8(data).map_err(|err| err.to_string())
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// A standalone page with the table and a bar per row.
+pub fn to_html(report: &Report) -> String {
+    let title = escape_html(&report.title);
+    let max = report
+        .bars
+        .iter()
+        .map(|(_, value)| *value)
+        .fold(0.0, f64::max);
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+         <style>td, th {{ padding: 2px 8px; }} .bar {{ background: #4a7; height: 1em; }}</style>\n\
+         </head>\n<body>\n<h1>{title}</h1>\n<table>\n<tr>"
+    );
+    for header in &report.headers {
+        let _ = write!(out, "<th>{}</th>", escape_html(header));
+    }
+    out += "<th></th></tr>\n";
+    for (row, (_, value)) in report.rows.iter().zip(&report.bars) {
+        out += "<tr>";
+        for cell in row {
+            let _ = write!(out, "<td>{}</td>", escape_html(cell));
+        }
+        let percent = if max > 0.0 { value / max * 100.0 } else { 0.0 };
+        let _ = writeln!(
+            out,
+            "<td style=\"width: 200px\"><div class=\"bar\" style=\"width: {percent:.0}%\"></div></td></tr>"
+        );
+    }
+    out += "</table>\n</body>\n</html>\n";
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bill::parse_date;
+    use rust_decimal_macros::dec;
+
+    fn bills() -> Vec<Bill> {
+        let bill = |id, title, amount, date, category, payee: &str| {
+            let mut bill = Bill::new(id, title, amount, parse_date(date).unwrap(), category);
+            bill.payee = payee.to_owned();
+            bill
+        };
+        vec![
+            bill(1, "Rent", dec!(1000), "2024-01-01", "Housing", "Landlord"),
+            bill(2, "Groceries", dec!(150), "2024-01-10", "Food", "Market"),
+            bill(3, "Groceries", dec!(50), "2024-03-10", "Food", "Market"),
+            bill(4, "Rent", dec!(1000), "2024-03-01", "Housing", "Landlord"),
+            bill(5, "Cinema", dec!(25), "2024-03-20", "Fun", ""),
+        ]
+    }
+
+    fn report(grouping: Grouping, limit: usize) -> Report {
+        let bills = bills();
+        let refs: Vec<&Bill> = bills.iter().collect();
+        build(&refs, grouping, limit)
+    }
+
+    #[test]
+    fn groups_are_sorted_by_total_and_limited() {
+        let by_category = report(Grouping::Category, 2);
+        assert_eq!(
+            by_category.rows,
+            vec![
+                vec!["Housing", "2", "2000.00", "89.9%"],
+                vec!["Food", "2", "200.00", "9.0%"],
+            ]
+        );
+        let by_payee = report(Grouping::Payee, 10);
+        assert_eq!(by_payee.bars[2], ("(no payee)".to_owned(), 25.0));
+        let by_month = report(Grouping::Month, 1);
+        assert_eq!(by_month.bars[0], ("2024-01".to_owned(), 1150.0));
+        assert_eq!(by_month.rows.len(), 2);
+    }
+
+    #[test]
+    fn trend_fills_empty_months() {
+        let trend = report(Grouping::Trend, 10);
+        let changes: Vec<&str> = trend.rows.iter().map(|row| row[3].as_str()).collect();
+        assert_eq!(changes, vec!["-", "-100.0%", "-"]);
+        assert_eq!(trend.bars[1], ("2024-02".to_owned(), 0.0));
+        assert!(build(&[], Grouping::Trend, 10).rows.is_empty());
+    }
+
+    #[test]
+    fn top_lists_the_largest_bills() {
+        let top = report(Grouping::Top, 3);
+        let ids: Vec<&str> = top.rows.iter().map(|row| row[0].as_str()).collect();
+        assert_eq!(ids, vec!["1", "4", "2"]);
+    }
+
+    #[test]
+    fn renders_table_chart_csv_and_html() {
+        let report = report(Grouping::Category, 10);
+        let table = table(&report);
+        assert!(table.contains("Category  Bills    Total  Share"));
+        assert!(table.contains("Housing       2  2000.00  89.9%"));
+
+        let chart = chart(&report);
+        let first = chart.lines().next().unwrap();
+        assert_eq!(first.matches('#').count(), BAR_WIDTH);
+        assert!(chart.lines().nth(2).unwrap().starts_with("Fun     |#"));
+
+        assert!(to_csv(&report)
+            .unwrap()
+            .starts_with("Category,Bills,Total,Share\nHousing,2,2000.00,89.9%\n"));
+        let html = to_html(&Report {
+            title: "<Fun & games>".to_owned(),
+            ..report
+        });
+        assert!(html.contains("<h1>&lt;Fun &amp; games&gt;</h1>"));
+        assert!(html.contains("<td>Housing</td>"));
+    }
+}
@@ -0,0 +1,409 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+use chrono::NaiveDate;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+
+use crate::bill::{Bill, Bills, DATE_FORMAT};
+use crate::currency::{self, ExchangeRate};
+
+const CENT: Decimal = Decimal::from_parts(1, 0, 0, false, 2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Method {
+    Equal,
+    Exact,
+    Percent,
+}
+
+// One person's part of a shared bill. The amount owed is worked out from
+// the bill when balances are computed, so equal and percentage splits
+// follow later changes to the bill's amount. Kept flat so it fits a CSV
+// row.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Share {
+    pub bill_id: u32,
+    pub paid_by: String,
+    pub person: String,
+    pub method: Method,
+    // Unused for equal splits, the amount for exact splits and the
+    // percentage for percent splits.
+    pub value: Decimal,
+}
+
+// Money handed from one person to another to settle up, in the base
+// currency.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Payment {
+    pub date: NaiveDate,
+    pub from: String,
+    pub to: String,
+    pub amount: Decimal,
+}
+
+impl fmt::Display for Payment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} paid {} {:.2}",
+            self.date.format(DATE_FORMAT),
+            self.from,
+            self.to,
+            self.amount
+        )
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Transfer {
+    pub from: String,
+    pub to: String,
+    pub amount: Decimal,
+}
+
+impl fmt::Display for Transfer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} pays {} {:.2}", self.from, self.to, self.amount)
+    }
+}
+
+fn parse_name(input: &str) -> Result<String, String> {
+    let name = input.trim();
+    if name.is_empty() {
+        Err("A name can't be empty".to_owned())
+    } else {
+        Ok(name.to_owned())
+    }
+}
+
+// Parses `alice,bob` for an equal split.
+pub fn parse_people(input: &str) -> Result<Vec<String>, String> {
+    input.split(',').map(parse_name).collect()
+}
+
+// Parses `alice=10,bob=20.50` for exact or percent splits.
+pub fn parse_parts(input: &str) -> Result<Vec<(String, Decimal)>, String> {
+    input
+        .split(',')
+        .map(|part| {
+            let (name, value) = part
+                .split_once('=')
+                .ok_or(format!("'{part}' is not like name=amount"))?;
+            let value = value
+                .trim()
+                .parse::<Decimal>()
+                .ok()
+                .filter(|value| *value > Decimal::ZERO)
+                .ok_or(format!("'{value}' is not a positive number"))?;
+            Ok((parse_name(name)?, value))
+        })
+        .collect()
+}
+
+// Checks the parts and turns them into the bill's shares.
+pub fn shares(
+    bill: &Bill,
+    paid_by: &str,
+    method: Method,
+    parts: Vec<(String, Decimal)>,
+) -> Result<Vec<Share>, String> {
+    if parts.is_empty() {
+        return Err("A split needs at least one person".to_owned());
+    }
+    let mut seen = HashSet::new();
+    if let Some((name, _)) = parts
+        .iter()
+        .find(|(name, _)| !seen.insert(name.to_lowercase()))
+    {
+        return Err(format!("{name} is listed twice"));
+    }
+    let sum: Decimal = parts.iter().map(|(_, value)| value).sum();
+    match method {
+        Method::Exact if sum != bill.amount => {
+            return Err(format!(
+                "The amounts add up to {sum:.2}, not {:.2}",
+                bill.amount
+            ))
+        }
+        Method::Percent if sum != Decimal::ONE_HUNDRED => {
+            return Err(format!("The percentages add up to {sum}, not 100"))
+        }
+        _ => {}
+    }
+    Ok(parts
+        .into_iter()
+        .map(|(person, value)| Share {
+            bill_id: bill.id,
+            paid_by: paid_by.to_owned(),
+            person,
+            method,
+            value,
+        })
+        .collect())
+}
+
+// Splits `total` in proportion to `weights` without losing a cent: every
+// part is rounded down and the cents left over, fewer than there are
+// parts, go to the first parts.
+fn allocate(total: Decimal, weights: &[Decimal]) -> Vec<Decimal> {
+    let sum: Decimal = weights.iter().sum();
+    let mut parts: Vec<Decimal> = weights
+        .iter()
+        .map(|weight| (total * weight / sum).round_dp_with_strategy(2, RoundingStrategy::ToZero))
+        .collect();
+    let mut left = total - parts.iter().sum::<Decimal>();
+    for part in parts.iter_mut() {
+        if left < CENT {
+            break;
+        }
+        *part += CENT;
+        left -= CENT;
+    }
+    parts
+}
+
+// What each person owes for the bill, in the bill's currency.
+pub fn owed(bill: &Bill, shares: &[&Share]) -> Result<Vec<(String, Decimal)>, String> {
+    let amounts = match shares.first().map(|share| share.method) {
+        None => return Ok(Vec::new()),
+        Some(Method::Exact) => {
+            let amounts: Vec<Decimal> = shares.iter().map(|share| share.value).collect();
+            let sum: Decimal = amounts.iter().sum();
+            if sum != bill.amount {
+                return Err(format!(
+                    "The split of bill #{} adds up to {sum:.2}, but the bill is {:.2}",
+                    bill.id, bill.amount
+                ));
+            }
+            amounts
+        }
+        Some(Method::Equal) => allocate(bill.amount, &vec![Decimal::ONE; shares.len()]),
+        Some(Method::Percent) => {
+            let weights: Vec<Decimal> = shares.iter().map(|share| share.value).collect();
+            allocate(bill.amount, &weights)
+        }
+    };
+    Ok(shares
+        .iter()
+        .map(|share| share.person.clone())
+        .zip(amounts)
+        .collect())
+}
+
+// Replaces the split of a bill. An empty list removes it.
+pub fn set(splits: &mut Vec<Share>, bill_id: u32, shares: Vec<Share>) {
+    splits.retain(|share| share.bill_id != bill_id);
+    splits.extend(shares);
+}
+
+// What everybody is owed (positive) or owes (negative) in the base
+// currency, after the payments made so far.
+pub fn balances(
+    bills: &Bills,
+    splits: &[Share],
+    payments: &[Payment],
+    rates: &[ExchangeRate],
+    base: &str,
+) -> Result<BTreeMap<String, Decimal>, String> {
+    let mut by_bill: BTreeMap<u32, Vec<&Share>> = BTreeMap::new();
+    for share in splits {
+        by_bill.entry(share.bill_id).or_default().push(share);
+    }
+    let mut balances: BTreeMap<String, Decimal> = BTreeMap::new();
+    for (id, shares) in by_bill {
+        // The split of a removed bill is kept in case the remove is undone.
+        let Some(bill) = bills.get(&id) else {
+            continue;
+        };
+        let payer = &shares[0].paid_by;
+        for (person, amount) in owed(bill, &shares)? {
+            let amount = currency::convert(rates, amount, &bill.currency, base, bill.date)?;
+            *balances.entry(payer.clone()).or_default() += amount;
+            *balances.entry(person).or_default() -= amount;
+        }
+    }
+    for payment in payments {
+        *balances.entry(payment.from.clone()).or_default() += payment.amount;
+        *balances.entry(payment.to.clone()).or_default() -= payment.amount;
+    }
+    Ok(balances)
+}
+
+// Transfers that bring every balance to zero. Debts that exactly match a
+// credit are paid directly, the rest goes from the largest debtor to the
+// largest creditor, which never needs more than one transfer fewer than
+// there are people.
+pub fn settle_up(balances: &BTreeMap<String, Decimal>) -> Vec<Transfer> {
+    let mut creditors: Vec<(String, Decimal)> = Vec::new();
+    let mut debtors: Vec<(String, Decimal)> = Vec::new();
+    for (person, balance) in balances {
+        if *balance > Decimal::ZERO {
+            creditors.push((person.clone(), *balance));
+        } else if *balance < Decimal::ZERO {
+            debtors.push((person.clone(), -*balance));
+        }
+    }
+    let mut transfers = Vec::new();
+    debtors.retain(|(debtor, debt)| {
+        match creditors.iter().position(|(_, credit)| credit == debt) {
+            Some(index) => {
+                let (creditor, credit) = creditors.remove(index);
+                transfers.push(Transfer {
+                    from: debtor.clone(),
+                    to: creditor,
+                    amount: credit,
+                });
+                false
+            }
+            None => true,
+        }
+    });
+    loop {
+        creditors.sort_by_key(|(_, credit)| Reverse(*credit));
+        debtors.sort_by_key(|(_, debt)| Reverse(*debt));
+        let (Some(creditor), Some(debtor)) = (creditors.first_mut(), debtors.first_mut()) else {
+            break;
+        };
+        let amount = creditor.1.min(debtor.1);
+        transfers.push(Transfer {
+            from: debtor.0.clone(),
+            to: creditor.0.clone(),
+            amount,
+        });
+        creditor.1 -= amount;
+        debtor.1 -= amount;
+        creditors.retain(|(_, credit)| !credit.is_zero());
+        debtors.retain(|(_, debt)| !debt.is_zero());
+    }
+    transfers
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bill::parse_date;
+    use rust_decimal_macros::dec;
+
+    fn bill(id: u32, amount: Decimal) -> Bill {
+        Bill::new(
+            id,
+            "Lunch",
+            amount,
+            parse_date("2024-05-01").unwrap(),
+            "Food",
+        )
+    }
+
+    fn equal(bill: &Bill, paid_by: &str, people: &str) -> Vec<Share> {
+        let parts = parse_people(people)
+            .unwrap()
+            .into_iter()
+            .map(|person| (person, Decimal::ZERO))
+            .collect();
+        shares(bill, paid_by, Method::Equal, parts).unwrap()
+    }
+
+    #[test]
+    fn parts_are_parsed_and_checked() {
+        assert_eq!(
+            parse_parts("alice=10, bob = 20.5").unwrap(),
+            vec![
+                ("alice".to_owned(), dec!(10)),
+                ("bob".to_owned(), dec!(20.5))
+            ]
+        );
+        assert!(parse_parts("alice").is_err());
+        assert!(parse_parts("alice=-1").is_err());
+
+        let lunch = bill(1, dec!(30));
+        let exact = parse_parts("alice=10,bob=15").unwrap();
+        assert_eq!(
+            shares(&lunch, "alice", Method::Exact, exact),
+            Err("The amounts add up to 25.00, not 30.00".to_owned())
+        );
+        let percent = parse_parts("alice=50,bob=40").unwrap();
+        assert!(shares(&lunch, "alice", Method::Percent, percent).is_err());
+        let twice = parse_people("alice,Alice").unwrap();
+        let twice = twice
+            .into_iter()
+            .map(|name| (name, Decimal::ZERO))
+            .collect();
+        assert!(shares(&lunch, "alice", Method::Equal, twice).is_err());
+    }
+
+    #[test]
+    fn equal_and_percent_splits_keep_every_cent() {
+        let lunch = bill(1, dec!(100));
+        let split = equal(&lunch, "alice", "alice,bob,carol");
+        let split: Vec<&Share> = split.iter().collect();
+        let amounts: Vec<Decimal> = owed(&lunch, &split)
+            .unwrap()
+            .into_iter()
+            .map(|(_, amount)| amount)
+            .collect();
+        assert_eq!(amounts, vec![dec!(33.34), dec!(33.33), dec!(33.33)]);
+
+        let parts = parse_parts("alice=12.5,bob=87.5").unwrap();
+        let split = shares(&bill(2, dec!(9.99)), "bob", Method::Percent, parts).unwrap();
+        let split: Vec<&Share> = split.iter().collect();
+        let owed = owed(&bill(2, dec!(9.99)), &split).unwrap();
+        assert_eq!(owed[0].1 + owed[1].1, dec!(9.99));
+        assert_eq!(owed[0].1, dec!(1.25));
+    }
+
+    #[test]
+    fn balances_follow_bills_and_payments() {
+        let bills: Bills = [bill(1, dec!(90)), bill(2, dec!(30))]
+            .into_iter()
+            .map(|bill| (bill.id, bill))
+            .collect();
+        let mut splits = Vec::new();
+        set(
+            &mut splits,
+            1,
+            equal(&bills[&1], "alice", "alice,bob,carol"),
+        );
+        set(&mut splits, 2, equal(&bills[&2], "bob", "bob,carol"));
+        let payments = vec![Payment {
+            date: parse_date("2024-05-02").unwrap(),
+            from: "carol".to_owned(),
+            to: "alice".to_owned(),
+            amount: dec!(10),
+        }];
+        let balances = balances(&bills, &splits, &payments, &[], "USD").unwrap();
+        assert_eq!(balances["alice"], dec!(50));
+        assert_eq!(balances["bob"], dec!(-15));
+        assert_eq!(balances["carol"], dec!(-35));
+        assert_eq!(balances.values().sum::<Decimal>(), Decimal::ZERO);
+
+        set(&mut splits, 2, Vec::new());
+        assert_eq!(splits.len(), 3);
+    }
+
+    #[test]
+    fn settle_up_needs_few_transfers() {
+        let balances: BTreeMap<String, Decimal> = [
+            ("alice", dec!(50)),
+            ("bob", dec!(-15)),
+            ("carol", dec!(-35)),
+            ("dave", dec!(20)),
+            ("erin", dec!(-20)),
+        ]
+        .into_iter()
+        .map(|(name, balance)| (name.to_owned(), balance))
+        .collect();
+        let transfers = settle_up(&balances);
+        assert_eq!(transfers.len(), 3);
+        assert_eq!(transfers[0].to_string(), "erin pays dave 20.00");
+        let mut after = balances.clone();
+        for transfer in &transfers {
+            *after.get_mut(&transfer.from).unwrap() += transfer.amount;
+            *after.get_mut(&transfer.to).unwrap() -= transfer.amount;
+        }
+        assert!(after.values().all(|balance| balance.is_zero()));
+        assert!(settle_up(&BTreeMap::new()).is_empty());
+    }
+}
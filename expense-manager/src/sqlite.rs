@@ -0,0 +1,412 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{NaiveDate, NaiveDateTime};
+use rusqlite::{params, Connection, OptionalExtension, Row, Transaction};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::bill::{Bill, Bills, DATE_FORMAT};
+use crate::history::{Change, LogEntry};
+use crate::ledger::Ledger;
+use crate::storage::{log_entries, StorageError, Store};
+
+// Sortable, so `WHERE time > '2024-01-31'` works in ad-hoc queries.
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f";
+
+// Schema changes, oldest first. A database remembers how many it has had in
+// `PRAGMA user_version`, so only the new ones run when it is opened. Never
+// edit one that has shipped; add another.
+const MIGRATIONS: &[&str] = &[
+    // Bills get real columns for querying. Amounts are text so they stay
+    // exact; cast them to do sums in SQL.
+    "CREATE TABLE bills (
+        id INTEGER PRIMARY KEY,
+        title TEXT NOT NULL,
+        amount TEXT NOT NULL,
+        currency TEXT NOT NULL,
+        date TEXT NOT NULL,
+        category TEXT NOT NULL,
+        payee TEXT NOT NULL,
+        notes TEXT NOT NULL,
+        paid INTEGER NOT NULL,
+        recurring_id INTEGER,
+        import_id TEXT
+    );
+    CREATE INDEX bills_by_date ON bills (date);
+    CREATE INDEX bills_by_category ON bills (category COLLATE NOCASE, date);
+    -- Recurring bills, budgets, rules and the rest, as JSON.
+    CREATE TABLE sections (
+        name TEXT PRIMARY KEY,
+        data TEXT NOT NULL
+    );",
+    "CREATE TABLE change_log (
+        seq INTEGER PRIMARY KEY AUTOINCREMENT,
+        time TEXT NOT NULL,
+        bill_id INTEGER NOT NULL,
+        before TEXT,
+        after TEXT
+    );
+    CREATE INDEX change_log_by_time ON change_log (time);",
+];
+
+#[derive(Debug)]
+pub struct SqliteStore {
+    path: PathBuf,
+}
+
+impl SqliteStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    fn error(&self, err: impl ToString) -> StorageError {
+        StorageError::Database(self.path.clone(), err.to_string())
+    }
+
+    // Opens the database, creating it if needed, and brings its schema up to
+    // date.
+    fn connect(&self) -> Result<Connection, StorageError> {
+        let mut conn = Connection::open(&self.path).map_err(|err| self.error(err))?;
+        migrate(&mut conn).map_err(|err| self.error(err))?;
+        Ok(conn)
+    }
+
+    // Like `connect`, but a missing database is NotFound, as for files.
+    fn connect_existing(&self) -> Result<Connection, StorageError> {
+        if !self.path.exists() {
+            return Err(StorageError::NotFound(self.path.clone()));
+        }
+        self.connect()
+    }
+
+    fn corrupt(&self, reason: String) -> StorageError {
+        StorageError::Corrupt(self.path.clone(), reason)
+    }
+
+    fn parse_json<T: DeserializeOwned>(
+        &self,
+        data: Option<&str>,
+    ) -> Result<Option<T>, StorageError> {
+        data.map(serde_json::from_str)
+            .transpose()
+            .map_err(|err| self.corrupt(err.to_string()))
+    }
+
+    fn load_section<T: DeserializeOwned + Default>(
+        &self,
+        conn: &Connection,
+        name: &str,
+    ) -> Result<T, StorageError> {
+        let data: Option<String> = conn
+            .query_row("SELECT data FROM sections WHERE name = ?1", [name], |row| {
+                row.get(0)
+            })
+            .optional()
+            .map_err(|err| self.error(err))?;
+        Ok(self.parse_json(data.as_deref())?.unwrap_or_default())
+    }
+
+    fn save_section<T: Serialize>(
+        &self,
+        tx: &Transaction,
+        name: &str,
+        value: &T,
+    ) -> Result<(), StorageError> {
+        let data = serde_json::to_string(value).map_err(|err| self.corrupt(err.to_string()))?;
+        tx.execute(
+            "INSERT OR REPLACE INTO sections (name, data) VALUES (?1, ?2)",
+            params![name, data],
+        )
+        .map_err(|err| self.error(err))?;
+        Ok(())
+    }
+
+    fn save_sections(&self, tx: &Transaction, ledger: &Ledger) -> Result<(), StorageError> {
+        self.save_section(tx, "recurring", &ledger.recurring)?;
+        self.save_section(tx, "budgets", &ledger.budgets)?;
+        self.save_section(tx, "rules", &ledger.rules)?;
+        self.save_section(tx, "settings", &ledger.settings)?;
+        self.save_section(tx, "rates", &ledger.rates)?;
+        self.save_section(tx, "splits", &ledger.splits)?;
+        self.save_section(tx, "payments", &ledger.payments)?;
+        self.save_section(tx, "attachments", &ledger.attachments)?;
+        self.save_section(tx, "income", &ledger.income)?;
+        self.save_section(tx, "goals", &ledger.goals)
+    }
+
+    fn write_log(&self, tx: &Transaction, entries: &[LogEntry]) -> Result<(), StorageError> {
+        let json = |bill: &Option<Bill>| bill.as_ref().map(serde_json::to_string).transpose();
+        for entry in entries {
+            let before = json(&entry.change.before).map_err(|err| self.corrupt(err.to_string()))?;
+            let after = json(&entry.change.after).map_err(|err| self.corrupt(err.to_string()))?;
+            tx.execute(
+                "INSERT INTO change_log (time, bill_id, before, after) VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    entry.time.format(TIME_FORMAT).to_string(),
+                    entry.change.id(),
+                    before,
+                    after
+                ],
+            )
+            .map_err(|err| self.error(err))?;
+        }
+        Ok(())
+    }
+}
+
+impl Store for SqliteStore {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn load(&self) -> Result<Ledger, StorageError> {
+        let conn = self.connect_existing()?;
+        let bills = {
+            let mut statement = conn
+                .prepare("SELECT * FROM bills")
+                .map_err(|err| self.error(err))?;
+            let rows = statement
+                .query_map([], read_bill)
+                .map_err(|err| self.error(err))?;
+            rows.map(|row| row.map(|bill| (bill.id, bill)))
+                .collect::<Result<Bills, _>>()
+                .map_err(|err| self.error(err))?
+        };
+        Ok(Ledger {
+            bills,
+            recurring: self.load_section(&conn, "recurring")?,
+            budgets: self.load_section(&conn, "budgets")?,
+            rules: self.load_section(&conn, "rules")?,
+            settings: self.load_section(&conn, "settings")?,
+            rates: self.load_section(&conn, "rates")?,
+            splits: self.load_section(&conn, "splits")?,
+            payments: self.load_section(&conn, "payments")?,
+            attachments: self.load_section(&conn, "attachments")?,
+            income: self.load_section(&conn, "income")?,
+            goals: self.load_section(&conn, "goals")?,
+        })
+    }
+
+    fn save(&self, ledger: &Ledger) -> Result<(), StorageError> {
+        let mut conn = self.connect()?;
+        let tx = conn.transaction().map_err(|err| self.error(err))?;
+        tx.execute("DELETE FROM bills", [])
+            .map_err(|err| self.error(err))?;
+        for bill in ledger.bills.values() {
+            write_bill(&tx, bill).map_err(|err| self.error(err))?;
+        }
+        self.save_sections(&tx, ledger)?;
+        tx.commit().map_err(|err| self.error(err))
+    }
+
+    // Only writes the bills that changed, together with their log entries in
+    // the same transaction, so a save is quick however many bills there are.
+    fn save_with_log(&self, ledger: &Ledger, previous: &Bills) -> Result<(), StorageError> {
+        let entries = log_entries(previous, &ledger.bills);
+        let mut conn = self.connect()?;
+        let tx = conn.transaction().map_err(|err| self.error(err))?;
+        for entry in &entries {
+            match (&entry.change.before, &entry.change.after) {
+                (_, Some(bill)) => write_bill(&tx, bill),
+                (Some(bill), None) => tx
+                    .execute("DELETE FROM bills WHERE id = ?1", [bill.id])
+                    .map(|_| ()),
+                (None, None) => Ok(()),
+            }
+            .map_err(|err| self.error(err))?;
+        }
+        self.save_sections(&tx, ledger)?;
+        self.write_log(&tx, &entries)?;
+        tx.commit().map_err(|err| self.error(err))
+    }
+
+    fn append_log(&self, entries: &[LogEntry]) -> Result<(), StorageError> {
+        let mut conn = self.connect()?;
+        let tx = conn.transaction().map_err(|err| self.error(err))?;
+        self.write_log(&tx, entries)?;
+        tx.commit().map_err(|err| self.error(err))
+    }
+
+    fn load_log(&self) -> Result<Vec<LogEntry>, StorageError> {
+        let conn = match self.connect_existing() {
+            Ok(conn) => conn,
+            Err(StorageError::NotFound(_)) => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        let mut statement = conn
+            .prepare("SELECT time, before, after FROM change_log ORDER BY seq")
+            .map_err(|err| self.error(err))?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                ))
+            })
+            .map_err(|err| self.error(err))?;
+        rows.map(|row| {
+            let (time, before, after) = row.map_err(|err| self.error(err))?;
+            let time = NaiveDateTime::parse_from_str(&time, TIME_FORMAT)
+                .map_err(|err| self.corrupt(format!("change log time '{time}': {err}")))?;
+            let change = Change {
+                before: self.parse_json(before.as_deref())?,
+                after: self.parse_json(after.as_deref())?,
+            };
+            Ok(LogEntry { time, change })
+        })
+        .collect()
+    }
+}
+
+// Runs the migrations the database has not had yet, each in its own
+// transaction.
+fn migrate(conn: &mut Connection) -> Result<(), String> {
+    let version: usize = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(|err| err.to_string())?;
+    if version > MIGRATIONS.len() {
+        return Err(format!(
+            "schema version {version} is newer than this program understands ({})",
+            MIGRATIONS.len()
+        ));
+    }
+    for (number, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction().map_err(|err| err.to_string())?;
+        tx.execute_batch(migration)
+            .and_then(|()| tx.pragma_update(None, "user_version", number + 1))
+            .and_then(|()| tx.commit())
+            .map_err(|err| format!("migration {} failed: {err}", number + 1))?;
+    }
+    Ok(())
+}
+
+fn write_bill(tx: &Transaction, bill: &Bill) -> rusqlite::Result<()> {
+    tx.execute(
+        "INSERT OR REPLACE INTO bills
+         (id, title, amount, currency, date, category, payee, notes, paid, recurring_id, import_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        params![
+            bill.id,
+            bill.title,
+            bill.amount.to_string(),
+            bill.currency,
+            bill.date.format(DATE_FORMAT).to_string(),
+            bill.category,
+            bill.payee,
+            bill.notes,
+            bill.paid,
+            bill.recurring_id,
+            bill.import_id,
+        ],
+    )?;
+    Ok(())
+}
+
+fn read_bill(row: &Row) -> rusqlite::Result<Bill> {
+    // Text columns that do not parse are reported like any other bad value.
+    let invalid = |index: usize, err: String| {
+        rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, err.into())
+    };
+    let amount: String = row.get("amount")?;
+    let date: String = row.get("date")?;
+    Ok(Bill {
+        id: row.get("id")?,
+        title: row.get("title")?,
+        amount: amount
+            .parse()
+            .map_err(|err| invalid(2, format!("amount '{amount}': {err}")))?,
+        currency: row.get("currency")?,
+        date: NaiveDate::parse_from_str(&date, DATE_FORMAT)
+            .map_err(|err| invalid(4, format!("date '{date}': {err}")))?,
+        category: row.get("category")?,
+        payee: row.get("payee")?,
+        notes: row.get("notes")?,
+        paid: row.get("paid")?,
+        recurring_id: row.get("recurring_id")?,
+        import_id: row.get("import_id")?,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bill::parse_date;
+    use crate::budget::Budget;
+    use rust_decimal_macros::dec;
+
+    fn ledger() -> Ledger {
+        let mut ledger = Ledger::default();
+        for (id, date, category) in [
+            (1, "2024-01-01", "Housing"),
+            (2, "2024-01-15", "Food"),
+            (3, "2024-02-01", "Housing"),
+        ] {
+            let mut bill = Bill::new(id, "Bill", dec!(10.05), parse_date(date).unwrap(), category);
+            bill.import_id = Some(format!("bank:{id}"));
+            ledger.bills.insert(id, bill);
+        }
+        ledger.budgets.push(Budget {
+            category: "Food".to_owned(),
+            monthly_limit: dec!(200),
+        });
+        ledger
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SqliteStore::new(dir.path().join("bills.db"));
+        assert!(matches!(store.load(), Err(StorageError::NotFound(_))));
+        assert!(store.load_log().unwrap().is_empty());
+        store.save(&ledger()).unwrap();
+        assert_eq!(store.load().unwrap(), ledger());
+    }
+
+    #[test]
+    fn save_with_log_writes_only_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SqliteStore::new(dir.path().join("bills.db"));
+        store.save(&ledger()).unwrap();
+        let mut changed = ledger();
+        changed.bills.remove(&1);
+        changed.bills.get_mut(&2).unwrap().paid = true;
+        store.save_with_log(&changed, &ledger().bills).unwrap();
+        assert_eq!(store.load().unwrap(), changed);
+
+        let log = store.load_log().unwrap();
+        assert_eq!(log.len(), 2);
+        assert_eq!(log[0].change.after, None);
+        let past = crate::history::as_of(
+            &changed.bills,
+            &log,
+            log[0].time - chrono::Duration::seconds(1),
+        );
+        assert_eq!(past.unwrap(), ledger().bills);
+    }
+
+    #[test]
+    fn queries_use_the_indexes_and_newer_schemas_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SqliteStore::new(dir.path().join("bills.db"));
+        store.save(&ledger()).unwrap();
+        let conn = Connection::open(store.path()).unwrap();
+        let version: usize = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+        let plan = |query: &str| -> String {
+            conn.query_row(&format!("EXPLAIN QUERY PLAN {query}"), [], |row| row.get(3))
+                .unwrap()
+        };
+        assert!(plan("SELECT * FROM bills WHERE date >= '2024-02-01'").contains("bills_by_date"));
+        assert!(
+            plan("SELECT * FROM bills WHERE category = 'food' COLLATE NOCASE")
+                .contains("bills_by_category")
+        );
+
+        conn.pragma_update(None, "user_version", MIGRATIONS.len() + 1)
+            .unwrap();
+        assert!(store.load().unwrap_err().to_string().contains("newer"));
+    }
+}
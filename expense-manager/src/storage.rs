@@ -0,0 +1,848 @@
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chrono::Local;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::attachment;
+use crate::bill::{Bill, Bills};
+use crate::crypto::{self, Sealer};
+use crate::history::{self, LogEntry};
+use crate::ledger::Ledger;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Csv,
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Csv => "csv",
+            #[cfg(feature = "sqlite")]
+            Format::Sqlite => "db",
+        }
+    }
+
+    // The format a file name suggests, if any.
+    pub fn from_path(path: &Path) -> Option<Format> {
+        match path.extension()?.to_str()? {
+            "json" => Some(Format::Json),
+            "csv" => Some(Format::Csv),
+            #[cfg(feature = "sqlite")]
+            "db" | "sqlite" => Some(Format::Sqlite),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            #[cfg(feature = "sqlite")]
+            "sqlite" => Ok(Format::Sqlite),
+            #[cfg(feature = "sqlite")]
+            _ => Err(format!(
+                "Unknown format: {s} (expected json, csv or sqlite)"
+            )),
+            #[cfg(not(feature = "sqlite"))]
+            _ => Err(format!("Unknown format: {s} (expected json or csv)")),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum StorageError {
+    NotFound(PathBuf),
+    Io(PathBuf, io::Error),
+    Corrupt(PathBuf, String),
+    Encryption(PathBuf, String),
+    #[cfg(feature = "sqlite")]
+    Database(PathBuf, String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::NotFound(path) => write!(f, "{} does not exist", path.display()),
+            StorageError::Io(path, err) => write!(f, "could not access {}: {err}", path.display()),
+            StorageError::Corrupt(path, reason) => {
+                write!(f, "{} is not a valid bills file: {reason}", path.display())
+            }
+            StorageError::Encryption(path, reason) => {
+                write!(f, "{}: {reason}", path.display())
+            }
+            #[cfg(feature = "sqlite")]
+            StorageError::Database(path, reason) => {
+                write!(f, "database error in {}: {reason}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+// Where the ledger and its change log are kept.
+pub trait Store: fmt::Debug {
+    fn path(&self) -> &Path;
+
+    fn load(&self) -> Result<Ledger, StorageError>;
+
+    fn save(&self, ledger: &Ledger) -> Result<(), StorageError>;
+
+    fn append_log(&self, entries: &[LogEntry]) -> Result<(), StorageError>;
+
+    fn load_log(&self) -> Result<Vec<LogEntry>, StorageError>;
+
+    // Saves the ledger and logs how its bills differ from `previous`, the
+    // bills as they were last saved.
+    fn save_with_log(&self, ledger: &Ledger, previous: &Bills) -> Result<(), StorageError> {
+        self.save(ledger)?;
+        let entries = log_entries(previous, &ledger.bills);
+        if entries.is_empty() {
+            return Ok(());
+        }
+        self.append_log(&entries)
+    }
+
+    // Attached files are kept next to the data, in `bills.attachments/`
+    // for `bills.json`.
+    fn attachments_dir(&self) -> PathBuf {
+        let path = self.path();
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        path.with_file_name(format!("{stem}.attachments"))
+    }
+
+    // The passphrase the data is encrypted with, if it is.
+    fn passphrase(&self) -> Option<&str> {
+        None
+    }
+
+    // Encrypts attached files like the data, if it is encrypted.
+    fn sealer(&self) -> Option<&Sealer> {
+        None
+    }
+
+    // Saves the ledger and rewrites the change log encrypted with a new
+    // passphrase, or unencrypted for None.
+    fn change_passphrase(
+        &mut self,
+        _ledger: &Ledger,
+        _passphrase: Option<String>,
+    ) -> Result<(), StorageError> {
+        Err(StorageError::Encryption(
+            self.path().to_owned(),
+            "only JSON and CSV files can be encrypted".to_owned(),
+        ))
+    }
+}
+
+// The file or database for `path`, encrypted with `passphrase` if given.
+pub fn open(
+    path: impl Into<PathBuf>,
+    format: Format,
+    passphrase: Option<String>,
+) -> Result<Box<dyn Store>, StorageError> {
+    match format {
+        #[cfg(feature = "sqlite")]
+        Format::Sqlite if passphrase.is_some() => Err(StorageError::Encryption(
+            path.into(),
+            "only JSON and CSV files can be encrypted".to_owned(),
+        )),
+        #[cfg(feature = "sqlite")]
+        Format::Sqlite => Ok(Box::new(crate::sqlite::SqliteStore::new(path))),
+        _ => Ok(Box::new(
+            FileStore::new(path, format).with_passphrase(passphrase),
+        )),
+    }
+}
+
+// Whether the data file at `path` exists and is encrypted, so the user has
+// to be asked for the passphrase.
+pub fn is_encrypted(path: &Path) -> bool {
+    let mut magic = [0; 8];
+    File::open(path)
+        .and_then(|mut file| io::Read::read_exact(&mut file, &mut magic))
+        .is_ok_and(|()| crypto::is_encrypted(&magic))
+}
+
+// The changes between two saves, all stamped with the current time.
+pub fn log_entries(previous: &Bills, bills: &Bills) -> Vec<LogEntry> {
+    let time = Local::now().naive_local();
+    history::diff(previous, bills)
+        .into_iter()
+        .map(|change| LogEntry { time, change })
+        .collect()
+}
+
+#[derive(Debug)]
+pub struct FileStore {
+    path: PathBuf,
+    format: Format,
+    // Set when the files are encrypted.
+    sealer: Option<Sealer>,
+}
+
+impl FileStore {
+    pub fn new(path: impl Into<PathBuf>, format: Format) -> Self {
+        Self {
+            path: path.into(),
+            format,
+            sealer: None,
+        }
+    }
+
+    pub fn with_passphrase(mut self, passphrase: Option<String>) -> Self {
+        self.sealer = passphrase.map(Sealer::new);
+        self
+    }
+
+    // Reads a file, decrypting it if it is encrypted. With a passphrase an
+    // unencrypted file is refused: only `change_passphrase` turns one into
+    // an encrypted file, and it reads the file without a passphrase.
+    fn read(&self, path: &Path) -> Result<Vec<u8>, StorageError> {
+        crypto::unseal(self.sealer.as_ref(), read_file(path)?)
+            .map_err(|reason| StorageError::Encryption(path.to_owned(), reason))
+    }
+
+    fn seal(&self, path: &Path, data: &[u8]) -> Result<Vec<u8>, StorageError> {
+        crypto::seal(self.sealer.as_ref(), data)
+            .map_err(|reason| StorageError::Encryption(path.to_owned(), reason))
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> Result<(), StorageError> {
+        let data = self.seal(path, data)?;
+        write_atomic(path, &data).map_err(|err| StorageError::Io(path.to_owned(), err))
+    }
+
+    // Lists the files of a save that is being renamed into place, e.g.
+    // `bills.commit` next to `bills.csv`.
+    fn commit_path(&self) -> PathBuf {
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        self.path.with_file_name(format!("{stem}.commit"))
+    }
+
+    // Writes several files so that either all of them or none are replaced.
+    // Every file goes to a temporary file first, and only once they are all
+    // written are they renamed into place.
+    fn write_together(&self, files: &[(PathBuf, Vec<u8>)]) -> Result<(), StorageError> {
+        self.stage(files)?;
+        self.finish_commit()
+    }
+
+    // Writes the temporary files and then the commit file naming them. From
+    // then on the save counts as done: if the renames are interrupted, the
+    // next load finishes them.
+    fn stage(&self, files: &[(PathBuf, Vec<u8>)]) -> Result<(), StorageError> {
+        // Names are relative to the data file's directory, since attached
+        // files are in a directory of their own.
+        let base = self.path.parent().unwrap_or(Path::new(""));
+        let mut names = String::new();
+        for (path, data) in files {
+            let data = self.seal(path, data)?;
+            write_synced(&tmp_path(path), &data)
+                .map_err(|err| StorageError::Io(path.to_owned(), err))?;
+            let name = path.strip_prefix(base).unwrap_or(path);
+            names.push_str(&name.to_string_lossy());
+            names.push('\n');
+        }
+        let commit = self.commit_path();
+        write_atomic(&commit, names.as_bytes()).map_err(|err| StorageError::Io(commit, err))
+    }
+
+    // Renames the files of a staged save into place. Temporary files left by
+    // a save that failed before its commit file was written are never used.
+    fn finish_commit(&self) -> Result<(), StorageError> {
+        let commit = self.commit_path();
+        let names = match fs::read_to_string(&commit) {
+            Ok(names) => names,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(StorageError::Io(commit, err)),
+        };
+        for name in names.lines() {
+            let path = self.path.with_file_name(name);
+            let tmp = tmp_path(&path);
+            // Already renamed if the temporary file is gone.
+            if tmp.exists() {
+                fs::rename(&tmp, &path).map_err(|err| StorageError::Io(path.clone(), err))?;
+            }
+        }
+        fs::remove_file(&commit).map_err(|err| StorageError::Io(commit, err))
+    }
+
+    // Replaces the whole change log.
+    fn write_log(&self, entries: &[LogEntry]) -> Result<(), StorageError> {
+        let path = self.log_path();
+        self.write(&path, &log_lines(&path, entries)?)
+    }
+
+    // The change log is kept next to the data file as one JSON object per
+    // line, in either format, so saving only ever appends to it.
+    pub fn log_path(&self) -> PathBuf {
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        self.path.with_file_name(format!("{stem}.log.jsonl"))
+    }
+
+    // CSV keeps everything that is not a bill in files next to the bills
+    // file, e.g. `bills.recurring.csv` next to `bills.csv`.
+    fn section_path(&self, section: &str) -> PathBuf {
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        self.path
+            .with_file_name(format!("{stem}.{section}.{}", self.format.extension()))
+    }
+
+    fn load_section<T: DeserializeOwned>(&self, section: &str) -> Result<Vec<T>, StorageError> {
+        let path = self.section_path(section);
+        match self.read(&path) {
+            Ok(data) => parse_csv(&path, &data),
+            Err(StorageError::NotFound(_)) => Ok(Vec::new()),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn section<T: Serialize>(
+        &self,
+        section: &str,
+        rows: &[T],
+    ) -> Result<(PathBuf, Vec<u8>), StorageError> {
+        let path = self.section_path(section);
+        let data = to_csv(&path, rows)?;
+        Ok((path, data))
+    }
+
+    // Every file a save writes, unencrypted. CSV writes the sections before
+    // the bills file.
+    fn files(&self, ledger: &Ledger) -> Result<Vec<(PathBuf, Vec<u8>)>, StorageError> {
+        let data = match self.format {
+            Format::Json => serde_json::to_vec_pretty(ledger)
+                .map_err(|err| StorageError::Corrupt(self.path.clone(), err.to_string()))?,
+            #[cfg(feature = "sqlite")]
+            Format::Sqlite => unreachable!("open() keeps SQLite files out of FileStore"),
+            Format::Csv => {
+                let sections = vec![
+                    self.section("recurring", &ledger.recurring)?,
+                    self.section("budgets", &ledger.budgets)?,
+                    self.section("rules", &ledger.rules)?,
+                    self.section("settings", &[&ledger.settings])?,
+                    self.section("rates", &ledger.rates)?,
+                    self.section("splits", &ledger.splits)?,
+                    self.section("payments", &ledger.payments)?,
+                    self.section("attachments", &ledger.attachments)?,
+                    self.section("income", &ledger.income)?,
+                    self.section("goals", &ledger.goals)?,
+                ];
+                let mut bills: Vec<&Bill> = ledger.bills.values().collect();
+                bills.sort_by_key(|bill| bill.id);
+                let mut files = sections;
+                files.push((self.path.clone(), to_csv(&self.path, &bills)?));
+                return Ok(files);
+            }
+        };
+        Ok(vec![(self.path.clone(), data)])
+    }
+}
+
+impl Store for FileStore {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn load(&self) -> Result<Ledger, StorageError> {
+        self.finish_commit()?;
+        let data = self.read(&self.path)?;
+        match self.format {
+            Format::Json => serde_json::from_slice(&data)
+                .map_err(|err| StorageError::Corrupt(self.path.clone(), err.to_string())),
+            #[cfg(feature = "sqlite")]
+            Format::Sqlite => unreachable!("open() keeps SQLite files out of FileStore"),
+            Format::Csv => {
+                let bills: Vec<Bill> = parse_csv(&self.path, &data)?;
+                Ok(Ledger {
+                    bills: bills.into_iter().map(|bill| (bill.id, bill)).collect(),
+                    recurring: self.load_section("recurring")?,
+                    budgets: self.load_section("budgets")?,
+                    rules: self.load_section("rules")?,
+                    // A one-row table.
+                    settings: self
+                        .load_section("settings")?
+                        .into_iter()
+                        .next()
+                        .unwrap_or_default(),
+                    rates: self.load_section("rates")?,
+                    splits: self.load_section("splits")?,
+                    payments: self.load_section("payments")?,
+                    attachments: self.load_section("attachments")?,
+                    income: self.load_section("income")?,
+                    goals: self.load_section("goals")?,
+                })
+            }
+        }
+    }
+
+    // CSV spreads the ledger over several files, which are replaced
+    // together so the sections always match the bills.
+    fn save(&self, ledger: &Ledger) -> Result<(), StorageError> {
+        self.write_together(&self.files(ledger)?)
+    }
+
+    // An encrypted log can't be appended to, so it is rewritten instead.
+    fn append_log(&self, entries: &[LogEntry]) -> Result<(), StorageError> {
+        if self.sealer.is_some() {
+            let mut log = self.load_log()?;
+            log.extend_from_slice(entries);
+            return self.write_log(&log);
+        }
+        let path = self.log_path();
+        let data = log_lines(&path, entries)?;
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| {
+                file.write_all(&data)?;
+                file.sync_all()
+            })
+            .map_err(|err| StorageError::Io(path, err))
+    }
+
+    fn load_log(&self) -> Result<Vec<LogEntry>, StorageError> {
+        self.finish_commit()?;
+        let path = self.log_path();
+        let data = match self.read(&path) {
+            Ok(data) => data,
+            Err(StorageError::NotFound(_)) => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        data.split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .enumerate()
+            .map(|(number, line)| {
+                serde_json::from_slice(line).map_err(|err| {
+                    StorageError::Corrupt(path.clone(), format!("line {}: {err}", number + 1))
+                })
+            })
+            .collect()
+    }
+
+    fn passphrase(&self) -> Option<&str> {
+        self.sealer.as_ref().map(Sealer::passphrase)
+    }
+
+    fn sealer(&self) -> Option<&Sealer> {
+        self.sealer.as_ref()
+    }
+
+    // Every file, attached files included, is staged under the new
+    // passphrase before any is replaced, so a failure leaves all of them
+    // readable with the old one.
+    fn change_passphrase(
+        &mut self,
+        ledger: &Ledger,
+        passphrase: Option<String>,
+    ) -> Result<(), StorageError> {
+        let log = self.load_log()?;
+        let dir = self.attachments_dir();
+        let attached = attachment::stored_files(&dir, &ledger.attachments, self.sealer())
+            .map_err(|reason| StorageError::Encryption(dir, reason))?;
+        let old = std::mem::replace(&mut self.sealer, passphrase.map(Sealer::new));
+        let log_path = self.log_path();
+        let staged = self.files(ledger).and_then(|mut files| {
+            files.push((log_path.clone(), log_lines(&log_path, &log)?));
+            files.extend(attached);
+            self.stage(&files)
+        });
+        if let Err(err) = staged {
+            self.sealer = old;
+            return Err(err);
+        }
+        self.finish_commit()
+    }
+}
+
+// The change log keeps one JSON object per line.
+fn log_lines(path: &Path, entries: &[LogEntry]) -> Result<Vec<u8>, StorageError> {
+    let mut data = Vec::new();
+    for entry in entries {
+        serde_json::to_writer(&mut data, entry)
+            .map_err(|err| StorageError::Corrupt(path.to_owned(), err.to_string()))?;
+        data.push(b'\n');
+    }
+    Ok(data)
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, StorageError> {
+    fs::read(path).map_err(|err| match err.kind() {
+        io::ErrorKind::NotFound => StorageError::NotFound(path.to_owned()),
+        _ => StorageError::Io(path.to_owned(), err),
+    })
+}
+
+fn parse_csv<T: DeserializeOwned>(path: &Path, data: &[u8]) -> Result<Vec<T>, StorageError> {
+    csv::Reader::from_reader(data)
+        .deserialize()
+        .collect::<Result<_, _>>()
+        .map_err(|err| StorageError::Corrupt(path.to_owned(), err.to_string()))
+}
+
+fn to_csv<T: Serialize>(path: &Path, rows: &[T]) -> Result<Vec<u8>, StorageError> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in rows {
+        writer
+            .serialize(row)
+            .map_err(|err| StorageError::Corrupt(path.to_owned(), err.to_string()))?;
+    }
+    writer
+        .into_inner()
+        .map_err(|err| StorageError::Io(path.to_owned(), err.into_error()))
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_owned();
+    tmp_name.push(".tmp");
+    path.with_file_name(tmp_name)
+}
+
+fn write_synced(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(data)?;
+    file.sync_all()
+}
+
+// Writes to a temporary file next to the target and renames it into place,
+// so a crash mid-write leaves the previous file intact.
+fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let tmp_path = tmp_path(path);
+    write_synced(&tmp_path, data)?;
+    fs::rename(&tmp_path, path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::attachment::Attachment;
+    use crate::budget::Budget;
+    use crate::currency::ExchangeRate;
+    use crate::forecast::{Goal, Income};
+    use crate::history::Change;
+    use crate::import::CategoryRule;
+    use crate::ledger::Settings;
+    use crate::recurring::{RecurringBill, Rule};
+    use crate::split::{Method, Payment, Share};
+    use chrono::NaiveDate;
+    use rust_decimal_macros::dec;
+
+    fn sample_ledger() -> Ledger {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+        let mut internet = Bill::new(2, "Internet", dec!(49.99), date, "Utilities");
+        internet.currency = "USD".to_owned();
+        internet.notes = "fiber, 1 Gbit".to_owned();
+        internet.paid = true;
+        let mut rent = Bill::new(1, "Rent", dec!(1200), date, "Housing");
+        rent.currency = "EUR".to_owned();
+        rent.recurring_id = Some(1);
+        internet.import_id = Some("bank:0042".to_owned());
+        Ledger {
+            bills: [rent, internet]
+                .into_iter()
+                .map(|bill| (bill.id, bill))
+                .collect(),
+            recurring: vec![RecurringBill::new(
+                1,
+                "Rent",
+                dec!(1200),
+                "Housing",
+                Rule::Monthly(31),
+                date,
+            )],
+            budgets: vec![Budget::new("Housing", dec!(1500))],
+            rules: vec![CategoryRule::new("telekom", "Utilities")],
+            settings: Settings {
+                base_currency: "EUR".to_owned(),
+                last_bill_id: 3,
+            },
+            rates: vec![ExchangeRate {
+                date,
+                from: "USD".to_owned(),
+                to: "EUR".to_owned(),
+                rate: dec!(0.9215),
+            }],
+            splits: vec![Share {
+                bill_id: 2,
+                paid_by: "alice".to_owned(),
+                person: "bob".to_owned(),
+                method: Method::Percent,
+                value: dec!(100),
+            }],
+            payments: vec![Payment {
+                date,
+                from: "bob".to_owned(),
+                to: "alice".to_owned(),
+                amount: dec!(45.50),
+            }],
+            attachments: vec![Attachment {
+                bill_id: 2,
+                hash: "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08".to_owned(),
+                name: "internet, january.pdf".to_owned(),
+                size: 4,
+            }],
+            income: vec![
+                Income {
+                    id: 1,
+                    source: "Salary".to_owned(),
+                    amount: dec!(3100),
+                    currency: "EUR".to_owned(),
+                    date,
+                    rule: Some(Rule::Monthly(28)),
+                },
+                Income {
+                    id: 2,
+                    source: "Tax refund".to_owned(),
+                    amount: dec!(240.15),
+                    currency: "EUR".to_owned(),
+                    date,
+                    rule: None,
+                },
+            ],
+            goals: vec![Goal {
+                name: "New bike".to_owned(),
+                target: dec!(900),
+                saved: dec!(150),
+                by: NaiveDate::from_ymd_opt(2024, 8, 31).unwrap(),
+            }],
+        }
+    }
+
+    #[test]
+    fn round_trips_both_formats() {
+        let dir = tempfile::tempdir().unwrap();
+        for format in [Format::Json, Format::Csv] {
+            let store = FileStore::new(
+                dir.path().join(format!("bills.{}", format.extension())),
+                format,
+            );
+            store.save(&sample_ledger()).unwrap();
+            let loaded = store.load().unwrap();
+            assert_eq!(loaded, sample_ledger());
+        }
+    }
+
+    #[test]
+    fn files_from_before_currencies_load_as_usd() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bills.json");
+        fs::write(
+            &path,
+            r#"{"bills":[{"id":1,"title":"Rent","amount":1200.1,"date":"2024-01-01","category":"Housing","payee":"","notes":"","paid":false}]}"#,
+        )
+        .unwrap();
+        let ledger = FileStore::new(&path, Format::Json).load().unwrap();
+        assert_eq!(ledger.bills[&1].amount, dec!(1200.1));
+        assert_eq!(ledger.bills[&1].currency, "USD");
+        assert_eq!(ledger.base_currency(), "USD");
+    }
+
+    #[test]
+    fn change_log_is_appended_to() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileStore::new(dir.path().join("bills.csv"), Format::Csv);
+        assert!(store.load_log().unwrap().is_empty());
+        let ledger = sample_ledger();
+        let entry = |id| LogEntry {
+            time: NaiveDate::from_ymd_opt(2024, 2, 1)
+                .unwrap()
+                .and_hms_opt(9, 30, 0)
+                .unwrap(),
+            change: Change {
+                before: None,
+                after: Some(ledger.bills[&id].clone()),
+            },
+        };
+        store.append_log(&[entry(1)]).unwrap();
+        store.append_log(&[entry(2)]).unwrap();
+        assert_eq!(store.load_log().unwrap(), vec![entry(1), entry(2)]);
+        assert_eq!(store.log_path(), dir.path().join("bills.log.jsonl"));
+    }
+
+    #[test]
+    fn missing_file_is_reported_as_not_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileStore::new(dir.path().join("bills.json"), Format::Json);
+        assert!(matches!(store.load(), Err(StorageError::NotFound(_))));
+    }
+
+    #[test]
+    fn corrupt_file_is_reported_with_its_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bills.csv");
+        fs::write(
+            &path,
+            "id,title,amount,date,category,payee,notes,paid,recurring_id\n1,Rent,lots,2024-01-01,,,,false,\n",
+        )
+        .unwrap();
+        let err = FileStore::new(&path, Format::Csv).load().unwrap_err();
+        assert!(matches!(err, StorageError::Corrupt(..)));
+        assert!(err.to_string().contains("bills.csv"));
+    }
+
+    #[test]
+    fn save_leaves_no_temporary_file_behind() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileStore::new(dir.path().join("bills.csv"), Format::Csv);
+        store.save(&sample_ledger()).unwrap();
+        let mut names: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "bills.attachments.csv",
+                "bills.budgets.csv",
+                "bills.csv",
+                "bills.goals.csv",
+                "bills.income.csv",
+                "bills.payments.csv",
+                "bills.rates.csv",
+                "bills.recurring.csv",
+                "bills.rules.csv",
+                "bills.settings.csv",
+                "bills.splits.csv"
+            ]
+        );
+    }
+
+    #[test]
+    fn interrupted_csv_save_is_finished_or_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileStore::new(dir.path().join("bills.csv"), Format::Csv);
+        let mut before = sample_ledger();
+        before.splits.clear();
+        before.bills.remove(&2);
+        store.save(&before).unwrap();
+
+        // Staged but not renamed yet: the sections and bills of the new save
+        // only show up all together.
+        let files = store.files(&sample_ledger()).unwrap();
+        store.stage(&files).unwrap();
+        assert!(dir.path().join("bills.commit").exists());
+        fs::rename(
+            dir.path().join("bills.splits.csv.tmp"),
+            dir.path().join("bills.splits.csv"),
+        )
+        .unwrap();
+        assert_eq!(store.load().unwrap(), sample_ledger());
+        assert!(!dir.path().join("bills.commit").exists());
+        assert!(!dir.path().join("bills.csv.tmp").exists());
+
+        // Without the commit file the save never happened.
+        for (path, data) in store.files(&before).unwrap() {
+            fs::write(tmp_path(&path), data).unwrap();
+        }
+        assert_eq!(store.load().unwrap(), sample_ledger());
+        store.save(&before).unwrap();
+        assert_eq!(store.load().unwrap(), before);
+    }
+
+    #[test]
+    fn encrypted_files_need_the_right_passphrase() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bills.csv");
+        let open = |passphrase: Option<&str>| {
+            FileStore::new(&path, Format::Csv).with_passphrase(passphrase.map(str::to_owned))
+        };
+        let mut store = open(Some("hunter2"));
+        store.save(&sample_ledger()).unwrap();
+        store
+            .save_with_log(&sample_ledger(), &Bills::new())
+            .unwrap();
+        assert!(is_encrypted(&path));
+        assert!(is_encrypted(&dir.path().join("bills.rates.csv")));
+        assert!(!fs::read(store.log_path())
+            .unwrap()
+            .windows(4)
+            .any(|window| window == b"Rent"));
+        assert_eq!(open(Some("hunter2")).load().unwrap(), sample_ledger());
+        assert_eq!(open(Some("hunter2")).load_log().unwrap().len(), 2);
+
+        let err = open(Some("hunter3")).load().unwrap_err().to_string();
+        assert!(err.contains("wrong passphrase"), "{err}");
+        let err = open(None).load().unwrap_err().to_string();
+        assert!(
+            err.contains("encrypted, but no passphrase was given"),
+            "{err}"
+        );
+
+        store
+            .change_passphrase(&sample_ledger(), Some("hunter3".to_owned()))
+            .unwrap();
+        assert_eq!(open(Some("hunter3")).load().unwrap(), sample_ledger());
+        assert!(open(Some("hunter2")).load().is_err());
+        store.change_passphrase(&sample_ledger(), None).unwrap();
+        assert!(!is_encrypted(&path));
+        assert_eq!(open(None).load_log().unwrap().len(), 2);
+
+        let err = open(Some("hunter2")).load().unwrap_err().to_string();
+        assert!(
+            err.contains("not encrypted, but a passphrase was given"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn changing_the_passphrase_encrypts_attached_files_too() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = FileStore::new(dir.path().join("bills.json"), Format::Json);
+        let ledger = sample_ledger();
+        store.save(&ledger).unwrap();
+        let receipt = ledger.attachments[0].path(&store.attachments_dir());
+        fs::create_dir(store.attachments_dir()).unwrap();
+        fs::write(&receipt, b"test").unwrap();
+
+        store
+            .change_passphrase(&ledger, Some("hunter2".to_owned()))
+            .unwrap();
+        assert!(crypto::is_encrypted(&fs::read(&receipt).unwrap()));
+        let out = dir.path().join("out.pdf");
+        let attachment = &ledger.attachments[0];
+        attachment::export(&store.attachments_dir(), attachment, &out, store.sealer()).unwrap();
+        assert_eq!(fs::read(&out).unwrap(), b"test");
+
+        store.change_passphrase(&ledger, None).unwrap();
+        assert_eq!(fs::read(&receipt).unwrap(), b"test");
+        assert!(!store.commit_path().exists());
+    }
+
+    #[test]
+    fn a_failed_passphrase_change_keeps_the_old_passphrase() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bills.csv");
+        let mut store = FileStore::new(&path, Format::Csv).with_passphrase(Some("old".to_owned()));
+        store
+            .save_with_log(&sample_ledger(), &Bills::new())
+            .unwrap();
+        // A directory where a temporary file should go makes staging fail
+        // half way through.
+        fs::create_dir(dir.path().join("bills.csv.tmp")).unwrap();
+
+        assert!(store
+            .change_passphrase(&sample_ledger(), Some("new".to_owned()))
+            .is_err());
+        assert_eq!(store.passphrase(), Some("old"));
+        let old = FileStore::new(&path, Format::Csv).with_passphrase(Some("old".to_owned()));
+        assert_eq!(old.load().unwrap(), sample_ledger());
+        assert_eq!(old.load_log().unwrap().len(), 2);
+    }
+}
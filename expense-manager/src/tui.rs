@@ -0,0 +1,738 @@
+use std::collections::BTreeMap;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Cell, List, ListItem, ListState, Paragraph, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::bill::{parse_date, Bill, Bills, Filter, DATE_FORMAT};
+use crate::currency::{self, parse_currency};
+use crate::helper::{parse_amount, parse_title, today, DEFAULT_CATEGORY};
+use crate::history::{self, History};
+use crate::ledger::Ledger;
+use crate::storage::Store;
+
+// Rows moved by Page Up and Page Down.
+const PAGE_ROWS: usize = 10;
+
+// The id of a new bill until it's saved. Bill ids start at 1, so it never
+// matches a saved bill.
+const DRAFT_ID: u32 = 0;
+
+// The columns that can be edited in place, in the order Tab visits them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Title,
+    Amount,
+    Currency,
+    Date,
+    Category,
+    Payee,
+}
+
+const FIELDS: [Field; 6] = [
+    Field::Title,
+    Field::Amount,
+    Field::Currency,
+    Field::Date,
+    Field::Category,
+    Field::Payee,
+];
+
+impl Field {
+    fn column(self) -> usize {
+        match self {
+            Field::Title => 2,
+            Field::Amount => 3,
+            Field::Currency => 4,
+            Field::Date => 1,
+            Field::Category => 5,
+            Field::Payee => 6,
+        }
+    }
+
+    fn text(self, bill: &Bill) -> String {
+        match self {
+            Field::Title => bill.title.clone(),
+            Field::Amount => bill.amount.to_string(),
+            Field::Currency => bill.currency.clone(),
+            Field::Date => bill.date.format(DATE_FORMAT).to_string(),
+            Field::Category => bill.category.clone(),
+            Field::Payee => bill.payee.clone(),
+        }
+    }
+
+    // Checks the input with the same parsers as the menu.
+    fn set(self, bill: &mut Bill, input: &str) -> Result<(), String> {
+        let input = input.trim();
+        match self {
+            Field::Title => bill.title = parse_title(input)?,
+            Field::Amount => bill.amount = parse_amount(input)?,
+            Field::Currency => bill.currency = parse_currency(input)?,
+            Field::Date => bill.date = parse_date(input)?,
+            Field::Category if input.is_empty() => bill.category = DEFAULT_CATEGORY.to_owned(),
+            Field::Category => bill.category = input.to_owned(),
+            Field::Payee => bill.payee = input.to_owned(),
+        }
+        Ok(())
+    }
+
+    fn next(self, step: isize) -> Field {
+        let index = FIELDS.iter().position(|field| *field == self).unwrap_or(0);
+        FIELDS[(index as isize + step).rem_euclid(FIELDS.len() as isize) as usize]
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Mode {
+    Browse,
+    // Focus is on the category sidebar.
+    Categories,
+    // Typing in the filter bar.
+    Filter,
+    Edit { field: Field, input: String },
+}
+
+// Everything on screen, kept apart from the terminal so it can be driven by
+// key events in tests.
+pub struct App {
+    ledger: Ledger,
+    history: History,
+    mode: Mode,
+    filter: String,
+    // None shows every category.
+    category: Option<String>,
+    table: TableState,
+    // A bill being added. It is listed and edited like the others but only
+    // joins the bills once it is saved with Enter.
+    draft: Option<Bill>,
+    message: String,
+    // Set when the bills changed and need saving.
+    dirty: bool,
+    quit: bool,
+}
+
+impl App {
+    pub fn new(ledger: Ledger) -> Self {
+        Self {
+            ledger,
+            history: History::default(),
+            mode: Mode::Browse,
+            filter: String::new(),
+            category: None,
+            table: TableState::default().with_selected(0),
+            draft: None,
+            message: String::new(),
+            dirty: false,
+            quit: false,
+        }
+    }
+
+    // The categories in use with their bill counts, ignoring case. Of the
+    // spellings of a category, the one that sorts first is shown.
+    fn categories(&self) -> Vec<(String, usize)> {
+        let mut counts: BTreeMap<String, (String, usize)> = BTreeMap::new();
+        for bill in self.ledger.bills.values() {
+            let entry = counts
+                .entry(bill.category.to_lowercase())
+                .or_insert((bill.category.clone(), 0));
+            if bill.category < entry.0 {
+                entry.0 = bill.category.clone();
+            }
+            entry.1 += 1;
+        }
+        counts.into_values().collect()
+    }
+
+    // The bills in the list: the selected category, then the filter text
+    // against the title, payee, category and notes.
+    fn visible(&self) -> Vec<&Bill> {
+        let filter = Filter {
+            category: self.category.clone(),
+            ..Filter::default()
+        };
+        let text = self.filter.to_lowercase();
+        filter
+            .apply(&self.ledger.bills)
+            .into_iter()
+            .filter(|bill| {
+                [&bill.title, &bill.payee, &bill.category, &bill.notes]
+                    .iter()
+                    .any(|field| field.to_lowercase().contains(&text))
+            })
+            .chain(&self.draft)
+            .collect()
+    }
+
+    fn selected_bill(&self) -> Option<&Bill> {
+        self.visible().get(self.table.selected()?).copied()
+    }
+
+    fn select_id(&mut self, id: u32) {
+        let index = self.visible().iter().position(|bill| bill.id == id);
+        self.table.select(index.or(Some(0)));
+    }
+
+    fn move_selection(&mut self, step: isize) {
+        let last = self.visible().len().saturating_sub(1) as isize;
+        let current = self.table.selected().unwrap_or(0) as isize;
+        self.table
+            .select(Some((current + step).clamp(0, last) as usize));
+    }
+
+    fn move_category(&mut self, step: isize) {
+        let mut choices: Vec<Option<String>> = vec![None];
+        choices.extend(self.categories().into_iter().map(|(name, _)| Some(name)));
+        let current = choices
+            .iter()
+            .position(|choice| same_category(choice, &self.category))
+            .unwrap_or(0) as isize;
+        let last = choices.len() as isize - 1;
+        self.category = choices.swap_remove((current + step).clamp(0, last) as usize);
+        self.table.select(Some(0));
+    }
+
+    // Runs an edit of the bills as one undoable step.
+    fn change(&mut self, edit: impl FnOnce(&mut Ledger) -> Result<String, String>) {
+        let before = self.ledger.bills.clone();
+        match edit(&mut self.ledger) {
+            Ok(message) => {
+                self.history
+                    .record(history::diff(&before, &self.ledger.bills));
+                self.dirty = true;
+                self.message = message;
+            }
+            Err(err) => self.message = err,
+        }
+    }
+
+    // Starts a draft with an empty title, which has to be filled in before
+    // the draft can be saved. The draft gets its real id when it's saved, so
+    // discarding it doesn't use one up.
+    fn add(&mut self) {
+        let mut bill = Bill::new(
+            DRAFT_ID,
+            "",
+            Default::default(),
+            today(),
+            self.category.as_deref().unwrap_or(DEFAULT_CATEGORY),
+        );
+        bill.currency = self.ledger.base_currency().to_owned();
+        self.draft = Some(bill);
+        // The new bill must show up to be edited.
+        self.filter.clear();
+        self.select_id(DRAFT_ID);
+        self.start_edit(Field::Title);
+    }
+
+    // Enter saves the field, and a draft with it.
+    fn save_edit(&mut self) {
+        if !self.finish_edit() {
+            return;
+        }
+        if let Some(mut bill) = self.draft.take() {
+            let id = self.ledger.next_bill_id();
+            bill.id = id;
+            self.change(|ledger| {
+                ledger.bills.insert(id, bill);
+                Ok(format!("Added bill #{id}"))
+            });
+            self.select_id(id);
+        }
+    }
+
+    fn cancel_edit(&mut self) {
+        if self.draft.take().is_some() {
+            self.message = "Discarded the new bill".to_owned();
+            self.move_selection(0);
+        }
+        self.mode = Mode::Browse;
+    }
+
+    fn start_edit(&mut self, field: Field) {
+        if let Some(bill) = self.selected_bill() {
+            let input = field.text(bill);
+            self.mode = Mode::Edit { field, input };
+        }
+    }
+
+    // Saves the field being edited and closes the editor. On a bad value the
+    // editor stays open and shows why.
+    fn finish_edit(&mut self) -> bool {
+        let Mode::Edit { field, input } = &self.mode else {
+            return true;
+        };
+        let (field, input) = (*field, input.clone());
+        let Some(id) = self.selected_bill().map(|bill| bill.id) else {
+            self.mode = Mode::Browse;
+            return true;
+        };
+        let mut bill = match &self.draft {
+            Some(draft) => draft.clone(),
+            None => self.ledger.bills[&id].clone(),
+        };
+        if let Err(err) = field.set(&mut bill, &input) {
+            self.message = err;
+            return false;
+        }
+        if self.draft.is_some() {
+            self.draft = Some(bill);
+        } else if bill != self.ledger.bills[&id] {
+            self.change(|ledger| {
+                ledger.bills.insert(id, bill);
+                Ok(format!("Updated bill #{id}"))
+            });
+        }
+        self.select_id(id);
+        self.mode = Mode::Browse;
+        true
+    }
+
+    fn undo(&mut self, redo: bool) {
+        let result = if redo {
+            self.history.redo(&mut self.ledger.bills)
+        } else {
+            self.history.undo(&mut self.ledger.bills)
+        };
+        self.message = match result {
+            Ok(Some(changes)) => {
+                self.dirty = true;
+                let verb = if redo { "Redid" } else { "Undid" };
+                format!("{verb} {} change(s)", changes.len())
+            }
+            Ok(None) if redo => "Nothing to redo".to_owned(),
+            Ok(None) => "Nothing to undo".to_owned(),
+            Err(err) => format!("Can't undo: {err}"),
+        };
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) {
+        self.message.clear();
+        match &mut self.mode {
+            Mode::Edit { field, input } => match key.code {
+                KeyCode::Esc => self.cancel_edit(),
+                KeyCode::Enter => self.save_edit(),
+                KeyCode::Tab | KeyCode::BackTab => {
+                    let step = if key.code == KeyCode::Tab { 1 } else { -1 };
+                    let next = field.next(step);
+                    if self.finish_edit() {
+                        self.start_edit(next);
+                    }
+                }
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Char(c) => input.push(c),
+                _ => {}
+            },
+            Mode::Filter => match key.code {
+                KeyCode::Esc => {
+                    self.filter.clear();
+                    self.mode = Mode::Browse;
+                }
+                KeyCode::Enter => self.mode = Mode::Browse,
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                }
+                KeyCode::Char(c) => self.filter.push(c),
+                _ => {}
+            },
+            Mode::Categories => match key.code {
+                KeyCode::Up | KeyCode::Char('k') => self.move_category(-1),
+                KeyCode::Down | KeyCode::Char('j') => self.move_category(1),
+                KeyCode::Tab | KeyCode::Enter | KeyCode::Esc | KeyCode::Right => {
+                    self.mode = Mode::Browse
+                }
+                KeyCode::Char('q') => self.quit = true,
+                _ => {}
+            },
+            Mode::Browse => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+                KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+                KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+                KeyCode::PageUp => self.move_selection(-(PAGE_ROWS as isize)),
+                KeyCode::PageDown => self.move_selection(PAGE_ROWS as isize),
+                KeyCode::Home | KeyCode::Char('g') => self.table.select(Some(0)),
+                KeyCode::End | KeyCode::Char('G') => self.move_selection(isize::MAX / 2),
+                KeyCode::Tab | KeyCode::Left => self.mode = Mode::Categories,
+                KeyCode::Char('/') => self.mode = Mode::Filter,
+                KeyCode::Enter | KeyCode::Char('e') => self.start_edit(Field::Title),
+                KeyCode::Char('a') => self.add(),
+                KeyCode::Char('p') => {
+                    if let Some(id) = self.selected_bill().map(|bill| bill.id) {
+                        self.change(|ledger| {
+                            let bill = ledger.bills.get_mut(&id).expect("bill is listed");
+                            bill.paid = !bill.paid;
+                            let state = if bill.paid { "paid" } else { "unpaid" };
+                            Ok(format!("Marked bill #{id} {state}"))
+                        });
+                    }
+                }
+                KeyCode::Char('d') | KeyCode::Delete => {
+                    if let Some(id) = self.selected_bill().map(|bill| bill.id) {
+                        self.change(|ledger| {
+                            ledger.remove_bill(id);
+                            Ok(format!("Removed bill #{id}, press u to undo"))
+                        });
+                    }
+                }
+                KeyCode::Char('u') => self.undo(false),
+                KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    self.undo(true)
+                }
+                _ => {}
+            },
+        }
+        // Edits can empty a category or the list.
+        let categories = self.categories();
+        if self.category.is_some()
+            && !categories
+                .iter()
+                .any(|(name, _)| same_category(&Some(name.clone()), &self.category))
+        {
+            self.category = None;
+        }
+        if self.table.selected().unwrap_or(0) >= self.visible().len() {
+            self.move_selection(0);
+            if self.visible().is_empty() {
+                self.table.select(Some(0));
+            }
+        }
+    }
+
+    // The running total of the listed bills in the base currency.
+    fn totals(&self) -> String {
+        let visible = self.visible();
+        let base = self.ledger.base_currency();
+        let unpaid: Vec<&Bill> = visible.iter().copied().filter(|bill| !bill.paid).collect();
+        match (
+            currency::total(&visible, &self.ledger.rates, base),
+            currency::total(&unpaid, &self.ledger.rates, base),
+        ) {
+            (Ok(total), Ok(unpaid_total)) => format!(
+                "{} bills   Total {:.2} {base}   Unpaid {:.2} {base}",
+                visible.len(),
+                currency::round(total),
+                currency::round(unpaid_total)
+            ),
+            (Err(err), _) | (_, Err(err)) => format!("{} bills   {err}", visible.len()),
+        }
+    }
+
+    fn help(&self) -> &'static str {
+        match self.mode {
+            Mode::Browse => "j/k move  / filter  Tab categories  Enter edit  a add  p paid  d delete  u undo  ^R redo  q quit",
+            Mode::Categories => "j/k pick a category  Tab back to the bills  q quit",
+            Mode::Filter => "Type to filter  Enter keep  Esc clear",
+            Mode::Edit { .. } => "Enter save  Tab/Shift-Tab save and move  Esc cancel",
+        }
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame) {
+        let [filter_area, body, totals_area, help_area] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(3),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [sidebar_area, table_area] =
+            Layout::horizontal([Constraint::Length(24), Constraint::Min(20)]).areas(body);
+        let focused = |on: bool| {
+            if on {
+                Style::new().yellow()
+            } else {
+                Style::new()
+            }
+        };
+
+        let cursor = if self.mode == Mode::Filter { "_" } else { "" };
+        frame.render_widget(
+            Paragraph::new(format!("{}{cursor}", self.filter)).block(
+                Block::bordered()
+                    .title("Filter (/)")
+                    .border_style(focused(self.mode == Mode::Filter)),
+            ),
+            filter_area,
+        );
+
+        let categories = self.categories();
+        let mut items = vec![ListItem::new(format!("All ({})", self.ledger.bills.len()))];
+        items.extend(
+            categories
+                .iter()
+                .map(|(name, count)| ListItem::new(format!("{name} ({count})"))),
+        );
+        let selected = categories
+            .iter()
+            .position(|(name, _)| same_category(&Some(name.clone()), &self.category))
+            .map_or(0, |index| index + 1);
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(
+                    Block::bordered()
+                        .title("Categories")
+                        .border_style(focused(self.mode == Mode::Categories)),
+                )
+                .highlight_style(Style::new().reversed()),
+            sidebar_area,
+            &mut ListState::default().with_selected(Some(selected)),
+        );
+
+        let selected = self.table.selected();
+        let rows: Vec<Row> = self
+            .visible()
+            .iter()
+            .enumerate()
+            .map(|(index, bill)| {
+                let mut cells: Vec<Cell> = [
+                    if bill.id == DRAFT_ID {
+                        "new".to_owned()
+                    } else {
+                        bill.id.to_string()
+                    },
+                    bill.date.format(DATE_FORMAT).to_string(),
+                    bill.title.clone(),
+                    format!("{:.2}", currency::round(bill.amount)),
+                    bill.currency.clone(),
+                    bill.category.clone(),
+                    bill.payee.clone(),
+                    if bill.paid { "yes" } else { "no" }.to_owned(),
+                ]
+                .into_iter()
+                .map(Cell::from)
+                .collect();
+                if let Mode::Edit { field, input } = &self.mode {
+                    if Some(index) == selected {
+                        cells[field.column()] =
+                            Cell::from(format!("{input}_")).style(Style::new().black().on_yellow());
+                    }
+                }
+                Row::new(cells)
+            })
+            .collect();
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(5),
+                Constraint::Length(10),
+                Constraint::Fill(2),
+                Constraint::Length(10),
+                Constraint::Length(4),
+                Constraint::Fill(1),
+                Constraint::Fill(1),
+                Constraint::Length(4),
+            ],
+        )
+        .header(
+            Row::new([
+                "#", "Date", "Title", "Amount", "Cur", "Category", "Payee", "Paid",
+            ])
+            .bold(),
+        )
+        .block(
+            Block::bordered()
+                .title("Bills")
+                .border_style(focused(matches!(
+                    self.mode,
+                    Mode::Browse | Mode::Edit { .. }
+                ))),
+        )
+        .row_highlight_style(Style::new().reversed());
+        frame.render_stateful_widget(table, table_area, &mut self.table);
+
+        frame.render_widget(Line::from(self.totals()).bold(), totals_area);
+        let help = if self.message.is_empty() {
+            Line::from(self.help()).dim()
+        } else {
+            Line::from(self.message.as_str())
+        };
+        frame.render_widget(help, help_area);
+    }
+}
+
+fn same_category(a: &Option<String>, b: &Option<String>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a.eq_ignore_ascii_case(b),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+// Runs the full-screen interface until the user quits. Changes are saved as
+// they are made, like in the menu.
+pub fn run(store: &dyn Store, mut ledger: Ledger) -> Result<(), String> {
+    let saved = ledger.bills.clone();
+    let generated = crate::generate_recurring(&mut ledger);
+    let mut app = App::new(ledger);
+    app.dirty = generated;
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, store, &mut app, saved);
+    ratatui::restore();
+    result
+}
+
+fn event_loop(
+    terminal: &mut DefaultTerminal,
+    store: &dyn Store,
+    app: &mut App,
+    mut saved: Bills,
+) -> Result<(), String> {
+    while !app.quit {
+        if app.dirty {
+            match store.save_with_log(&app.ledger, &saved) {
+                Ok(()) => saved = app.ledger.bills.clone(),
+                Err(err) => app.message = format!("Could not save bills: {err}"),
+            }
+            app.dirty = false;
+        }
+        terminal
+            .draw(|frame| app.draw(frame))
+            .map_err(|err| err.to_string())?;
+        if let Event::Key(key) = event::read().map_err(|err| err.to_string())? {
+            if key.kind == KeyEventKind::Press {
+                app.handle_key(key);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+    use rust_decimal_macros::dec;
+
+    fn app() -> App {
+        let mut ledger = Ledger::default();
+        for (id, title, amount, date, category) in [
+            (1, "Rent", dec!(1200), "2024-01-01", "Housing"),
+            (2, "Groceries", dec!(85.50), "2024-01-15", "Food"),
+            (3, "Dinner", dec!(40), "2024-01-20", "food"),
+        ] {
+            let bill = Bill::new(id, title, amount, parse_date(date).unwrap(), category);
+            ledger.bills.insert(id, bill);
+        }
+        App::new(ledger)
+    }
+
+    fn press(app: &mut App, keys: &[KeyCode]) {
+        for key in keys {
+            app.handle_key(KeyEvent::from(*key));
+        }
+    }
+
+    fn typing(text: &str) -> Vec<KeyCode> {
+        text.chars().map(KeyCode::Char).collect()
+    }
+
+    fn screen(app: &mut App) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(110, 12)).unwrap();
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        buffer
+            .content()
+            .chunks(buffer.area.width as usize)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn filter_and_categories_narrow_the_list_and_total() {
+        let mut app = app();
+        assert!(app.totals().starts_with("3 bills   Total 1325.50 USD"));
+        press(&mut app, &[KeyCode::Tab, KeyCode::Down]);
+        assert_eq!(app.category.as_deref(), Some("Food"));
+        assert!(app.totals().starts_with("2 bills   Total 125.50 USD"));
+        press(&mut app, &[KeyCode::Tab, KeyCode::Char('/')]);
+        press(&mut app, &typing("DIN"));
+        press(&mut app, &[KeyCode::Enter]);
+        assert_eq!(app.visible().len(), 1);
+        assert_eq!(app.selected_bill().unwrap().title, "Dinner");
+        press(&mut app, &[KeyCode::Char('/'), KeyCode::Esc]);
+        assert_eq!(app.visible().len(), 2);
+    }
+
+    #[test]
+    fn inline_edits_are_checked_and_undoable() {
+        let mut app = app();
+        press(
+            &mut app,
+            &[KeyCode::Char('j'), KeyCode::Enter, KeyCode::Tab],
+        );
+        assert_eq!(
+            app.mode,
+            Mode::Edit {
+                field: Field::Amount,
+                input: "85.50".to_owned()
+            }
+        );
+        press(&mut app, &[KeyCode::Backspace; 5]);
+        press(&mut app, &typing("-3"));
+        press(&mut app, &[KeyCode::Enter]);
+        assert_eq!(app.message, "'-3' is not a valid amount");
+        press(&mut app, &[KeyCode::Backspace, KeyCode::Backspace]);
+        press(&mut app, &typing("90"));
+        press(&mut app, &[KeyCode::Enter]);
+        assert_eq!(app.mode, Mode::Browse);
+        assert_eq!(app.ledger.bills[&2].amount, dec!(90));
+        assert!(app.dirty);
+
+        press(&mut app, &[KeyCode::Char('d')]);
+        assert!(!app.ledger.bills.contains_key(&2));
+        press(&mut app, &[KeyCode::Char('u'), KeyCode::Char('u')]);
+        assert_eq!(app.ledger.bills[&2].amount, dec!(85.50));
+    }
+
+    #[test]
+    fn add_opens_the_new_bill_for_editing() {
+        let mut app = app();
+        press(&mut app, &[KeyCode::Char('a'), KeyCode::Tab]);
+        assert_eq!(app.message, "The title can't be empty");
+        press(&mut app, &typing("Coffee"));
+        press(&mut app, &[KeyCode::Tab]);
+        press(&mut app, &[KeyCode::Backspace; 4]);
+        press(&mut app, &typing("-3"));
+        press(&mut app, &[KeyCode::Enter]);
+        assert_eq!(app.message, "'-3' is not a valid amount");
+        assert!(!app.ledger.bills.contains_key(&4));
+        press(&mut app, &[KeyCode::Backspace, KeyCode::Backspace]);
+        press(&mut app, &typing("3"));
+        press(&mut app, &[KeyCode::Enter]);
+        assert_eq!(app.mode, Mode::Browse);
+        assert_eq!(app.ledger.bills[&4].title, "Coffee");
+        assert_eq!(app.ledger.bills[&4].amount, dec!(3));
+        assert_eq!(app.ledger.bills[&4].category, DEFAULT_CATEGORY);
+        let screen = screen(&mut app);
+        assert!(screen.contains("Coffee"), "{screen}");
+        assert!(screen.contains("4 bills   Total 1328.50 USD"), "{screen}");
+        assert!(screen.contains("uncategorized (1)"), "{screen}");
+
+        press(&mut app, &[KeyCode::Char('u')]);
+        assert!(!app.ledger.bills.contains_key(&4));
+    }
+
+    #[test]
+    fn cancelling_an_add_leaves_the_bills_alone() {
+        let mut app = app();
+        press(&mut app, &[KeyCode::Char('a')]);
+        press(&mut app, &typing("Coffee"));
+        press(&mut app, &[KeyCode::Tab, KeyCode::Esc]);
+        assert_eq!(app.message, "Discarded the new bill");
+        assert_eq!(app.ledger.bills.len(), 3);
+        assert_eq!(app.visible().len(), 3);
+        assert!(!app.dirty);
+        assert_eq!(app.selected_bill().unwrap().title, "Dinner");
+
+        press(&mut app, &[KeyCode::Char('a')]);
+        press(&mut app, &typing("Tea"));
+        press(&mut app, &[KeyCode::Enter]);
+        assert_eq!(app.message, "Added bill #4");
+        assert_eq!(app.ledger.bills[&4].title, "Tea");
+    }
+}
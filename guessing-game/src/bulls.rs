@@ -0,0 +1,122 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::puzzle::{Puzzle, Verdict};
+
+pub const LENGTH: usize = 4;
+const ATTEMPTS: u32 = 10;
+
+// Bulls and Cows: guess a code of different digits. A bull is a right digit
+// in the right place, a cow a right digit in another place.
+#[derive(Debug, Clone)]
+pub struct BullsAndCows {
+    code: Vec<u8>,
+}
+
+impl BullsAndCows {
+    pub fn new(rng: &mut impl Rng) -> BullsAndCows {
+        let mut digits: Vec<u8> = (0..10).collect();
+        digits.shuffle(rng);
+        digits.truncate(LENGTH);
+        BullsAndCows { code: digits }
+    }
+
+    pub fn with_code(code: &str) -> Result<BullsAndCows, String> {
+        Ok(BullsAndCows {
+            code: digits(code, code.len())?,
+        })
+    }
+}
+
+// The digits of `code`, which must be `length` different digits.
+fn digits(code: &str, length: usize) -> Result<Vec<u8>, String> {
+    let problem = || format!("Please type {length} different digits!");
+    let digits: Vec<u8> = code
+        .chars()
+        .map(|c| c.to_digit(10).map(|digit| digit as u8))
+        .collect::<Option<_>>()
+        .ok_or_else(problem)?;
+    let different = digits
+        .iter()
+        .enumerate()
+        .all(|(i, digit)| !digits[..i].contains(digit));
+    if digits.len() != length || length == 0 || !different {
+        return Err(problem());
+    }
+    Ok(digits)
+}
+
+// Bulls and cows of `guess` against `code`, both of different digits.
+pub fn count(code: &[u8], guess: &[u8]) -> (usize, usize) {
+    let bulls = code.iter().zip(guess).filter(|(a, b)| a == b).count();
+    let common = guess.iter().filter(|digit| code.contains(digit)).count();
+    (bulls, common - bulls)
+}
+
+fn plural(count: usize, word: &str) -> String {
+    if count == 1 {
+        format!("1 {word}")
+    } else {
+        format!("{count} {word}s")
+    }
+}
+
+impl Puzzle for BullsAndCows {
+    fn intro(&self) -> String {
+        format!(
+            "Guess the code of {} different digits!\n\
+             A bull is a right digit in the right place, a cow one in another place.",
+            self.code.len()
+        )
+    }
+
+    fn name(&self) -> &str {
+        "code"
+    }
+
+    fn secret(&self) -> String {
+        self.code.iter().map(|digit| digit.to_string()).collect()
+    }
+
+    fn limit(&self) -> Option<u32> {
+        Some(ATTEMPTS)
+    }
+
+    fn guess(&mut self, guess: &str) -> Result<Verdict, String> {
+        let guess = digits(guess, self.code.len())?;
+        let (bulls, cows) = count(&self.code, &guess);
+        Ok(Verdict {
+            solved: bulls == self.code.len(),
+            hint: format!("{}, {}", plural(bulls, "bull"), plural(cows, "cow")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn bulls_and_cows_are_counted() {
+        let mut puzzle = BullsAndCows::with_code("1234").unwrap();
+        assert_eq!(puzzle.guess("5678").unwrap().hint, "0 bulls, 0 cows");
+        assert_eq!(puzzle.guess("1243").unwrap().hint, "2 bulls, 2 cows");
+        assert_eq!(puzzle.guess("4519").unwrap().hint, "0 bulls, 2 cows");
+        assert_eq!(puzzle.guess("1567").unwrap().hint, "1 bull, 0 cows");
+        assert!(puzzle.guess("1234").unwrap().solved);
+
+        for bad in ["123", "12345", "1123", "12a4", ""] {
+            assert_eq!(
+                puzzle.guess(bad),
+                Err("Please type 4 different digits!".to_owned())
+            );
+        }
+        assert!(BullsAndCows::with_code("1223").is_err());
+
+        let code = BullsAndCows::new(&mut StdRng::seed_from_u64(7)).secret();
+        assert!(BullsAndCows::with_code(&code).is_ok());
+        assert_eq!(code.len(), LENGTH);
+    }
+}
@@ -0,0 +1,66 @@
+use std::cmp::Ordering;
+use std::io::{self, BufRead, Write};
+
+use crate::game::Settings;
+use crate::solver::{self, Solver};
+
+// How a guess compares with the secret, as a player answers it.
+pub fn parse_feedback(answer: &str) -> Option<Ordering> {
+    match answer.trim().to_lowercase().as_str() {
+        "s" | "small" | "too small" | "<" => Some(Ordering::Less),
+        "b" | "big" | "too big" | ">" => Some(Ordering::Greater),
+        "y" | "yes" | "r" | "right" | "=" => Some(Ordering::Equal),
+        _ => None,
+    }
+}
+
+// The player thinks of a number in `settings`' range and the program guesses
+// it. Returns the number, or None if the answers contradict each other or
+// the input ran out.
+pub fn reverse(
+    settings: Settings,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> io::Result<Option<u32>> {
+    writeln!(
+        output,
+        "Think of a number between {} and {}. I need at most {} guesses.",
+        settings.min,
+        settings.max,
+        solver::worst_case(settings.min, settings.max)
+    )?;
+    let mut solver = Solver::new(settings.min, settings.max);
+    loop {
+        let guess = solver.guess();
+        let ordering = loop {
+            writeln!(
+                output,
+                "I guess {guess}. Too small, too big or right? (s/b/y)"
+            )?;
+            let mut answer = String::new();
+            if input.read_line(&mut answer)? == 0 {
+                writeln!(output, "Bye!")?;
+                return Ok(None);
+            }
+            match parse_feedback(&answer) {
+                Some(ordering) => break ordering,
+                None => writeln!(output, "Please answer s, b or y!")?,
+            }
+        };
+        match solver.feedback(ordering) {
+            Ok(Some(secret)) => {
+                writeln!(
+                    output,
+                    "Your number is {secret}, found in {} guesses!",
+                    solver.guesses()
+                )?;
+                return Ok(Some(secret));
+            }
+            Ok(None) => {}
+            Err(contradiction) => {
+                writeln!(output, "{contradiction}.")?;
+                return Ok(None);
+            }
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use clap::ValueEnum;
+use rand::Rng;
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn settings(self) -> Settings {
+        match self {
+            Difficulty::Easy => Settings {
+                min: 1,
+                max: 50,
+                attempts: Some(10),
+            },
+            Difficulty::Normal => Settings {
+                min: 1,
+                max: 100,
+                attempts: Some(7),
+            },
+            Difficulty::Hard => Settings {
+                min: 1,
+                max: 1000,
+                attempts: Some(10),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Settings {
+    pub min: u32,
+    pub max: u32,
+    // None means unlimited.
+    pub attempts: Option<u32>,
+}
+
+impl Settings {
+    pub fn new(min: u32, max: u32, attempts: Option<u32>) -> Result<Settings, String> {
+        if min >= max {
+            return Err(format!(
+                "The range {min}..={max} needs at least two numbers"
+            ));
+        }
+        if attempts == Some(0) {
+            return Err("At least one attempt is needed".to_owned());
+        }
+        Ok(Settings { min, max, attempts })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Playing,
+    Won,
+    Lost,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuessOutcome {
+    // The secret is bigger, or smaller, than the guess.
+    TooSmall,
+    TooBig,
+    Won { attempts: u32 },
+    // The last attempt was wrong too.
+    Lost { secret: u32 },
+    // Outside the range; doesn't cost an attempt.
+    OutOfRange,
+    // The game had already ended.
+    Over,
+}
+
+#[derive(Debug, Clone)]
+pub struct Game {
+    settings: Settings,
+    secret: u32,
+    attempts: u32,
+    state: State,
+}
+
+impl Game {
+    // Picks the secret with `rng`, so a seeded RNG gives a known game.
+    pub fn new(settings: Settings, rng: &mut impl Rng) -> Game {
+        let secret = rng.gen_range(settings.min..=settings.max);
+        Game::with_secret(settings, secret)
+    }
+
+    pub fn with_secret(settings: Settings, secret: u32) -> Game {
+        Game {
+            settings,
+            secret,
+            attempts: 0,
+            state: State::Playing,
+        }
+    }
+
+    pub fn settings(&self) -> Settings {
+        self.settings
+    }
+
+    pub fn secret(&self) -> u32 {
+        self.secret
+    }
+
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    // None when there is no limit.
+    pub fn attempts_left(&self) -> Option<u32> {
+        self.settings.attempts.map(|limit| limit - self.attempts)
+    }
+
+    pub fn guess(&mut self, guess: u32) -> GuessOutcome {
+        if self.state != State::Playing {
+            return GuessOutcome::Over;
+        }
+        if guess < self.settings.min || guess > self.settings.max {
+            return GuessOutcome::OutOfRange;
+        }
+        self.attempts += 1;
+        match guess.cmp(&self.secret) {
+            Ordering::Equal => {
+                self.state = State::Won;
+                GuessOutcome::Won {
+                    attempts: self.attempts,
+                }
+            }
+            _ if self.attempts_left() == Some(0) => {
+                self.state = State::Lost;
+                GuessOutcome::Lost {
+                    secret: self.secret,
+                }
+            }
+            Ordering::Less => GuessOutcome::TooSmall,
+            Ordering::Greater => GuessOutcome::TooBig,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn same_seed_same_secret() {
+        let settings = Difficulty::Hard.settings();
+        let secret = |seed| Game::new(settings, &mut StdRng::seed_from_u64(seed)).secret();
+        assert_eq!(secret(7), secret(7));
+        assert!((1..=1000).contains(&secret(7)));
+    }
+
+    #[test]
+    fn settings_are_checked() {
+        assert!(Settings::new(5, 5, None).is_err());
+        assert!(Settings::new(1, 10, Some(0)).is_err());
+        assert_eq!(
+            Settings::new(1, 10, Some(3)),
+            Ok(Settings {
+                min: 1,
+                max: 10,
+                attempts: Some(3)
+            })
+        );
+    }
+}
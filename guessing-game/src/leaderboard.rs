@@ -0,0 +1,299 @@
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use std::cmp::Reverse;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::game::{Difficulty, Settings};
+use crate::puzzle;
+
+pub const DEFAULT_FILE: &str = "leaderboard.tsv";
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+// One finished game. Stored as a tab-separated line:
+//
+//   date | difficulty | won | attempts | milliseconds | points | player
+//
+// Lines written before points were kept have no points column.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Score {
+    pub date: NaiveDate,
+    // A difficulty name, "custom" for other settings, or the name of another
+    // puzzle like "wordle".
+    pub difficulty: String,
+    pub won: bool,
+    pub attempts: u32,
+    pub time: Duration,
+    // From `puzzle::score`, 0 for a lost game.
+    pub points: u32,
+    pub player: String,
+}
+
+impl Score {
+    fn to_line(&self) -> String {
+        // The name is last and free text, so it must not break the line up.
+        let player: String = self
+            .player
+            .chars()
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .collect();
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            self.date.format(DATE_FORMAT),
+            self.difficulty,
+            if self.won { "won" } else { "lost" },
+            self.attempts,
+            self.time.as_millis(),
+            self.points,
+            player.trim()
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Score> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let (points, player) = match fields[..] {
+            [_, _, _, _, _, points, player] => (Some(points.parse().ok()?), player),
+            [_, _, _, _, _, player] => (None, player),
+            _ => return None,
+        };
+        let mut score = Score {
+            date: NaiveDate::parse_from_str(fields[0], DATE_FORMAT).ok()?,
+            difficulty: fields[1].to_owned(),
+            won: match fields[2] {
+                "won" => true,
+                "lost" => false,
+                _ => return None,
+            },
+            attempts: fields[3].parse().ok()?,
+            time: Duration::from_millis(fields[4].parse().ok()?),
+            points: 0,
+            player: player.to_owned(),
+        };
+        score.points = match points {
+            Some(points) => points,
+            None if score.won => {
+                // Older lines are scored with the limit of their difficulty.
+                let limit = Difficulty::value_variants()
+                    .iter()
+                    .map(|preset| preset.settings())
+                    .find(|&settings| difficulty(settings) == score.difficulty)
+                    .and_then(|settings| settings.attempts);
+                puzzle::score(score.attempts, limit)
+            }
+            None => 0,
+        };
+        Some(score)
+    }
+}
+
+// The difficulty a game counts under. Games with their own range or limit
+// are only compared with each other.
+pub fn difficulty(settings: Settings) -> String {
+    Difficulty::value_variants()
+        .iter()
+        .find(|difficulty| difficulty.settings() == settings)
+        .and_then(|difficulty| difficulty.to_possible_value())
+        .map_or("custom".to_owned(), |value| value.get_name().to_owned())
+}
+
+// Adds a score at the end of the file. The file is locked while writing, so
+// games finishing at the same time don't mix their lines.
+pub fn record(path: &Path, score: &Score) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.lock()?;
+    file.write_all(score.to_line().as_bytes())
+}
+
+// All scores in the file, skipping lines that can't be read. A missing file
+// is an empty leaderboard.
+pub fn load(path: &Path) -> io::Result<Vec<Score>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    file.lock_shared()?;
+    let mut scores = Vec::new();
+    for line in BufReader::new(&file).lines() {
+        if let Some(score) = Score::from_line(&line?) {
+            scores.push(score);
+        }
+    }
+    Ok(scores)
+}
+
+// The best `limit` wins of each difficulty, most points first, then fewest
+// attempts and then fastest. Difficulties come in the order easy, normal, hard, custom, and
+// then other puzzles alphabetically.
+pub fn best(scores: &[Score], limit: usize) -> Vec<(String, Vec<&Score>)> {
+    let mut names: Vec<String> = Difficulty::value_variants()
+        .iter()
+        .map(|difficulty| difficulty.settings())
+        .map(difficulty)
+        .collect();
+    names.push("custom".to_owned());
+    let mut others: Vec<String> = scores
+        .iter()
+        .map(|score| score.difficulty.clone())
+        .filter(|name| !names.contains(name))
+        .collect();
+    others.sort();
+    others.dedup();
+    names.extend(others);
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let mut wins: Vec<&Score> = scores
+                .iter()
+                .filter(|score| score.won && score.difficulty == name)
+                .collect();
+            if wins.is_empty() {
+                return None;
+            }
+            wins.sort_by_key(|score| (Reverse(score.points), score.attempts, score.time));
+            wins.truncate(limit);
+            Some((name, wins))
+        })
+        .collect()
+}
+
+pub fn show(scores: &[Score], limit: usize, output: &mut dyn Write) -> io::Result<()> {
+    let best = best(scores, limit);
+    if best.is_empty() {
+        return writeln!(output, "No games won yet.");
+    }
+    for (difficulty, wins) in best {
+        writeln!(output, "{difficulty}")?;
+        let width = wins
+            .iter()
+            .map(|score| score.player.len())
+            .max()
+            .unwrap_or(0);
+        for (place, score) in wins.iter().enumerate() {
+            writeln!(
+                output,
+                "{:>3}. {:<width$}  {:>3} points  {:>3} attempts  {:>6.1}s  {}",
+                place + 1,
+                score.player,
+                score.points,
+                score.attempts,
+                score.time.as_secs_f64(),
+                score.date.format(DATE_FORMAT)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::thread;
+
+    fn score(player: &str, difficulty: &str, won: bool, attempts: u32, millis: u64) -> Score {
+        Score {
+            date: NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+            difficulty: difficulty.to_owned(),
+            won,
+            attempts,
+            time: Duration::from_millis(millis),
+            points: if won {
+                puzzle::score(attempts, Some(10))
+            } else {
+                0
+            },
+            player: player.to_owned(),
+        }
+    }
+
+    #[test]
+    fn best_wins_per_difficulty() {
+        assert_eq!(difficulty(Difficulty::Hard.settings()), "hard");
+        assert_eq!(difficulty(Settings::new(1, 20, None).unwrap()), "custom");
+
+        let scores = vec![
+            score("ann", "normal", true, 5, 9000),
+            score("bob", "normal", true, 4, 30000),
+            score("cy", "normal", false, 1, 100),
+            score("dee", "normal", true, 4, 12000),
+            // Eve had a limit of 2, so her 2 attempts are worth less than
+            // Ivy's 3 out of 10.
+            Score {
+                points: 50,
+                ..score("eve", "custom", true, 2, 1000)
+            },
+            score("ivy", "custom", true, 3, 1000),
+            score("fay", "easy", true, 6, 5000),
+            score("gus", "wordle", true, 3, 20000),
+            score("hal", "bulls-and-cows", true, 5, 60000),
+        ];
+        let best = best(&scores, 2);
+        let names: Vec<(&str, Vec<&str>)> = best
+            .iter()
+            .map(|(difficulty, wins)| {
+                let players = wins.iter().map(|score| score.player.as_str()).collect();
+                (difficulty.as_str(), players)
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("easy", vec!["fay"]),
+                ("normal", vec!["dee", "bob"]),
+                ("custom", vec!["ivy", "eve"]),
+                ("bulls-and-cows", vec!["hal"]),
+                ("wordle", vec!["gus"])
+            ]
+        );
+
+        let mut output = Vec::new();
+        show(&scores[..1], 5, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "normal\n  1. ann   60 points    5 attempts     9.0s  2024-03-01\n"
+        );
+    }
+
+    #[test]
+    fn lines_round_trip_and_old_lines_get_points() {
+        let won = score("ann lee", "wordle", true, 3, 1500);
+        assert_eq!(Score::from_line(won.to_line().trim_end()), Some(won));
+        let old = Score::from_line("2024-03-01\tnormal\twon\t4\t1500\tann").unwrap();
+        assert_eq!(old.points, puzzle::score(4, Some(7)));
+        let old = Score::from_line("2024-03-01\tcustom\tlost\t4\t1500\tann").unwrap();
+        assert_eq!(old.points, 0);
+        assert_eq!(Score::from_line("2024-03-01\tnormal\twon\t4\t1500"), None);
+    }
+
+    #[test]
+    fn concurrent_records_all_survive() {
+        let dir = std::env::temp_dir().join(format!("leaderboard-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(DEFAULT_FILE);
+        let _ = std::fs::remove_file(&path);
+
+        let threads: Vec<_> = (0..8)
+            .map(|thread| {
+                let path = path.clone();
+                thread::spawn(move || {
+                    for game in 0..50 {
+                        let name = format!("player {thread}\twith a tab");
+                        record(&path, &score(&name, "hard", true, game, 1234)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let scores = load(&path).unwrap();
+        assert_eq!(scores.len(), 400);
+        assert!(scores[0].player.ends_with(" with a tab"));
+        assert_eq!(scores[0].time, Duration::from_millis(1234));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(load(&path).unwrap().is_empty());
+    }
+}
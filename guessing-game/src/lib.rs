@@ -0,0 +1,8 @@
+pub mod bulls;
+pub mod console;
+pub mod game;
+pub mod leaderboard;
+pub mod net;
+pub mod puzzle;
+pub mod solver;
+pub mod wordle;
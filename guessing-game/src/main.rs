@@ -1,36 +1,297 @@
-use rand::Rng;
-use std::cmp::Ordering;
-use std::io;
-
-fn main() {
-    println!("Guess the number!");
-    let secret_number = rand::thread_rng().gen_range(1..=100); // generating a random number in the range [1, 100]
-    println!("The secret number is: {}", secret_number);
-    loop {
-        println!("Please input your guess.");
-
-        let mut guess = String::new();
-        io::stdin()
-            .read_line(&mut guess)
-            .expect("Failed to take input");
-
-        let guess: u32 = match guess.trim().parse() {
-            Ok(num) => num,
-            Err(_) => {
-                println!("Please type a number!");
-                continue;
+use clap::{Parser, ValueEnum};
+use guessing_game::bulls::BullsAndCows;
+use guessing_game::console;
+use guessing_game::game::{Difficulty, Game, Settings, State};
+use guessing_game::leaderboard::{self, Score};
+use guessing_game::net;
+use guessing_game::puzzle::{self, Puzzle};
+use guessing_game::wordle::Wordle;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::io::{self, BufReader};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Instant;
+
+// Places shown per difficulty by --scores.
+const SCORES_SHOWN: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Mode {
+    Number,
+    BullsAndCows,
+    Wordle,
+}
+
+/// Guess the secret number.
+#[derive(Debug, Parser)]
+#[command(name = "guessing-game")]
+struct Args {
+    /// What to guess
+    #[arg(
+        long,
+        value_enum,
+        default_value = "number",
+        conflicts_with_all = ["reverse", "serve", "join"]
+    )]
+    puzzle: Mode,
+    #[arg(long, value_enum, default_value = "normal")]
+    difficulty: Difficulty,
+    /// Smallest possible number [default: from the difficulty]
+    #[arg(long)]
+    min: Option<u32>,
+    /// Largest possible number [default: from the difficulty]
+    #[arg(long)]
+    max: Option<u32>,
+    /// Guesses allowed, 0 for no limit [default: from the difficulty]
+    #[arg(long)]
+    attempts: Option<u32>,
+    /// Show the secret
+    #[arg(long)]
+    debug: bool,
+    /// Pick the same secret every time
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Name on the leaderboard [default: your user name]
+    #[arg(long)]
+    name: Option<String>,
+    /// Show the best scores per difficulty and puzzle instead of playing
+    #[arg(long)]
+    scores: bool,
+    /// Leaderboard file
+    #[arg(long, default_value = leaderboard::DEFAULT_FILE)]
+    leaderboard: PathBuf,
+    /// Think of a number and let the computer guess it
+    #[arg(long, conflicts_with_all = ["serve", "join"])]
+    reverse: bool,
+    /// Host a multiplayer round on an address like 0.0.0.0:7878
+    #[arg(long, conflicts_with = "join")]
+    serve: Option<String>,
+    /// Players the hosted round waits for
+    #[arg(
+        long,
+        default_value_t = 2,
+        requires = "serve",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    players: usize,
+    /// Join the multiplayer round hosted at an address like 192.168.1.5:7878
+    #[arg(long)]
+    join: Option<String>,
+}
+
+impl Args {
+    fn player(&self) -> String {
+        self.name
+            .clone()
+            .or_else(|| std::env::var("USER").ok())
+            .or_else(|| std::env::var("USERNAME").ok())
+            .unwrap_or_else(|| "anonymous".to_owned())
+    }
+
+    fn settings(&self) -> Result<Settings, String> {
+        let preset = self.difficulty.settings();
+        let attempts = match self.attempts {
+            Some(0) => None,
+            Some(attempts) => Some(attempts),
+            None => preset.attempts,
+        };
+        Settings::new(
+            self.min.unwrap_or(preset.min),
+            self.max.unwrap_or(preset.max),
+            attempts,
+        )
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    if args.scores {
+        let shown = leaderboard::load(&args.leaderboard)
+            .and_then(|scores| leaderboard::show(&scores, SCORES_SHOWN, &mut io::stdout()));
+        return match shown {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("Could not read {}: {err}", args.leaderboard.display());
+                ExitCode::FAILURE
             }
-        }; // Converting a string to integer
-        println!("You guessed: {}", guess);
-
-        // Comparing two numbers
-        match guess.cmp(&secret_number) {
-            Ordering::Less => println!("Too small!"),
-            Ordering::Equal => {
-                println!("You guess it right!");
-                break;
+        };
+    }
+    let settings = match args.settings() {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::from(2);
+        }
+    };
+    if args.reverse {
+        return match console::reverse(settings, &mut io::stdin().lock(), &mut io::stdout()) {
+            Ok(Some(_)) => ExitCode::SUCCESS,
+            Ok(None) => ExitCode::FAILURE,
+            Err(err) => {
+                eprintln!("Input or output failed: {err}");
+                ExitCode::FAILURE
             }
-            Ordering::Greater => println!("Too big!"),
+        };
+    }
+    let mut rng = match args.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let game = Game::new(settings, &mut rng);
+    if let Some(address) = &args.serve {
+        return host(address, &args, game);
+    }
+    if let Some(address) = &args.join {
+        // Names are sent as one word.
+        let name = args.player().replace(char::is_whitespace, "_");
+        let played = TcpStream::connect(address).and_then(|stream| {
+            net::join(
+                stream,
+                &name,
+                BufReader::new(io::stdin()),
+                &mut io::stdout(),
+            )
+        });
+        return match played {
+            Ok(Some(winner)) if winner == name => ExitCode::SUCCESS,
+            Ok(_) => ExitCode::FAILURE,
+            Err(err) => {
+                eprintln!("Could not play at {address}: {err}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+    // Number games count under their difficulty, the others under their
+    // puzzle name.
+    let (mut puzzle, difficulty): (Box<dyn Puzzle>, String) = match args.puzzle {
+        Mode::Number => (Box::new(game), leaderboard::difficulty(settings)),
+        Mode::BullsAndCows => (
+            Box::new(BullsAndCows::new(&mut rng)),
+            "bulls-and-cows".to_owned(),
+        ),
+        Mode::Wordle => (Box::new(Wordle::new(&mut rng)), "wordle".to_owned()),
+    };
+    let started = Instant::now();
+    let outcome = match puzzle::play(
+        puzzle.as_mut(),
+        &mut io::stdin().lock(),
+        &mut io::stdout(),
+        args.debug,
+    ) {
+        Ok(outcome) => outcome,
+        Err(err) => {
+            eprintln!("Input or output failed: {err}");
+            return ExitCode::FAILURE;
         }
+    };
+    let state = outcome.state;
+    if state == State::Playing {
+        return ExitCode::FAILURE;
+    }
+
+    // Games where the secret was known don't count.
+    if args.debug || args.seed.is_some() {
+        println!("Not recorded on the leaderboard: the secret was known.");
+    } else {
+        let score = Score {
+            date: chrono::Local::now().date_naive(),
+            difficulty,
+            won: state == State::Won,
+            attempts: outcome.attempts,
+            time: started.elapsed(),
+            points: outcome.score,
+            player: args.player(),
+        };
+        if let Err(err) = leaderboard::record(&args.leaderboard, &score) {
+            eprintln!(
+                "Could not record the score in {}: {err}",
+                args.leaderboard.display()
+            );
+        }
+    }
+    if state == State::Won {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn host(address: &str, args: &Args, game: Game) -> ExitCode {
+    let listener = match TcpListener::bind(address) {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("Could not listen on {address}: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    println!("Waiting for {} players on {address}", args.players);
+    if args.debug {
+        println!("The secret number is: {}", game.secret());
+    }
+    let settings = game.settings();
+    match net::serve(
+        &listener,
+        settings,
+        game.secret(),
+        args.players,
+        &mut io::stdout(),
+    ) {
+        Ok(Some(winner)) => {
+            println!("{winner} won! The secret number was {}.", game.secret());
+            ExitCode::SUCCESS
+        }
+        Ok(None) => {
+            println!("Nobody won. The secret number was {}.", game.secret());
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("The round failed: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn settings(args: &[&str]) -> Result<Settings, String> {
+        Args::try_parse_from([&["guessing-game"], args].concat())
+            .unwrap()
+            .settings()
+    }
+
+    #[test]
+    fn flags_override_the_difficulty() {
+        assert_eq!(
+            settings(&[]),
+            Ok(Settings {
+                min: 1,
+                max: 100,
+                attempts: Some(7)
+            })
+        );
+        assert_eq!(
+            settings(&["--difficulty", "hard", "--max", "500", "--attempts", "0"]),
+            Ok(Settings {
+                min: 1,
+                max: 500,
+                attempts: None
+            })
+        );
+        assert!(settings(&["--min", "10", "--max", "10"]).is_err());
+        assert!(Args::try_parse_from(["guessing-game", "--difficulty", "insane"]).is_err());
+        assert!(
+            Args::try_parse_from(["guessing-game", "--puzzle", "wordle", "--reverse"]).is_err()
+        );
+        assert!(Args::try_parse_from(["guessing-game", "--reverse"]).is_ok());
+        assert!(
+            Args::try_parse_from(["guessing-game", "--serve", ":7878", "--players", "0"]).is_err()
+        );
+        assert!(
+            Args::try_parse_from(["guessing-game", "--serve", ":7878", "--players", "1"]).is_ok()
+        );
     }
 }
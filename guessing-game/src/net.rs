@@ -0,0 +1,310 @@
+// Several players racing for the same secret number over TCP.
+//
+// The protocol is one command per line:
+//
+//   client: JOIN <name>            server: START <min> <max>
+//   client: GUESS <number>         server: LESS | GREATER | EQUAL | OUT
+//                                  server: WINNER <name> <secret>
+//                                  server: NOBODY <secret>
+//                                  server: ERROR <message>
+//
+// LESS and GREATER compare the guess with the secret, like `Ordering`: LESS
+// means the guess is too small. OUT means the player has no attempts left.
+// The whole JOIN line must arrive within JOIN_TIMEOUT of connecting, so a
+// client that stays silent, or sends it a byte at a time, can't hold up the
+// lobby for long. A player who doesn't take a message within WRITE_TIMEOUT
+// is hung up on, so they can't stall the round for the others.
+// The round starts once every player has joined, and WINNER goes to everyone
+// when the first correct guess arrives, after which the server hangs up.
+// NOBODY ends the round when every player is out of attempts or gone.
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::game::{Game, GuessOutcome, Settings};
+
+pub const JOIN_TIMEOUT: Duration = Duration::from_secs(5);
+pub const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Round {
+    winner: Option<String>,
+    // One per player, to send replies and the result.
+    streams: Vec<TcpStream>,
+    // Players who are out of attempts or gone.
+    done: Vec<bool>,
+}
+
+impl Round {
+    fn send(&mut self, player: usize, line: &str) {
+        // A player who left just misses the message. One who stopped reading
+        // is hung up on, which ends their `play` as if they had left.
+        let stream = &mut self.streams[player];
+        if writeln!(stream, "{line}").is_err() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+
+    fn finish(&mut self, winner: &str, secret: u32) {
+        self.winner = Some(winner.to_owned());
+        self.end(&format!("WINNER {winner} {secret}"));
+    }
+
+    fn drop_out(&mut self, player: usize, secret: u32) {
+        self.done[player] = true;
+        if self.winner.is_none() && self.done.iter().all(|&done| done) {
+            self.end(&format!("NOBODY {secret}"));
+        }
+    }
+
+    fn end(&mut self, line: &str) {
+        for stream in &mut self.streams {
+            let _ = writeln!(stream, "{line}");
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+// Reads the JOIN line, giving up once JOIN_TIMEOUT has passed since `start`
+// however the line is split up.
+fn read_join(reader: &mut BufReader<TcpStream>, start: Instant) -> io::Result<String> {
+    let deadline = start + JOIN_TIMEOUT;
+    let mut line = Vec::new();
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "timed out"));
+        }
+        reader.get_ref().set_read_timeout(Some(left))?;
+        let available = reader.fill_buf()?;
+        if available.is_empty() {
+            break;
+        }
+        match available.iter().position(|&byte| byte == b'\n') {
+            Some(end) => {
+                line.extend_from_slice(&available[..=end]);
+                reader.consume(end + 1);
+                break;
+            }
+            None => {
+                let read = available.len();
+                line.extend_from_slice(available);
+                reader.consume(read);
+            }
+        }
+    }
+    String::from_utf8(line).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+// Waits for `players` players to join, plays the round and returns the
+// winner, or None if everybody left or ran out of attempts.
+pub fn serve(
+    listener: &TcpListener,
+    settings: Settings,
+    secret: u32,
+    players: usize,
+    log: &mut dyn Write,
+) -> io::Result<Option<String>> {
+    let mut joined: Vec<(String, BufReader<TcpStream>)> = Vec::new();
+    while joined.len() < players {
+        let (mut stream, address) = listener.accept()?;
+        let start = Instant::now();
+        // Clones share the timeout, so it covers every write to the player.
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let line = match read_join(&mut reader, start) {
+            Ok(line) => line,
+            Err(err) => {
+                writeln!(log, "No JOIN from {address}: {err}")?;
+                let _ = writeln!(stream, "ERROR send JOIN <name> when connecting");
+                continue;
+            }
+        };
+        let name = match line.trim().strip_prefix("JOIN ").map(str::trim) {
+            Some(name) if !name.is_empty() && !name.contains(' ') => name.to_owned(),
+            _ => {
+                let _ = writeln!(stream, "ERROR send JOIN <name> first, without spaces");
+                continue;
+            }
+        };
+        if joined.iter().any(|(other, _)| *other == name) {
+            let _ = writeln!(stream, "ERROR the name {name} is taken");
+            continue;
+        }
+        stream.set_read_timeout(None)?;
+        writeln!(log, "{name} joined from {address}")?;
+        joined.push((name, reader));
+    }
+
+    let streams = joined
+        .iter()
+        .map(|(_, reader)| reader.get_ref().try_clone())
+        .collect::<io::Result<Vec<_>>>()?;
+    let round = Arc::new(Mutex::new(Round {
+        winner: None,
+        streams,
+        done: vec![false; players],
+    }));
+    for player in 0..players {
+        round
+            .lock()
+            .unwrap()
+            .send(player, &format!("START {} {}", settings.min, settings.max));
+    }
+    writeln!(log, "The round has started")?;
+
+    let threads: Vec<_> = joined
+        .into_iter()
+        .enumerate()
+        .map(|(player, (name, reader))| {
+            let round = Arc::clone(&round);
+            thread::spawn(move || play(player, &name, reader, &round, settings, secret))
+        })
+        .collect();
+    for thread in threads {
+        let _ = thread.join();
+    }
+    let winner = round.lock().unwrap().winner.clone();
+    Ok(winner)
+}
+
+// Answers one player's guesses until the round is over or they leave.
+fn play(
+    player: usize,
+    name: &str,
+    reader: BufReader<TcpStream>,
+    round: &Mutex<Round>,
+    settings: Settings,
+    secret: u32,
+) {
+    let mut game = Game::with_secret(settings, secret);
+    for line in reader.lines() {
+        let Ok(line) = line else { break };
+        // Locked while answering, so only the first correct guess wins.
+        let mut round = round.lock().unwrap();
+        if round.winner.is_some() {
+            break;
+        }
+        let guess = match line.trim().strip_prefix("GUESS ").map(|n| n.trim().parse()) {
+            Some(Ok(guess)) => guess,
+            _ => {
+                round.send(player, "ERROR send GUESS <number>");
+                continue;
+            }
+        };
+        match game.guess(guess) {
+            GuessOutcome::TooSmall => round.send(player, "LESS"),
+            GuessOutcome::TooBig => round.send(player, "GREATER"),
+            GuessOutcome::Won { .. } => {
+                round.send(player, "EQUAL");
+                round.finish(name, secret);
+            }
+            GuessOutcome::Lost { .. } => {
+                round.send(player, "OUT");
+                round.drop_out(player, secret);
+            }
+            GuessOutcome::Over => round.send(player, "OUT"),
+            GuessOutcome::OutOfRange => round.send(
+                player,
+                &format!("ERROR guess between {} and {}", settings.min, settings.max),
+            ),
+        }
+    }
+    round.lock().unwrap().drop_out(player, secret);
+}
+
+enum Event {
+    Input(String),
+    Server(String),
+    Closed,
+}
+
+// Joins a round as `name`, sending the guesses typed into `input` and
+// printing what the server says. Returns the winner.
+pub fn join(
+    stream: TcpStream,
+    name: &str,
+    input: impl BufRead + Send + 'static,
+    output: &mut dyn Write,
+) -> io::Result<Option<String>> {
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    writeln!(writer, "JOIN {name}")?;
+    writeln!(output, "Waiting for the other players...")?;
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    match line.trim().split_once(' ') {
+        Some(("START", range)) => {
+            let (min, max) = range.split_once(' ').unwrap_or((range, "?"));
+            writeln!(output, "Guess the number between {min} and {max}!")?;
+        }
+        Some(("ERROR", message)) => return Err(io::Error::other(message.to_owned())),
+        _ => return Ok(None),
+    }
+
+    // The player may still be typing when somebody else wins, so input and
+    // server messages are read on their own threads.
+    let (events, received) = mpsc::channel();
+    let input_events = events.clone();
+    thread::spawn(move || {
+        for line in input.lines().map_while(Result::ok) {
+            if input_events.send(Event::Input(line)).is_err() {
+                break;
+            }
+        }
+    });
+    thread::spawn(move || {
+        for line in reader.lines().map_while(Result::ok) {
+            if events.send(Event::Server(line)).is_err() {
+                return;
+            }
+        }
+        let _ = events.send(Event::Closed);
+    });
+
+    let mut typed = VecDeque::new();
+    let mut waiting = false;
+    for event in received {
+        match event {
+            Event::Input(line) => typed.push_back(line),
+            Event::Server(line) => {
+                waiting = false;
+                match line.split_once(' ').unwrap_or((&line, "")) {
+                    ("LESS", _) => writeln!(output, "Too small!")?,
+                    ("GREATER", _) => writeln!(output, "Too big!")?,
+                    ("EQUAL", _) => writeln!(output, "You guess it right!")?,
+                    ("OUT", _) => writeln!(output, "Out of attempts! Wait for the others.")?,
+                    ("ERROR", message) => writeln!(output, "Server: {message}")?,
+                    ("WINNER", result) => {
+                        let (winner, secret) = result.split_once(' ').unwrap_or((result, "?"));
+                        writeln!(output, "{winner} won! The secret number was {secret}.")?;
+                        return Ok(Some(winner.to_owned()));
+                    }
+                    ("NOBODY", secret) => {
+                        writeln!(output, "Nobody found it. The secret number was {secret}.")?;
+                        return Ok(None);
+                    }
+                    _ => writeln!(output, "Server: {line}")?,
+                }
+            }
+            Event::Closed => break,
+        }
+        // One guess at a time, so every reply follows its own guess.
+        while !waiting {
+            let Some(line) = typed.pop_front() else { break };
+            match line.trim().parse::<u32>() {
+                Ok(guess) => {
+                    writeln!(writer, "GUESS {guess}")?;
+                    waiting = true;
+                }
+                Err(_) => writeln!(output, "Please type a number!")?,
+            }
+        }
+    }
+    writeln!(output, "The server ended the round.")?;
+    Ok(None)
+}
@@ -0,0 +1,187 @@
+use std::io::{self, BufRead, Write};
+
+use crate::game::{Game, GuessOutcome, State};
+
+// A game where the player guesses something hidden, one line at a time.
+pub trait Puzzle {
+    // Shown before the first guess.
+    fn intro(&self) -> String;
+    // What is hidden, like "secret number" or "word".
+    fn name(&self) -> &str;
+    fn secret(&self) -> String;
+    // Guesses allowed, None for no limit.
+    fn limit(&self) -> Option<u32>;
+    // Checks a guess. Err says why it isn't a valid guess, which doesn't
+    // cost an attempt.
+    fn guess(&mut self, guess: &str) -> Result<Verdict, String>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verdict {
+    pub solved: bool,
+    // What the guess tells about the secret, shown when it isn't solved.
+    pub hint: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Outcome {
+    // Playing if the input ran out first.
+    pub state: State,
+    pub attempts: u32,
+    pub score: u32,
+}
+
+// Up to 100 points for solving on the first try, fewer for every further
+// attempt. Without a limit the points are shared among the attempts.
+pub fn score(attempts: u32, limit: Option<u32>) -> u32 {
+    match limit {
+        Some(limit) => {
+            // In u64, since limits can be as large as u32::MAX.
+            let limit = u64::from(limit.max(1));
+            let attempts = u64::from(attempts).min(limit);
+            (100 * (limit + 1 - attempts) / limit) as u32
+        }
+        None => 100 / attempts.max(1),
+    }
+}
+
+// Plays `puzzle` reading guesses line by line from `input`.
+pub fn play(
+    puzzle: &mut dyn Puzzle,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+    debug: bool,
+) -> io::Result<Outcome> {
+    writeln!(output, "{}", puzzle.intro())?;
+    if debug {
+        writeln!(output, "The {} is: {}", puzzle.name(), puzzle.secret())?;
+    }
+    let limit = puzzle.limit();
+    let mut outcome = Outcome {
+        state: State::Playing,
+        attempts: 0,
+        score: 0,
+    };
+    while outcome.state == State::Playing {
+        match limit {
+            Some(limit) => writeln!(
+                output,
+                "Please input your guess ({} left).",
+                limit - outcome.attempts
+            )?,
+            None => writeln!(output, "Please input your guess.")?,
+        }
+
+        let mut guess = String::new();
+        if input.read_line(&mut guess)? == 0 {
+            writeln!(
+                output,
+                "Bye! The {} was {}.",
+                puzzle.name(),
+                puzzle.secret()
+            )?;
+            break;
+        }
+        let guess = guess.trim();
+        let verdict = match puzzle.guess(guess) {
+            Ok(verdict) => verdict,
+            Err(problem) => {
+                writeln!(output, "{problem}")?;
+                continue;
+            }
+        };
+        outcome.attempts += 1;
+        writeln!(output, "You guessed: {guess}")?;
+        if verdict.solved {
+            outcome.state = State::Won;
+            outcome.score = score(outcome.attempts, limit);
+            writeln!(
+                output,
+                "You guess it right in {} attempts!\nScore: {}",
+                outcome.attempts, outcome.score
+            )?;
+            break;
+        }
+        writeln!(output, "{}", verdict.hint)?;
+        if limit == Some(outcome.attempts) {
+            outcome.state = State::Lost;
+            writeln!(
+                output,
+                "Out of attempts! The {} was {}.",
+                puzzle.name(),
+                puzzle.secret()
+            )?;
+        }
+    }
+    Ok(outcome)
+}
+
+impl Puzzle for Game {
+    fn intro(&self) -> String {
+        let settings = self.settings();
+        format!(
+            "Guess the number!\nIt is between {} and {}.",
+            settings.min, settings.max
+        )
+    }
+
+    fn name(&self) -> &str {
+        "secret number"
+    }
+
+    fn secret(&self) -> String {
+        Game::secret(self).to_string()
+    }
+
+    fn limit(&self) -> Option<u32> {
+        self.settings().attempts
+    }
+
+    fn guess(&mut self, guess: &str) -> Result<Verdict, String> {
+        let guess: u32 = guess
+            .parse()
+            .map_err(|_| "Please type a number!".to_owned())?;
+        let hint = |hint: &str| {
+            Ok(Verdict {
+                solved: false,
+                hint: hint.to_owned(),
+            })
+        };
+        match Game::guess(self, guess) {
+            GuessOutcome::TooSmall => hint("Too small!"),
+            GuessOutcome::TooBig => hint("Too big!"),
+            GuessOutcome::Lost { secret } if guess < secret => hint("Too small!"),
+            GuessOutcome::Lost { .. } => hint("Too big!"),
+            GuessOutcome::Won { .. } => Ok(Verdict {
+                solved: true,
+                hint: String::new(),
+            }),
+            GuessOutcome::OutOfRange => {
+                let settings = self.settings();
+                Err(format!(
+                    "Please guess between {} and {}.",
+                    settings.min, settings.max
+                ))
+            }
+            GuessOutcome::Over => Err("The game is over.".to_owned()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fewer_attempts_score_more() {
+        assert_eq!(score(1, Some(10)), 100);
+        assert_eq!(score(10, Some(10)), 10);
+        assert_eq!(score(4, Some(6)), 50);
+        assert_eq!(score(1, None), 100);
+        assert_eq!(score(3, None), 33);
+        assert_eq!(score(1, Some(50_000_000)), 100);
+        assert_eq!(score(1, Some(u32::MAX)), 100);
+        assert_eq!(score(u32::MAX, Some(u32::MAX)), 0);
+        assert_eq!(score(0, None), 100);
+    }
+}
@@ -0,0 +1,149 @@
+use std::cmp::Ordering;
+
+// Finds a number in min..=max by binary search, from how each guess compares
+// with it: Less means the guess is too small, like `guess.cmp(&secret)`.
+// Halving what is left is optimal, no strategy has a better worst case.
+#[derive(Debug, Clone)]
+pub struct Solver {
+    low: u32,
+    high: u32,
+    // The guesses that moved each bound, to explain contradictions.
+    too_small: Option<u32>,
+    too_big: Option<u32>,
+    guess: Option<u32>,
+    guesses: u32,
+}
+
+impl Solver {
+    pub fn new(min: u32, max: u32) -> Solver {
+        Solver {
+            low: min,
+            high: max,
+            too_small: None,
+            too_big: None,
+            guess: None,
+            guesses: 0,
+        }
+    }
+
+    pub fn guesses(&self) -> u32 {
+        self.guesses
+    }
+
+    // The next guess, halving the numbers still possible.
+    pub fn guess(&mut self) -> u32 {
+        let guess = self.low + (self.high - self.low) / 2;
+        self.guess = Some(guess);
+        self.guesses += 1;
+        guess
+    }
+
+    // Takes how the last guess compares with the secret. Returns the secret
+    // once it is found, or why the answers so far can't all be true.
+    pub fn feedback(&mut self, ordering: Ordering) -> Result<Option<u32>, String> {
+        let guess = self.guess.take().ok_or("Nothing was guessed yet")?;
+        match ordering {
+            Ordering::Equal => return Ok(Some(guess)),
+            Ordering::Less if guess >= self.high => {
+                return Err(self.contradiction(Some(guess), self.too_big))
+            }
+            Ordering::Greater if guess <= self.low => {
+                return Err(self.contradiction(self.too_small, Some(guess)))
+            }
+            Ordering::Less => {
+                self.low = guess + 1;
+                self.too_small = Some(guess);
+            }
+            Ordering::Greater => {
+                self.high = guess - 1;
+                self.too_big = Some(guess);
+            }
+        }
+        Ok(None)
+    }
+
+    fn contradiction(&self, too_small: Option<u32>, too_big: Option<u32>) -> String {
+        match (too_small, too_big) {
+            (Some(small), Some(big)) => {
+                format!("That can't be: {small} is too small and {big} is too big, nothing is left between them")
+            }
+            (Some(small), None) => format!("That can't be: {small} is the largest possible number"),
+            (None, Some(big)) => format!("That can't be: {big} is the smallest possible number"),
+            (None, None) => "That can't be".to_owned(),
+        }
+    }
+}
+
+// The most guesses binary search needs for min..=max, which is also the
+// least any strategy can promise.
+pub fn worst_case(min: u32, max: u32) -> u32 {
+    let count = u64::from(max - min) + 1;
+    // Guesses g cover 2^g - 1 numbers.
+    64 - count.leading_zeros()
+}
+
+// Finds the number `compare` knows about. `compare` gets a guess and says
+// how it compares with the number. Returns the number and the guesses used.
+pub fn solve(
+    min: u32,
+    max: u32,
+    mut compare: impl FnMut(u32) -> Ordering,
+) -> Result<(u32, u32), String> {
+    let mut solver = Solver::new(min, max);
+    loop {
+        let guess = solver.guess();
+        if let Some(secret) = solver.feedback(compare(guess))? {
+            return Ok((secret, solver.guesses()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn finds_every_number_within_the_worst_case() {
+        assert_eq!(worst_case(1, 100), 7);
+        assert_eq!(worst_case(1, 1000), 10);
+        assert_eq!(worst_case(1, 2), 2);
+        assert_eq!(worst_case(0, u32::MAX), 33);
+        for secret in 1..=100 {
+            let (found, guesses) = solve(1, 100, |guess| guess.cmp(&secret)).unwrap();
+            assert_eq!(found, secret);
+            assert!(guesses <= 7);
+        }
+        assert_eq!(
+            solve(0, u32::MAX, |guess| guess.cmp(&u32::MAX)),
+            Ok((u32::MAX, 33))
+        );
+    }
+
+    #[test]
+    fn inconsistent_answers_are_caught() {
+        let mut solver = Solver::new(1, 10);
+        assert_eq!(solver.guess(), 5);
+        assert_eq!(solver.feedback(Ordering::Less), Ok(None));
+        assert_eq!(solver.guess(), 8);
+        assert_eq!(solver.feedback(Ordering::Greater), Ok(None));
+        assert_eq!(solver.guess(), 6);
+        assert_eq!(solver.feedback(Ordering::Less), Ok(None));
+        assert_eq!(solver.guess(), 7);
+        assert_eq!(
+            solver.feedback(Ordering::Greater),
+            Err(
+                "That can't be: 6 is too small and 7 is too big, nothing is left between them"
+                    .to_owned()
+            )
+        );
+
+        assert_eq!(
+            solve(1, 3, |_| Ordering::Less),
+            Err("That can't be: 3 is the largest possible number".to_owned())
+        );
+        assert_eq!(
+            solve(1, 3, |_| Ordering::Greater),
+            Err("That can't be: 1 is the smallest possible number".to_owned())
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::puzzle::{Puzzle, Verdict};
+
+// Five-letter words, one per line, in alphabetical order.
+const WORDS: &str = include_str!("words.txt");
+const LENGTH: usize = 5;
+const ATTEMPTS: u32 = 6;
+
+pub fn words() -> impl Iterator<Item = &'static str> {
+    WORDS.lines()
+}
+
+// Guess a five-letter word. Each guess comes back with its letters marked.
+#[derive(Debug, Clone)]
+pub struct Wordle {
+    word: &'static str,
+}
+
+impl Wordle {
+    pub fn new(rng: &mut impl Rng) -> Wordle {
+        let words: Vec<&'static str> = words().collect();
+        Wordle {
+            word: words.choose(rng).expect("the word list is empty"),
+        }
+    }
+
+    pub fn with_word(word: &str) -> Result<Wordle, String> {
+        Ok(Wordle { word: known(word)? })
+    }
+}
+
+// `word` as it is in the word list.
+fn known(word: &str) -> Result<&'static str, String> {
+    let word = word.to_lowercase();
+    if word.len() != LENGTH || !word.chars().all(|c| c.is_ascii_lowercase()) {
+        return Err("Please type a five-letter word!".to_owned());
+    }
+    words()
+        .find(|known| *known == word)
+        .ok_or_else(|| format!("{word} is not in the word list."))
+}
+
+// Marks each letter of `guess`: uppercase if `word` has it in the same place,
+// lowercase if elsewhere and _ if not at all. A letter guessed more often
+// than `word` has it is only marked as often as `word` has it, places that
+// are right first.
+pub fn marks(word: &str, guess: &str) -> String {
+    let word = word.as_bytes();
+    let guess = guess.as_bytes();
+    let mut left = [0; 26];
+    for (w, g) in word.iter().zip(guess) {
+        if w != g {
+            left[(w - b'a') as usize] += 1;
+        }
+    }
+    word.iter()
+        .zip(guess)
+        .map(|(&w, &g)| {
+            let letter = g as char;
+            if w == g {
+                return letter.to_ascii_uppercase();
+            }
+            let left = &mut left[(g - b'a') as usize];
+            if *left > 0 {
+                *left -= 1;
+                letter
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+impl Puzzle for Wordle {
+    fn intro(&self) -> String {
+        "Guess the five-letter word!\n\
+         Letters in the right place come back in capitals, letters in another \
+         place in small letters, and letters not in the word as _."
+            .to_owned()
+    }
+
+    fn name(&self) -> &str {
+        "word"
+    }
+
+    fn secret(&self) -> String {
+        self.word.to_owned()
+    }
+
+    fn limit(&self) -> Option<u32> {
+        Some(ATTEMPTS)
+    }
+
+    fn guess(&mut self, guess: &str) -> Result<Verdict, String> {
+        let guess = known(guess)?;
+        Ok(Verdict {
+            solved: guess == self.word,
+            hint: marks(self.word, guess),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn letters_are_marked_once_per_use() {
+        assert_eq!(marks("apple", "paper"), "paPe_");
+        assert_eq!(marks("apple", "allow"), "Al___");
+        assert_eq!(marks("paper", "speed"), "_p_E_");
+        assert_eq!(marks("crane", "crane"), "CRANE");
+
+        let mut puzzle = Wordle::with_word("apple").unwrap();
+        assert_eq!(
+            puzzle.guess("Paper"),
+            Ok(Verdict {
+                solved: false,
+                hint: "paPe_".to_owned()
+            })
+        );
+        assert_eq!(
+            puzzle.guess("apples"),
+            Err("Please type a five-letter word!".to_owned())
+        );
+        assert_eq!(
+            puzzle.guess("xyzzy"),
+            Err("xyzzy is not in the word list.".to_owned())
+        );
+        assert!(puzzle.guess("APPLE").unwrap().solved);
+    }
+
+    #[test]
+    fn word_list_is_sorted_five_letter_words() {
+        let words: Vec<&str> = words().collect();
+        assert!(words.len() > 100);
+        assert!(words.iter().all(|word| known(word) == Ok(*word)));
+        assert!(words.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
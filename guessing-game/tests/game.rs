@@ -0,0 +1,141 @@
+// Whole games played through the puzzle loop with scripted input.
+
+use guessing_game::bulls::BullsAndCows;
+use guessing_game::console;
+use guessing_game::game::{Difficulty, Game, GuessOutcome, Settings, State};
+use guessing_game::puzzle::{self, Outcome, Puzzle};
+use guessing_game::wordle::Wordle;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+fn script(puzzle: &mut dyn Puzzle, input: &str) -> (Outcome, String) {
+    let mut output = Vec::new();
+    let outcome = puzzle::play(puzzle, &mut input.as_bytes(), &mut output, false).unwrap();
+    (outcome, String::from_utf8(output).unwrap())
+}
+
+#[test]
+fn winning_game() {
+    let settings = Settings::new(1, 100, Some(7)).unwrap();
+    let mut game = Game::with_secret(settings, 42);
+    let (outcome, output) = script(&mut game, "50\nforty\n0\n25\n42\n");
+    assert_eq!(outcome.state, State::Won);
+    assert_eq!(outcome.score, 71);
+    assert_eq!(game.attempts(), 3);
+    assert_eq!(
+        output,
+        "Guess the number!
+It is between 1 and 100.
+Please input your guess (7 left).
+You guessed: 50
+Too big!
+Please input your guess (6 left).
+Please type a number!
+Please input your guess (6 left).
+Please guess between 1 and 100.
+Please input your guess (6 left).
+You guessed: 25
+Too small!
+Please input your guess (5 left).
+You guessed: 42
+You guess it right in 3 attempts!
+Score: 71
+"
+    );
+}
+
+#[test]
+fn losing_game_reveals_the_secret() {
+    let settings = Settings::new(1, 10, Some(2)).unwrap();
+    let mut game = Game::with_secret(settings, 7);
+    let (outcome, output) = script(&mut game, "1\n2\n7\n");
+    assert_eq!(outcome.state, State::Lost);
+    assert!(output.ends_with("Out of attempts! The secret number was 7.\n"));
+    assert_eq!(game.guess(7), GuessOutcome::Over);
+}
+
+#[test]
+fn input_running_out_leaves_the_game_unfinished() {
+    let mut game = Game::with_secret(Settings::new(1, 10, None).unwrap(), 3);
+    let (outcome, output) = script(&mut game, "5\n");
+    assert_eq!(outcome.state, State::Playing);
+    assert!(output.contains("Please input your guess.\n"));
+    assert!(output.ends_with("Bye! The secret number was 3.\n"));
+}
+
+#[test]
+fn seeded_games_can_be_replayed() {
+    let settings = Difficulty::Normal.settings();
+    let mut first = Game::new(settings, &mut StdRng::seed_from_u64(2024));
+    let mut second = Game::new(settings, &mut StdRng::seed_from_u64(2024));
+    let guesses = format!("{}\n", first.secret());
+    assert_eq!(script(&mut first, &guesses), script(&mut second, &guesses));
+    assert_eq!(first.state(), State::Won);
+}
+
+#[test]
+fn bulls_and_cows_game() {
+    let mut code = BullsAndCows::with_code("4071").unwrap();
+    let (outcome, output) = script(&mut code, "1234\n1123\n4170\n4071\n");
+    assert_eq!(
+        outcome,
+        Outcome {
+            state: State::Won,
+            attempts: 3,
+            score: 80
+        }
+    );
+    assert!(output.contains(
+        "You guessed: 1234
+0 bulls, 2 cows
+Please input your guess (9 left).
+Please type 4 different digits!
+Please input your guess (9 left).
+You guessed: 4170
+2 bulls, 2 cows
+"
+    ));
+}
+
+#[test]
+fn wordle_game_lost() {
+    let mut word = Wordle::with_word("paper").unwrap();
+    let input = "speed\nqwert\n".to_owned() + &"apple\n".repeat(6);
+    let (outcome, output) = script(&mut word, &input);
+    assert_eq!(outcome.state, State::Lost);
+    assert_eq!(outcome.attempts, 6);
+    assert!(output.contains("You guessed: speed\n_p_E_\n"));
+    assert!(output.contains("qwert is not in the word list.\n"));
+    assert!(output.ends_with("Out of attempts! The word was paper.\n"));
+}
+
+#[test]
+fn computer_finds_the_players_number() {
+    let settings = Settings::new(1, 100, None).unwrap();
+    let mut output = Vec::new();
+    // The player thinks of 70: 50 is too small, 75 too big, 62 and 68 too
+    // small, 71 too big and 69 too small.
+    let answers = "s\nhuh\nb\ns\ns\nb\ns\ny\n";
+    let found = console::reverse(settings, &mut answers.as_bytes(), &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert_eq!(found, Some(70), "{output}");
+    assert!(output.starts_with(
+        "Think of a number between 1 and 100. I need at most 7 guesses.
+I guess 50. Too small, too big or right? (s/b/y)
+I guess 75. Too small, too big or right? (s/b/y)
+Please answer s, b or y!
+I guess 75."
+    ));
+    assert!(output.ends_with("Your number is 70, found in 7 guesses!\n"));
+}
+
+#[test]
+fn computer_notices_contradicting_answers() {
+    let settings = Settings::new(1, 3, None).unwrap();
+    let mut output = Vec::new();
+    let found = console::reverse(settings, &mut "s\ns\n".as_bytes(), &mut output).unwrap();
+    assert_eq!(found, None);
+    assert!(String::from_utf8(output)
+        .unwrap()
+        .ends_with("That can't be: 3 is the largest possible number.\n"));
+}
@@ -0,0 +1,152 @@
+// A multiplayer round with the server and scripted clients on localhost.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use guessing_game::game::Settings;
+use guessing_game::net;
+
+fn client(
+    address: String,
+    name: &'static str,
+    guesses: &'static str,
+) -> thread::JoinHandle<(Option<String>, String)> {
+    thread::spawn(move || {
+        let stream = TcpStream::connect(address).unwrap();
+        let mut output = Vec::new();
+        let winner = net::join(stream, name, guesses.as_bytes(), &mut output).unwrap();
+        (winner, String::from_utf8(output).unwrap())
+    })
+}
+
+#[test]
+fn first_correct_guess_wins_and_everyone_is_told() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let settings = Settings::new(1, 100, None).unwrap();
+    let server = thread::spawn(move || {
+        let mut log = Vec::new();
+        let winner = net::serve(&listener, settings, 42, 2, &mut log).unwrap();
+        (winner, String::from_utf8(log).unwrap())
+    });
+
+    // Bob never finds it, so Alice wins however the guesses interleave.
+    let alice = client(address.clone(), "alice", "10\nabc\n90\n42\n");
+    let bob = client(address, "bob", "50\n");
+
+    let (winner, log) = server.join().unwrap();
+    assert_eq!(winner.as_deref(), Some("alice"));
+    assert!(log.contains("alice joined") && log.contains("bob joined"));
+
+    let (winner, output) = alice.join().unwrap();
+    assert_eq!(winner.as_deref(), Some("alice"));
+    assert_eq!(
+        output,
+        "Waiting for the other players...
+Guess the number between 1 and 100!
+Too small!
+Please type a number!
+Too big!
+You guess it right!
+alice won! The secret number was 42.
+"
+    );
+    let (winner, output) = bob.join().unwrap();
+    assert_eq!(winner.as_deref(), Some("alice"));
+    assert!(output.ends_with("alice won! The secret number was 42.\n"));
+}
+
+#[test]
+fn server_speaks_the_line_protocol() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let settings = Settings::new(1, 10, Some(2)).unwrap();
+    let server = thread::spawn(move || net::serve(&listener, settings, 7, 1, &mut Vec::new()));
+
+    let mut stream = TcpStream::connect(address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut reply = || {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        line
+    };
+    writeln!(stream, "HELLO").unwrap();
+    assert!(reply().starts_with("ERROR"));
+
+    let mut stream = TcpStream::connect(address).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut script = |line: &str| {
+        writeln!(stream, "{line}").unwrap();
+        let mut reply = String::new();
+        reader.read_line(&mut reply).unwrap();
+        reply
+    };
+    assert_eq!(script("JOIN carol"), "START 1 10\n");
+    assert_eq!(script("GUESS 11"), "ERROR guess between 1 and 10\n");
+    assert_eq!(script("GUESS 3"), "LESS\n");
+    assert_eq!(script("GUESS 9"), "OUT\n");
+    // Carol was the only player, so the round is over.
+    let mut rest = String::new();
+    reader.read_to_string(&mut rest).unwrap();
+    assert_eq!(rest, "NOBODY 7\n");
+    assert_eq!(server.join().unwrap().unwrap(), None);
+}
+
+#[test]
+fn silent_client_does_not_hold_up_the_lobby() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let settings = Settings::new(1, 10, None).unwrap();
+    let server = thread::spawn(move || {
+        let mut log = Vec::new();
+        let winner = net::serve(&listener, settings, 3, 1, &mut log).unwrap();
+        (winner, String::from_utf8(log).unwrap())
+    });
+
+    // Connects first but never sends JOIN.
+    let started = Instant::now();
+    let silent = TcpStream::connect(&address).unwrap();
+    let dave = client(address, "dave", "3\n");
+    let (winner, log) = server.join().unwrap();
+    assert_eq!(winner.as_deref(), Some("dave"));
+    assert!(log.starts_with("No JOIN from"), "{log}");
+    assert!(started.elapsed() >= net::JOIN_TIMEOUT);
+
+    let mut reply = String::new();
+    BufReader::new(silent).read_line(&mut reply).unwrap();
+    assert!(reply.starts_with("ERROR"));
+    assert_eq!(dave.join().unwrap().0.as_deref(), Some("dave"));
+}
+
+#[test]
+fn trickling_client_does_not_hold_up_the_lobby() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let settings = Settings::new(1, 10, None).unwrap();
+    let server = thread::spawn(move || {
+        let mut log = Vec::new();
+        let winner = net::serve(&listener, settings, 3, 1, &mut log).unwrap();
+        (winner, String::from_utf8(log).unwrap())
+    });
+
+    // Sends a byte every so often, but never the end of the JOIN line.
+    let started = Instant::now();
+    let mut trickle = TcpStream::connect(&address).unwrap();
+    let trickler = thread::spawn(move || {
+        for _ in 0..40 {
+            if trickle.write_all(b"J").is_err() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(250));
+        }
+    });
+    let erin = client(address, "erin", "3\n");
+    let (winner, log) = server.join().unwrap();
+    assert_eq!(winner.as_deref(), Some("erin"));
+    assert!(log.starts_with("No JOIN from"), "{log}");
+    assert!(started.elapsed() < net::JOIN_TIMEOUT * 2);
+    assert_eq!(erin.join().unwrap().0.as_deref(), Some("erin"));
+    trickler.join().unwrap();
+}
@@ -0,0 +1,110 @@
+// Benchmarks the search index on a synthetic catalog of 100k books.
+//
+// Run with `cargo bench --bench search`.
+
+#[allow(dead_code)]
+#[path = "../src/search.rs"]
+mod search;
+
+use search::SearchIndex;
+use std::time::{Duration, Instant};
+
+const CATALOG_SIZE: u32 = 100_000;
+const QUERY_ROUNDS: u32 = 1_000;
+
+const WORDS: [&str; 32] = [
+    "shadow", "river", "empire", "garden", "silent", "winter", "crown", "ocean", "stone",
+    "letters", "midnight", "glass", "forest", "machine", "harbor", "summer", "secret", "city",
+    "dragon", "library", "storm", "island", "mirror", "dream", "fire", "journey", "north", "echo",
+    "orchard", "paper", "valley", "star",
+];
+const SURNAMES: [&str; 8] = [
+    "Austen", "Herbert", "Le Guin", "Tolkien", "Morrison", "Achebe", "Murakami", "Atwood",
+];
+const PUBLISHERS: [&str; 4] = ["Penguin", "Harper", "Vintage", "Tor"];
+const TAGS: [&str; 6] = [
+    "classic", "fantasy", "mystery", "romance", "space", "history",
+];
+
+// Small linear congruential generator so every run indexes the same catalog.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self, bound: usize) -> usize {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((self.0 >> 33) % bound as u64) as usize
+    }
+}
+
+fn book_fields(rng: &mut Lcg, id: u32) -> (String, String, String, String) {
+    let title = format!(
+        "The {} {} {}",
+        WORDS[rng.next(WORDS.len())],
+        WORDS[rng.next(WORDS.len())],
+        id
+    );
+    let author = format!(
+        "Author{} {}",
+        id % 5_000,
+        SURNAMES[rng.next(SURNAMES.len())]
+    );
+    let publisher = PUBLISHERS[rng.next(PUBLISHERS.len())].to_owned();
+    let tag = TAGS[rng.next(TAGS.len())].to_owned();
+    (title, author, publisher, tag)
+}
+
+fn index_book(index: &mut SearchIndex<u32>, rng: &mut Lcg, id: u32) {
+    let (title, author, publisher, tag) = book_fields(rng, id);
+    index.insert(
+        id,
+        &[
+            (&title, 3.0),
+            (&author, 2.0),
+            (&publisher, 1.0),
+            (&tag, 1.5),
+        ],
+    );
+}
+
+fn report(label: &str, total: Duration, rounds: u32) {
+    println!(
+        "{:<36} {:>10.2?} total {:>10.2?}/op",
+        label,
+        total,
+        total / rounds
+    );
+}
+
+fn main() {
+    let mut rng = Lcg(42);
+    let mut index = SearchIndex::new();
+
+    let start = Instant::now();
+    for id in 1..=CATALOG_SIZE {
+        index_book(&mut index, &mut rng, id);
+    }
+    report("build 100k catalog", start.elapsed(), CATALOG_SIZE);
+
+    for query in ["dragon", "drag", "herbert fantasy", "midnight garden", "s"] {
+        let start = Instant::now();
+        let mut hits = 0;
+        for _ in 0..QUERY_ROUNDS {
+            hits = index.search(query, 20).len();
+        }
+        report(
+            &format!("search {:?} ({} hits)", query, hits),
+            start.elapsed(),
+            QUERY_ROUNDS,
+        );
+    }
+
+    let start = Instant::now();
+    for id in 1..=QUERY_ROUNDS {
+        index.remove(&id);
+        index_book(&mut index, &mut rng, id);
+    }
+    report("remove + re-insert", start.elapsed(), QUERY_ROUNDS);
+}
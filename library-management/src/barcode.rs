@@ -0,0 +1,199 @@
+use qrcode::{Color, QrCode};
+
+const BOOK_PREFIX: char = 'B';
+const READER_PREFIX: char = 'R';
+
+// Size of one QR module and the blank border around each code, in SVG units.
+const MODULE_SIZE: usize = 4;
+const QUIET_ZONE: usize = 4;
+const CAPTION_HEIGHT: usize = 24;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Scan {
+    Book(u32),
+    Reader(u32),
+}
+
+#[derive(Debug)]
+pub struct Label {
+    pub code: String,
+    pub caption: String,
+}
+
+impl Label {
+    pub fn new(code: String, caption: String) -> Self {
+        Self { code, caption }
+    }
+}
+
+// Luhn check digit over the numeric part, so a misread digit is rejected
+// instead of borrowing the wrong book.
+fn check_digit(digits: &str) -> u32 {
+    let sum: u32 = digits
+        .chars()
+        .rev()
+        .filter_map(|c| c.to_digit(10))
+        .enumerate()
+        .map(|(idx, digit)| {
+            if idx % 2 == 0 {
+                let doubled = digit * 2;
+                if doubled > 9 {
+                    doubled - 9
+                } else {
+                    doubled
+                }
+            } else {
+                digit
+            }
+        })
+        .sum();
+    (10 - sum % 10) % 10
+}
+
+// Ids are padded to eight digits; larger ids just make the code longer.
+const MIN_DIGITS: usize = 8;
+const MAX_DIGITS: usize = 10;
+
+fn encode(prefix: char, id: u32) -> String {
+    let digits = format!("{:0width$}", id, width = MIN_DIGITS);
+    format!("{}{}{}", prefix, digits, check_digit(&digits))
+}
+
+pub fn book_code(id: u32) -> String {
+    encode(BOOK_PREFIX, id)
+}
+
+pub fn reader_code(id: u32) -> String {
+    encode(READER_PREFIX, id)
+}
+
+pub fn parse(code: &str) -> Result<Scan, String> {
+    let code = code.trim().to_uppercase();
+    let mut chars = code.chars();
+    let prefix = chars.next().ok_or("Empty barcode")?;
+    let rest = chars.as_str();
+    if !(MIN_DIGITS + 1..=MAX_DIGITS + 1).contains(&rest.len())
+        || !rest.chars().all(|c| c.is_ascii_digit())
+    {
+        return Err(format!("Malformed barcode: {code}"));
+    }
+    let (digits, check) = rest.split_at(rest.len() - 1);
+    if check_digit(digits).to_string() != check {
+        return Err(format!("Check digit mismatch in barcode: {code}"));
+    }
+    let id = digits.parse::<u32>().map_err(|err| err.to_string())?;
+    match prefix {
+        BOOK_PREFIX => Ok(Scan::Book(id)),
+        READER_PREFIX => Ok(Scan::Reader(id)),
+        _ => Err(format!("Unknown barcode prefix: {prefix}")),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Draws one QR code with its caption underneath, with the top left corner at
+// (x, y). Returns the SVG elements and the size of the label.
+fn label_elements(label: &Label, x: usize, y: usize) -> Result<(String, usize, usize), String> {
+    let qr = QrCode::new(label.code.as_bytes()).map_err(|err| err.to_string())?;
+    let modules = qr.width();
+    let size = (modules + 2 * QUIET_ZONE) * MODULE_SIZE;
+    let mut svg = String::new();
+    for (idx, color) in qr.to_colors().into_iter().enumerate() {
+        if color == Color::Dark {
+            let left = x + (QUIET_ZONE + idx % modules) * MODULE_SIZE;
+            let top = y + (QUIET_ZONE + idx / modules) * MODULE_SIZE;
+            svg.push_str(&format!(
+                r#"<rect x="{left}" y="{top}" width="{MODULE_SIZE}" height="{MODULE_SIZE}"/>"#
+            ));
+        }
+    }
+    svg.push_str(&format!(
+        r#"<text x="{}" y="{}" font-family="monospace" font-size="12" text-anchor="middle">{}</text>"#,
+        x + size / 2,
+        y + size + CAPTION_HEIGHT / 2,
+        escape(format!("{} {}", label.code, label.caption).trim())
+    ));
+    Ok((svg, size, size + CAPTION_HEIGHT))
+}
+
+fn svg_document(width: usize, height: usize, body: &str) -> String {
+    format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}"><rect width="100%" height="100%" fill="white"/><g fill="black">{body}</g></svg>"#
+    )
+}
+
+// Lays the labels out row by row so the sheet can be printed and cut.
+pub fn label_sheet_svg(labels: &[Label], columns: usize) -> Result<String, String> {
+    let columns = columns.max(1);
+    // Codes of different lengths can produce different QR sizes, so the grid
+    // uses the largest label for every cell.
+    let (mut cell_width, mut cell_height) = (0, 0);
+    for label in labels {
+        let (_, width, height) = label_elements(label, 0, 0)?;
+        cell_width = cell_width.max(width);
+        cell_height = cell_height.max(height);
+    }
+    let mut body = String::new();
+    for (idx, label) in labels.iter().enumerate() {
+        let x = (idx % columns) * cell_width;
+        let y = (idx / columns) * cell_height;
+        body.push_str(&label_elements(label, x, y)?.0);
+    }
+    let rows = labels.len().div_ceil(columns);
+    Ok(svg_document(
+        cell_width * columns.min(labels.len().max(1)),
+        cell_height * rows,
+        &body,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn codes_round_trip_through_parse() {
+        assert_eq!(book_code(42), "B000000422");
+        assert_eq!(parse(&book_code(42)), Ok(Scan::Book(42)));
+        assert_eq!(parse(&reader_code(7)), Ok(Scan::Reader(7)));
+        assert_eq!(parse(" b000000422\n"), Ok(Scan::Book(42)));
+    }
+
+    #[test]
+    fn large_ids_round_trip_through_parse() {
+        for id in [99_999_999, 100_000_000, u32::MAX] {
+            assert_eq!(parse(&book_code(id)), Ok(Scan::Book(id)));
+            assert_eq!(parse(&reader_code(id)), Ok(Scan::Reader(id)));
+        }
+        assert_eq!(book_code(u32::MAX).len(), 12);
+        let too_long = format!("B{}", "9".repeat(12));
+        assert!(parse(&too_long).is_err(), "more digits than a u32 has");
+    }
+
+    #[test]
+    fn parse_rejects_misreads() {
+        assert!(parse("").is_err());
+        assert!(parse("B00000042").is_err(), "missing check digit");
+        assert!(parse("B000000432").is_err(), "wrong check digit");
+        assert!(parse("X000000422").is_err(), "unknown prefix");
+    }
+
+    #[test]
+    fn label_sheet_contains_every_caption() {
+        let labels = vec![
+            Label::new(book_code(1), "Dune".to_owned()),
+            Label::new(book_code(2), "Emma & Co".to_owned()),
+            Label::new(reader_code(1), "Constance".to_owned()),
+        ];
+        let sheet = label_sheet_svg(&labels, 2).unwrap();
+        assert!(sheet.starts_with("<svg"));
+        assert!(sheet.contains("B000000018 Dune"));
+        assert!(sheet.contains("Emma &amp; Co"));
+        assert_eq!(sheet.matches("<text").count(), 3);
+    }
+}
@@ -0,0 +1,129 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+use chrono::{DateTime, Local};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LibraryEvent {
+    BookAdded {
+        book_id: u32,
+        name: String,
+    },
+    LoanCreated {
+        reader_id: u32,
+        book_id: u32,
+        copy_id: u32,
+        return_date: DateTime<Local>,
+    },
+    LoanReturned {
+        reader_id: u32,
+        book_id: u32,
+        copy_id: u32,
+    },
+    FineCharged {
+        reader_id: u32,
+        book_id: u32,
+        amount: f32,
+    },
+    ReaderSuspended {
+        reader_id: u32,
+    },
+}
+
+// Fans library events out to every subscriber. Each subscriber gets its own
+// unbounded channel, so emitting never waits on a slow subscriber and every
+// subscriber sees the events in the order they happened.
+#[derive(Debug, Default)]
+pub struct EventBus {
+    subscribers: Vec<Sender<LibraryEvent>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self {
+            subscribers: Vec::new(),
+        }
+    }
+
+    pub fn subscribe(&mut self) -> Receiver<LibraryEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    // Runs the callback on its own thread for every event. The thread exits
+    // once the bus is dropped and all pending events have been handled.
+    pub fn on_event<F>(&mut self, mut callback: F) -> JoinHandle<()>
+    where
+        F: FnMut(LibraryEvent) + Send + 'static,
+    {
+        let receiver = self.subscribe();
+        thread::spawn(move || {
+            for event in receiver {
+                callback(event);
+            }
+        })
+    }
+
+    pub fn emit(&mut self, event: LibraryEvent) {
+        // Subscribers that hung up are dropped instead of failing the emit.
+        self.subscribers
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn returned(book_id: u32) -> LibraryEvent {
+        LibraryEvent::LoanReturned {
+            reader_id: 1,
+            book_id,
+            copy_id: book_id,
+        }
+    }
+
+    #[test]
+    fn subscribers_receive_events_in_order() {
+        let mut bus = EventBus::new();
+        let first = bus.subscribe();
+        let second = bus.subscribe();
+        for book_id in 1..=3 {
+            bus.emit(returned(book_id));
+        }
+        drop(bus);
+        let expected: Vec<LibraryEvent> = (1..=3).map(returned).collect();
+        assert_eq!(first.iter().collect::<Vec<_>>(), expected);
+        assert_eq!(second.iter().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn dropped_subscribers_are_removed() {
+        let mut bus = EventBus::new();
+        let kept = bus.subscribe();
+        drop(bus.subscribe());
+        bus.emit(returned(1));
+        assert_eq!(bus.subscribers.len(), 1);
+        assert_eq!(kept.recv(), Ok(returned(1)));
+    }
+
+    #[test]
+    fn callbacks_run_off_the_emitting_thread() {
+        let mut bus = EventBus::new();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let handle = bus.on_event({
+            let seen = Arc::clone(&seen);
+            move |event| seen.lock().unwrap().push(event)
+        });
+        bus.emit(returned(1));
+        bus.emit(LibraryEvent::ReaderSuspended { reader_id: 1 });
+        drop(bus);
+        handle.join().unwrap();
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![returned(1), LibraryEvent::ReaderSuspended { reader_id: 1 }]
+        );
+    }
+}
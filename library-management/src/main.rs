@@ -1,8 +1,26 @@
+mod barcode;
+mod events;
+mod search;
+
 use std::collections::HashMap;
 
-use chrono::{DateTime, Days, Local};
+use barcode::{Label, Scan};
+use chrono::{DateTime, Days, Duration, Local};
+use events::{EventBus, LibraryEvent};
+use search::SearchIndex;
+
+const TITLE_WEIGHT: f32 = 3.0;
+const AUTHOR_WEIGHT: f32 = 2.0;
+const PUBLISHER_WEIGHT: f32 = 1.0;
+const TAG_WEIGHT: f32 = 1.5;
 
+const FINE_PER_DAY: f32 = 10.0;
+const FINE_LIMIT: f32 = 100.0;
+
+// The records below are only shown through their `Debug` output, which the
+// dead code lint doesn't count as reading them.
 #[derive(Debug, Clone, Copy)]
+#[allow(dead_code)]
 enum Category {
     ScienceFiction,
     Romance,
@@ -19,6 +37,7 @@ struct Publisher {
 }
 
 #[derive(Debug)]
+#[allow(dead_code)]
 struct Book {
     id: u32,
     name: String,
@@ -27,38 +46,53 @@ struct Book {
     category: Category,
     isbn: String,
     publisher: Publisher,
+    tags: Vec<String>,
+    // Ids of the physical copies, each with its own barcode.
+    copies: Vec<u32>,
 }
 
 #[derive(Debug)]
+#[allow(dead_code)]
 struct Reader {
     id: u32,
     name: String,
     email: String,
     phone_number: String,
+    suspended: bool,
 }
 
 #[derive(Debug)]
+#[allow(dead_code)]
 struct Staff {
     id: u32,
     name: String,
 }
 
 #[derive(Debug)]
+#[allow(dead_code)]
 struct Report {
     id: u32,
     reader_id: u32,
     book_id: u32,
+    copy_id: u32,
     issue_date: DateTime<Local>,
     return_date: DateTime<Local>,
 }
 
 #[derive(Debug)]
+#[allow(dead_code)]
 struct Library {
     name: String,
     staff_members: Vec<Staff>,
     members: Vec<Reader>,
     books: HashMap<String, Book>,
+    // Ids are never reused, so a removed book's id can't point at another.
+    next_book_id: u32,
+    next_copy_id: u32,
     reports: HashMap<u32, Vec<Report>>,
+    index: SearchIndex<String>,
+    fines: HashMap<u32, f32>,
+    events: EventBus,
 }
 
 impl Publisher {
@@ -97,8 +131,27 @@ impl Book {
             category,
             isbn,
             publisher,
+            tags: Vec::new(),
+            copies: Vec::new(),
         }
     }
+
+    fn search_fields(&self) -> Vec<(&str, f32)> {
+        let mut fields = vec![
+            (self.name.as_str(), TITLE_WEIGHT),
+            (self.author.as_str(), AUTHOR_WEIGHT),
+            (self.publisher.name.as_str(), PUBLISHER_WEIGHT),
+        ];
+        fields.extend(self.tags.iter().map(|tag| (tag.as_str(), TAG_WEIGHT)));
+        fields
+    }
+
+    fn copy_barcodes(&self) -> Vec<String> {
+        self.copies
+            .iter()
+            .map(|&copy_id| barcode::book_code(copy_id))
+            .collect()
+    }
 }
 
 impl Reader {
@@ -108,8 +161,13 @@ impl Reader {
             name,
             email,
             phone_number,
+            suspended: false,
         }
     }
+
+    fn card_barcode(&self) -> String {
+        barcode::reader_code(self.id)
+    }
 }
 
 impl Staff {
@@ -123,6 +181,7 @@ impl Report {
         id: u32,
         reader_id: u32,
         book_id: u32,
+        copy_id: u32,
         issue_date: DateTime<Local>,
         return_date: DateTime<Local>,
     ) -> Self {
@@ -130,12 +189,15 @@ impl Report {
             id,
             reader_id,
             book_id,
+            copy_id,
             issue_date,
             return_date,
         }
     }
 }
 
+// The `-> ()` and `.clone()` are kept as the library was first written.
+#[allow(clippy::unused_unit, clippy::clone_on_copy)]
 impl Library {
     fn new(name: String) -> Self {
         Self {
@@ -143,7 +205,12 @@ impl Library {
             staff_members: Vec::new(),
             members: Vec::new(),
             books: HashMap::new(),
+            next_book_id: 1,
+            next_copy_id: 1,
             reports: HashMap::new(),
+            index: SearchIndex::new(),
+            fines: HashMap::new(),
+            events: EventBus::new(),
         }
     }
 
@@ -162,9 +229,49 @@ impl Library {
         isbn: String,
         publisher: Publisher,
     ) -> () {
-        let id = u32::try_from(&self.books.len() + 1).expect("error while getting the id");
-        let book = Book::new(id, name.clone(), author, price, category, isbn, publisher);
-        self.books.insert(name, book);
+        let id = self.next_book_id;
+        self.next_book_id += 1;
+        let mut book = Book::new(id, name.clone(), author, price, category, isbn, publisher);
+        book.copies.push(self.next_copy_id);
+        self.next_copy_id += 1;
+        self.index.insert(name.clone(), &book.search_fields());
+        self.books.insert(name.clone(), book);
+        self.events
+            .emit(LibraryEvent::BookAdded { book_id: id, name });
+    }
+
+    // Adds another copy of a book and returns its id.
+    fn add_copy(&mut self, name: &str) -> Option<u32> {
+        let book = self.books.get_mut(name)?;
+        let copy_id = self.next_copy_id;
+        self.next_copy_id += 1;
+        book.copies.push(copy_id);
+        Some(copy_id)
+    }
+
+    fn remove_book(&mut self, name: &str) -> Option<Book> {
+        let book = self.books.remove(name)?;
+        self.index.remove(&book.name);
+        Some(book)
+    }
+
+    fn tag_book(&mut self, name: &str, tags: &[&str]) -> bool {
+        match self.books.get_mut(name) {
+            Some(book) => {
+                book.tags.extend(tags.iter().map(|tag| tag.to_string()));
+                self.index.insert(book.name.clone(), &book.search_fields());
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn search(&self, query: &str, limit: usize) -> Vec<&Book> {
+        self.index
+            .search(query, limit)
+            .into_iter()
+            .filter_map(|hit| self.books.get(&hit.key))
+            .collect()
     }
 
     fn add_reader(&mut self, name: String, email: String, phone_number: String) -> () {
@@ -173,51 +280,221 @@ impl Library {
         self.members.push(reader);
     }
 
-    fn process_entry(&mut self, reader_id: u32, book_id: u32, days_for_return: u64) -> () {
-        if !self.reports.contains_key(&reader_id) {
-            // This will be borrow request
-            let mut user_reports: Vec<Report> = Vec::new();
-            let issue_date = Local::now();
-            let return_date = issue_date
-                .clone()
-                .checked_add_days(Days::new(days_for_return))
-                .unwrap();
-            let report_id =
-                u32::try_from(user_reports.len() + 1).expect("error while getting the id");
-            let report = Report::new(report_id, reader_id, book_id, issue_date, return_date);
-            user_reports.push(report);
-            self.reports.insert(reader_id, user_reports);
+    fn book_labels(&self) -> Vec<Label> {
+        let mut books: Vec<&Book> = self.books.values().collect();
+        books.sort_by_key(|book| book.id);
+        books
+            .into_iter()
+            .flat_map(|book| {
+                book.copy_barcodes()
+                    .into_iter()
+                    .enumerate()
+                    .map(|(idx, code)| Label::new(code, format!("{} #{}", book.name, idx + 1)))
+            })
+            .collect()
+    }
+
+    fn reader_card_labels(&self) -> Vec<Label> {
+        self.members
+            .iter()
+            .map(|reader| Label::new(reader.card_barcode(), reader.name.clone()))
+            .collect()
+    }
+
+    // Borrow or return using the codes scanned from a reader card and a book
+    // copy instead of their ids.
+    fn process_scan(
+        &mut self,
+        reader_code: &str,
+        book_code: &str,
+        days_for_return: u64,
+    ) -> Result<(), String> {
+        let Scan::Reader(reader_id) = barcode::parse(reader_code)? else {
+            return Err(format!("Not a reader card: {reader_code}"));
+        };
+        let Scan::Book(copy_id) = barcode::parse(book_code)? else {
+            return Err(format!("Not a book barcode: {book_code}"));
+        };
+        if !self.members.iter().any(|reader| reader.id == reader_id) {
+            return Err(format!("Unknown reader card: {reader_code}"));
+        }
+        let Some(book_id) = self
+            .books
+            .values()
+            .find(|book| book.copies.contains(&copy_id))
+            .map(|book| book.id)
+        else {
+            return Err(format!("Unknown book barcode: {book_code}"));
+        };
+        let borrowed = self
+            .reports
+            .get(&reader_id)
+            .is_some_and(|reports| reports.iter().any(|report| report.copy_id == copy_id));
+        if borrowed {
+            self.return_copy(reader_id, copy_id);
+        } else if self.on_loan(copy_id) {
+            return Err(format!("Copy {book_code} is on loan to another reader"));
         } else {
-            // Possibility of borrow and return
-            let user_reports: &mut Vec<Report> = self.reports.get_mut(&reader_id).unwrap();
-            for idx in 0..user_reports.len() {
-                if user_reports[idx].book_id == book_id {
-                    // Return request
-                    user_reports.remove(idx);
-                    if user_reports.len() == 0 {
-                        self.reports.remove(&reader_id);
-                        return;
-                    }
-                }
-            }
-            if days_for_return == u64::MAX {
-                return;
-            }
-            let report_id =
-                u32::try_from(user_reports.len() + 1).expect("error while getting the id");
-            let issue_date = Local::now();
-            let return_date = issue_date
-                .clone()
-                .checked_add_days(Days::new(days_for_return))
-                .unwrap();
-            let report = Report::new(report_id, reader_id, book_id, issue_date, return_date);
-            user_reports.push(report);
+            self.lend(reader_id, book_id, copy_id, days_for_return)?;
+        }
+        Ok(())
+    }
+
+    // Returns the reader's copy of the book if they have one, otherwise lends
+    // them the first copy that isn't on loan. Errors if the loan is refused.
+    fn process_entry(
+        &mut self,
+        reader_id: u32,
+        book_id: u32,
+        days_for_return: u64,
+    ) -> Result<(), String> {
+        let borrowed = self.reports.get(&reader_id).and_then(|reports| {
+            reports
+                .iter()
+                .find(|report| report.book_id == book_id)
+                .map(|report| report.copy_id)
+        });
+        if let Some(copy_id) = borrowed {
+            // Return request
+            self.return_copy(reader_id, copy_id);
+            return Ok(());
+        }
+        if days_for_return == u64::MAX {
+            return Ok(());
+        }
+        let free_copy = self
+            .books
+            .values()
+            .find(|book| book.id == book_id)
+            .and_then(|book| book.copies.iter().find(|&&copy_id| !self.on_loan(copy_id)))
+            .copied();
+        match free_copy {
+            Some(copy_id) => self.lend(reader_id, book_id, copy_id, days_for_return),
+            None => Err(format!("No copy of book {book_id} is free")),
+        }
+    }
+
+    fn on_loan(&self, copy_id: u32) -> bool {
+        self.reports
+            .values()
+            .flatten()
+            .any(|report| report.copy_id == copy_id)
+    }
+
+    fn lend(
+        &mut self,
+        reader_id: u32,
+        book_id: u32,
+        copy_id: u32,
+        days_for_return: u64,
+    ) -> Result<(), String> {
+        if self.is_suspended(reader_id) {
+            return Err(format!("Reader {reader_id} is suspended"));
         }
+        let user_reports = self.reports.entry(reader_id).or_default();
+        let report_id = u32::try_from(user_reports.len() + 1).expect("error while getting the id");
+        let issue_date = Local::now();
+        let return_date = issue_date
+            .clone()
+            .checked_add_days(Days::new(days_for_return))
+            .unwrap();
+        let report = Report::new(
+            report_id,
+            reader_id,
+            book_id,
+            copy_id,
+            issue_date,
+            return_date,
+        );
+        user_reports.push(report);
+        self.events.emit(LibraryEvent::LoanCreated {
+            reader_id,
+            book_id,
+            copy_id,
+            return_date,
+        });
+        Ok(())
+    }
+
+    fn return_copy(&mut self, reader_id: u32, copy_id: u32) {
+        let Some(user_reports) = self.reports.get_mut(&reader_id) else {
+            return;
+        };
+        let Some(idx) = user_reports
+            .iter()
+            .position(|report| report.copy_id == copy_id)
+        else {
+            return;
+        };
+        let report = user_reports.remove(idx);
+        if user_reports.is_empty() {
+            self.reports.remove(&reader_id);
+        }
+        self.complete_return(report);
+    }
+
+    fn complete_return(&mut self, report: Report) {
+        self.events.emit(LibraryEvent::LoanReturned {
+            reader_id: report.reader_id,
+            book_id: report.book_id,
+            copy_id: report.copy_id,
+        });
+        let overdue = Local::now() - report.return_date;
+        if overdue > Duration::zero() {
+            // Every started day past the return date is charged.
+            let days_late = overdue.num_days() + 1;
+            self.charge_fine(
+                report.reader_id,
+                report.book_id,
+                days_late as f32 * FINE_PER_DAY,
+            );
+        }
+    }
+
+    fn charge_fine(&mut self, reader_id: u32, book_id: u32, amount: f32) {
+        let outstanding = self.fines.entry(reader_id).or_insert(0.0);
+        *outstanding += amount;
+        let outstanding = *outstanding;
+        self.events.emit(LibraryEvent::FineCharged {
+            reader_id,
+            book_id,
+            amount,
+        });
+        if outstanding > FINE_LIMIT {
+            self.suspend_reader(reader_id);
+        }
+    }
+
+    fn suspend_reader(&mut self, reader_id: u32) {
+        let Some(reader) = self
+            .members
+            .iter_mut()
+            .find(|reader| reader.id == reader_id)
+        else {
+            return;
+        };
+        if !reader.suspended {
+            reader.suspended = true;
+            self.events
+                .emit(LibraryEvent::ReaderSuspended { reader_id });
+        }
+    }
+
+    fn is_suspended(&self, reader_id: u32) -> bool {
+        self.members
+            .iter()
+            .any(|reader| reader.id == reader_id && reader.suspended)
     }
 }
 
 fn main() {
     let mut library = Library::new("Library".to_owned());
+    let notifier = library.events.on_event(|event| match event {
+        LibraryEvent::LoanCreated { book_id: 2, .. } => {
+            println!("[chat] Rare book BookTwo has been borrowed")
+        }
+        event => println!("[event] {:?}", event),
+    });
 
     println!("{:?}", library);
 
@@ -265,9 +542,61 @@ fn main() {
 
     println!("{:?}", library);
 
-    library.process_entry(1, 1, 3);
+    library.add_book(
+        "BookWithdrawn".to_owned(),
+        "AuthorTwo".to_owned(),
+        900.0,
+        Category::Romance,
+        "1111111111".to_owned(),
+        Publisher::new(2, "PublisherTwo".to_owned(), 2019),
+    );
+    if let Some(book) = library.remove_book("BookWithdrawn") {
+        println!("Removed {} (id {})", book.name, book.id);
+    }
+
+    library.tag_book("BookTwo", &["space", "classic"]);
+    for book in library.search("auth", 10) {
+        println!("Found: {} by {}", book.name, book.author);
+    }
+    println!("{:?}", library.search("classic space", 10));
+
+    if let Err(err) = library.process_entry(1, 1, 3) {
+        println!("Loan refused: {err}");
+    }
 
     println!("{:?}", library);
+
+    let reader_card = library.members[1].card_barcode();
+    library.add_copy("BookTwo");
+    let book_copy = library.books["BookTwo"].copy_barcodes()[1].clone();
+    match library.process_scan(&reader_card, &book_copy, 7) {
+        Ok(()) => println!("Scanned {book_copy} for {reader_card}"),
+        Err(err) => println!("Scan failed: {err}"),
+    }
+    if let Err(err) = library.process_scan(&reader_card, "B000000001", 7) {
+        println!("Scan failed: {err}");
+    }
+
+    let mut labels = library.book_labels();
+    labels.extend(library.reader_card_labels());
+    match barcode::label_sheet_svg(&labels, 3) {
+        Ok(sheet) => match std::fs::write("labels.svg", sheet) {
+            Ok(()) => println!("Wrote {} labels to labels.svg", labels.len()),
+            Err(err) => println!("Could not write labels.svg: {err}"),
+        },
+        Err(err) => println!("Could not render labels: {err}"),
+    }
+
+    // Late return: the loan was due today, so one day of fine is charged.
+    for days_for_return in [0, u64::MAX] {
+        if let Err(err) = library.process_entry(3, 2, days_for_return) {
+            println!("Loan refused: {err}");
+        }
+    }
+    println!("Outstanding fines: {:?}", library.fines);
+
+    drop(library);
+    notifier.join().expect("event notifier panicked");
     // println!("{:?}", publisherOne);
     // let bookOne = Book::new(
     //     1,
@@ -280,3 +609,138 @@ fn main() {
     // );
     // println!("{:?}", bookOne);
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn add_book(library: &mut Library, name: &str) {
+        library.add_book(
+            name.to_owned(),
+            "Author".to_owned(),
+            10.0,
+            Category::Thriller,
+            "1234567890".to_owned(),
+            Publisher::new(1, "Publisher".to_owned(), 2024),
+        );
+    }
+
+    #[test]
+    fn removed_book_ids_are_not_reused() {
+        let mut library = Library::new("Test".to_owned());
+        add_book(&mut library, "One");
+        add_book(&mut library, "Two");
+        assert_eq!(library.remove_book("One").map(|book| book.id), Some(1));
+        add_book(&mut library, "Three");
+        assert_eq!(library.books["Two"].id, 2);
+        assert_eq!(library.books["Three"].id, 3);
+        assert_eq!(library.search("three", 10).len(), 1);
+        assert!(library.search("one", 10).is_empty());
+    }
+
+    #[test]
+    fn copies_have_their_own_barcodes_and_loans() {
+        let mut library = Library::new("Test".to_owned());
+        add_book(&mut library, "One");
+        assert_eq!(library.add_copy("One"), Some(2));
+        assert_eq!(library.add_copy("Missing"), None);
+        for name in ["Ann", "Bob"] {
+            library.add_reader(name.to_owned(), String::new(), String::new());
+        }
+        let codes = library.books["One"].copy_barcodes();
+        assert_eq!(codes, vec![barcode::book_code(1), barcode::book_code(2)]);
+        let labels = library.book_labels();
+        assert_eq!(labels[1].caption, "One #2");
+
+        let (ann, bob) = (barcode::reader_code(1), barcode::reader_code(2));
+        assert_eq!(library.process_scan(&ann, &codes[1], 7), Ok(()));
+        assert_eq!(library.reports[&1][0].copy_id, 2);
+        assert!(library.process_scan(&bob, &codes[1], 7).is_err());
+        assert_eq!(library.process_scan(&bob, &codes[0], 7), Ok(()));
+        assert_eq!(library.reports[&2][0].copy_id, 1);
+
+        // Returning by scan gives back exactly the scanned copy.
+        assert_eq!(library.process_scan(&ann, &codes[1], 7), Ok(()));
+        assert!(!library.reports.contains_key(&1));
+        assert!(library.on_loan(1));
+        assert!(!library.on_loan(2));
+    }
+
+    fn library_with_reader() -> Library {
+        let mut library = Library::new("Test".to_owned());
+        add_book(&mut library, "One");
+        library.add_reader("Ann".to_owned(), String::new(), String::new());
+        library
+    }
+
+    #[test]
+    fn late_return_emits_loan_return_and_fine_in_order() {
+        let mut library = library_with_reader();
+        let events = library.events.subscribe();
+        assert_eq!(library.process_entry(1, 1, 0), Ok(()));
+        assert_eq!(library.process_entry(1, 1, u64::MAX), Ok(()));
+        let events: Vec<LibraryEvent> = events.try_iter().collect();
+        assert_eq!(events.len(), 3, "{events:?}");
+        assert!(matches!(
+            events[0],
+            LibraryEvent::LoanCreated {
+                reader_id: 1,
+                book_id: 1,
+                copy_id: 1,
+                ..
+            }
+        ));
+        assert_eq!(
+            events[1..],
+            [
+                LibraryEvent::LoanReturned {
+                    reader_id: 1,
+                    book_id: 1,
+                    copy_id: 1
+                },
+                LibraryEvent::FineCharged {
+                    reader_id: 1,
+                    book_id: 1,
+                    amount: FINE_PER_DAY
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn suspended_reader_is_refused_a_loan() {
+        let mut library = library_with_reader();
+        assert_eq!(library.process_entry(1, 1, 7), Ok(()));
+        // Twenty days late is more than the fine limit.
+        library.reports.get_mut(&1).unwrap()[0].return_date = Local::now() - Duration::days(20);
+        let events = library.events.subscribe();
+        assert_eq!(library.process_entry(1, 1, u64::MAX), Ok(()));
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            vec![
+                LibraryEvent::LoanReturned {
+                    reader_id: 1,
+                    book_id: 1,
+                    copy_id: 1
+                },
+                LibraryEvent::FineCharged {
+                    reader_id: 1,
+                    book_id: 1,
+                    amount: 21.0 * FINE_PER_DAY
+                },
+                LibraryEvent::ReaderSuspended { reader_id: 1 }
+            ]
+        );
+
+        assert_eq!(
+            library.process_entry(1, 1, 7),
+            Err("Reader 1 is suspended".to_owned())
+        );
+        assert_eq!(
+            library.process_scan(&barcode::reader_code(1), &barcode::book_code(1), 7),
+            Err("Reader 1 is suspended".to_owned())
+        );
+        assert!(events.try_iter().next().is_none());
+        assert!(!library.on_loan(1));
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::ops::Bound;
+
+// A query token that only matches the start of an indexed token scores less
+// than an exact match, so "dune" ranks "Dune" above "Dunes of Arrakis".
+const PREFIX_PENALTY: f32 = 0.5;
+
+#[derive(Debug, PartialEq)]
+pub struct SearchHit<K> {
+    pub key: K,
+    pub score: f32,
+}
+
+// Inverted index from lowercase tokens to the documents containing them.
+// Tokens are kept in a BTreeMap so prefix matches are a single range scan.
+#[derive(Debug)]
+pub struct SearchIndex<K> {
+    postings: BTreeMap<String, HashMap<K, f32>>,
+    documents: HashMap<K, Vec<String>>,
+}
+
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(|token| token.to_lowercase())
+        .collect()
+}
+
+impl<K: Clone + Eq + Hash + Ord> SearchIndex<K> {
+    pub fn new() -> Self {
+        Self {
+            postings: BTreeMap::new(),
+            documents: HashMap::new(),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    // Indexes the weighted text fields of a document, replacing whatever was
+    // indexed for the same key before.
+    pub fn insert(&mut self, key: K, fields: &[(&str, f32)]) {
+        self.remove(&key);
+        let mut weights: HashMap<String, f32> = HashMap::new();
+        for (text, weight) in fields {
+            for token in tokenize(text) {
+                *weights.entry(token).or_insert(0.0) += weight;
+            }
+        }
+        let tokens = weights.keys().cloned().collect();
+        for (token, weight) in weights {
+            self.postings
+                .entry(token)
+                .or_default()
+                .insert(key.clone(), weight);
+        }
+        self.documents.insert(key, tokens);
+    }
+
+    pub fn remove(&mut self, key: &K) -> bool {
+        let Some(tokens) = self.documents.remove(key) else {
+            return false;
+        };
+        for token in tokens {
+            if let Some(docs) = self.postings.get_mut(&token) {
+                docs.remove(key);
+                if docs.is_empty() {
+                    self.postings.remove(&token);
+                }
+            }
+        }
+        true
+    }
+
+    // Returns the documents matching every query token, best match first.
+    // Equal scores are ordered by key, so the same query always returns the
+    // same hits.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchHit<K>> {
+        let mut terms = tokenize(query);
+        terms.sort();
+        terms.dedup();
+
+        let mut scores: Option<HashMap<K, f32>> = None;
+        for term in &terms {
+            let term_scores = self.term_scores(term);
+            scores = Some(match scores {
+                None => term_scores,
+                Some(acc) => acc
+                    .into_iter()
+                    .filter_map(|(key, score)| term_scores.get(&key).map(|s| (key, score + s)))
+                    .collect(),
+            });
+        }
+
+        let mut hits: Vec<SearchHit<K>> = scores
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(key, score)| SearchHit { key, score })
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.key.cmp(&b.key)));
+        hits.truncate(limit);
+        hits
+    }
+
+    fn term_scores(&self, term: &str) -> HashMap<K, f32> {
+        let total = self.documents.len() as f32;
+        let mut scores: HashMap<K, f32> = HashMap::new();
+        let matches = self
+            .postings
+            .range::<str, _>((Bound::Included(term), Bound::Unbounded))
+            .take_while(|(token, _)| token.starts_with(term));
+        for (token, docs) in matches {
+            let idf = (1.0 + total / docs.len() as f32).ln();
+            let factor = if token == term { 1.0 } else { PREFIX_PENALTY };
+            for (key, weight) in docs {
+                let score = weight * idf * factor;
+                let best = scores.entry(key.clone()).or_insert(0.0);
+                if score > *best {
+                    *best = score;
+                }
+            }
+        }
+        scores
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn keys(hits: Vec<SearchHit<u32>>) -> Vec<u32> {
+        hits.into_iter().map(|hit| hit.key).collect()
+    }
+
+    fn sample_index() -> SearchIndex<u32> {
+        let mut index = SearchIndex::new();
+        index.insert(
+            1,
+            &[("Dune", 3.0), ("Frank Herbert", 2.0), ("Chilton", 1.0)],
+        );
+        index.insert(2, &[("Dune Messiah", 3.0), ("Frank Herbert", 2.0)]);
+        index.insert(3, &[("The Dunwich Horror", 3.0), ("H. P. Lovecraft", 2.0)]);
+        index.insert(
+            4,
+            &[("Emma", 3.0), ("Jane Austen", 2.0), ("Dune Press", 1.0)],
+        );
+        index
+    }
+
+    #[test]
+    fn tokenize_folds_case_and_splits_punctuation() {
+        assert_eq!(
+            tokenize("H.P. Lovecraft's DUNWICH"),
+            vec!["h", "p", "lovecraft", "s", "dunwich"]
+        );
+    }
+
+    #[test]
+    fn search_ranks_title_matches_above_publisher_matches() {
+        let index = sample_index();
+        let hits = keys(index.search("dune", 10));
+        assert_eq!(hits.len(), 3, "prefix 'dune' should not match 'dunwich'");
+        assert_eq!(*hits.last().unwrap(), 4, "publisher match should rank last");
+    }
+
+    #[test]
+    fn search_matches_prefixes_and_requires_every_term() {
+        let index = sample_index();
+        assert_eq!(keys(index.search("dunw", 10)), vec![3]);
+        assert_eq!(keys(index.search("herb mess", 10)), vec![2]);
+        assert!(index.search("herbert austen", 10).is_empty());
+    }
+
+    #[test]
+    fn exact_match_beats_prefix_match() {
+        let mut index = SearchIndex::new();
+        index.insert(1, &[("Dunes", 3.0)]);
+        index.insert(2, &[("Dune", 3.0)]);
+        assert_eq!(keys(index.search("dune", 10)), vec![2, 1]);
+    }
+
+    #[test]
+    fn equal_scores_come_back_in_key_order() {
+        let mut index = SearchIndex::new();
+        for key in [5, 3, 9, 1, 7, 2, 8] {
+            index.insert(key, &[("Collected Poems", 3.0)]);
+        }
+        for _ in 0..10 {
+            assert_eq!(keys(index.search("poems", 3)), vec![1, 2, 3]);
+        }
+        assert_eq!(keys(index.search("poems", 10)), vec![1, 2, 3, 5, 7, 8, 9]);
+    }
+
+    #[test]
+    fn insert_and_remove_update_the_index_incrementally() {
+        let mut index = sample_index();
+        assert!(index.remove(&1));
+        assert!(!index.remove(&1));
+        assert_eq!(index.len(), 3);
+        assert!(index.search("chilton", 10).is_empty());
+
+        index.insert(2, &[("Children of Dune", 3.0)]);
+        assert_eq!(keys(index.search("messiah", 10)), Vec::<u32>::new());
+        assert_eq!(keys(index.search("children", 10)), vec![2]);
+    }
+}